@@ -0,0 +1,148 @@
+use std::env;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use teloxide::dispatching::UpdateHandler;
+use teloxide::prelude::*;
+use teloxide::types::UpdateKind;
+
+use crate::admin::Admins;
+use crate::i18n::{Key, Lang};
+use crate::store::TaskStore;
+use crate::HandlerResult;
+
+/// Who may use the bot, configured with `ACCESS_MODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    /// Everyone except banned users.
+    Open,
+    /// Only users approved by an admin.
+    Allowlist,
+    /// Approved users and users who redeemed an invite code with `/start <code>`.
+    Invite,
+}
+
+impl AccessMode {
+    pub fn from_env() -> Self {
+        match env::var("ACCESS_MODE")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "" | "open" => AccessMode::Open,
+            "allowlist" => AccessMode::Allowlist,
+            "invite" => AccessMode::Invite,
+            mode => panic!("invalid ACCESS_MODE {mode}, expected open, allowlist or invite"),
+        }
+    }
+}
+
+/// Entry of the user registry kept in the `TaskStore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccessStatus {
+    Allowed,
+    Banned,
+}
+
+/// Why an update stops before the handlers.
+#[derive(Debug, Clone, Copy)]
+pub enum Gate {
+    Banned,
+    NotAllowed(UserId),
+    InviteRequired(UserId),
+    InviteAccepted,
+}
+
+/// Answers unauthorised users, updates of everyone else go further to the handlers.
+pub fn access_handler() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    dptree::filter_map_async(check_access).endpoint(answer_gate)
+}
+
+async fn check_access(
+    update: Update,
+    rzd_db: Arc<dyn TaskStore>,
+    mode: AccessMode,
+    admins: Arc<Admins>,
+) -> Option<Gate> {
+    let user = update.user()?;
+    if admins.contains(user.id) {
+        return None;
+    }
+    let status = match rzd_db.get_access(user.id.0).await {
+        Ok(status) => status,
+        Err(err) => {
+            tracing::warn!(user_id = user.id.0, error = %err, "cant get access status");
+            None
+        }
+    };
+    match (status, mode) {
+        (Some(AccessStatus::Banned), _) => Some(Gate::Banned),
+        (Some(AccessStatus::Allowed), _) | (None, AccessMode::Open) => None,
+        (None, AccessMode::Allowlist) => Some(Gate::NotAllowed(user.id)),
+        (None, AccessMode::Invite) => {
+            let code = match &update.kind {
+                UpdateKind::Message(msg) => msg
+                    .text()
+                    .and_then(|text| text.strip_prefix("/start "))
+                    .map(str::trim),
+                _ => None,
+            };
+            let redeemed = match code {
+                Some(code) => redeem_invite(&*rzd_db, user.id, code).await,
+                None => false,
+            };
+            if redeemed {
+                Some(Gate::InviteAccepted)
+            } else {
+                Some(Gate::InviteRequired(user.id))
+            }
+        }
+    }
+}
+
+async fn redeem_invite(rzd_db: &dyn TaskStore, user_id: UserId, code: &str) -> bool {
+    match rzd_db.redeem_invite(code).await {
+        Ok(true) => match rzd_db.set_access(user_id.0, AccessStatus::Allowed).await {
+            Ok(()) => {
+                tracing::info!(user_id = user_id.0, "user joined with an invite code");
+                true
+            }
+            Err(err) => {
+                tracing::error!(user_id = user_id.0, error = %err, "cant allow user");
+                false
+            }
+        },
+        Ok(false) => false,
+        Err(err) => {
+            tracing::warn!(user_id = user_id.0, error = %err, "cant redeem invite code");
+            false
+        }
+    }
+}
+
+async fn answer_gate(bot: Bot, lang: Lang, gate: Gate, update: Update) -> HandlerResult {
+    let text = match gate {
+        Gate::Banned => lang.t(Key::AccessBanned).to_string(),
+        Gate::NotAllowed(user_id) => lang.tf(Key::AccessNotAllowed, &[("user_id", &user_id)]),
+        Gate::InviteRequired(user_id) => {
+            lang.tf(Key::AccessInviteRequired, &[("user_id", &user_id)])
+        }
+        Gate::InviteAccepted => lang.t(Key::InviteAccepted).to_string(),
+    };
+    match update.kind {
+        UpdateKind::Message(msg) => {
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        UpdateKind::CallbackQuery(q) => {
+            bot.answer_callback_query(q.id)
+                .text(text)
+                .show_alert(true)
+                .await?;
+        }
+        UpdateKind::InlineQuery(q) => {
+            bot.answer_inline_query(q.id, vec![]).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,668 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use teloxide::dispatching::UpdateHandler;
+use teloxide::prelude::*;
+use teloxide::types::{InputFile, Me};
+use teloxide::utils::command::BotCommands;
+
+use crate::access::AccessStatus;
+use crate::backup::Backups;
+use crate::i18n::{Key, Lang};
+use crate::limits::LimitOverrides;
+use crate::poller::PollerControl;
+use crate::rzd::RZDApi;
+use crate::store::TaskStore;
+use crate::task_edit::is_paused;
+use crate::utils::{format_task, truncate_message};
+use crate::HandlerResult;
+
+const TASKS_PER_MESSAGE: usize = 20;
+const DEFAULT_ERRORS_COUNT: usize = 10;
+/// Keeps broadcasts well below telegram's limit of 30 messages per second.
+const BROADCAST_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum AdminCommand {
+    Stats,
+    AllTasks(String),
+    DelTask(String),
+    Pause,
+    Resume,
+    Broadcast(String),
+    Errors(String),
+    Approve(String),
+    Ban(String),
+    Unban(String),
+    Invite,
+    Limits(String),
+    Backup(String),
+}
+
+/// Telegram user ids allowed to run the admin commands, taken from comma separated `ADMIN_IDS`.
+pub struct Admins {
+    ids: HashSet<u64>,
+}
+
+impl Admins {
+    pub fn from_env() -> Arc<Self> {
+        let mut ids = HashSet::new();
+        for id in env::var("ADMIN_IDS").unwrap_or_default().split(',') {
+            let id = id.trim();
+            if id.is_empty() {
+                continue;
+            }
+            match id.parse() {
+                Ok(id) => {
+                    ids.insert(id);
+                }
+                Err(err) => tracing::warn!(id, error = %err, "invalid admin id"),
+            }
+        }
+        if ids.is_empty() {
+            tracing::warn!("ADMIN_IDS is empty, admin commands are disabled");
+        }
+        Arc::new(Self { ids })
+    }
+
+    pub fn contains(&self, user_id: UserId) -> bool {
+        self.ids.contains(&user_id.0)
+    }
+
+    pub fn is_admin(&self, msg: &Message) -> bool {
+        msg.from().is_some_and(|user| self.contains(user.id))
+    }
+}
+
+/// Admin commands are parsed only for admins, for everyone else they are plain text.
+pub fn admin_handler() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    use dptree::case;
+
+    dptree::filter(|msg: Message, admins: Arc<Admins>| admins.is_admin(&msg))
+        .chain(teloxide::filter_command::<AdminCommand, _>())
+        .branch(case![AdminCommand::Stats].endpoint(stats))
+        .branch(case![AdminCommand::AllTasks(text)].endpoint(all_tasks))
+        .branch(case![AdminCommand::DelTask(text)].endpoint(delete_any_task))
+        .branch(case![AdminCommand::Pause].endpoint(pause))
+        .branch(case![AdminCommand::Resume].endpoint(resume))
+        .branch(case![AdminCommand::Broadcast(text)].endpoint(broadcast))
+        .branch(case![AdminCommand::Errors(text)].endpoint(rzd_errors))
+        .branch(case![AdminCommand::Approve(text)].endpoint(approve))
+        .branch(case![AdminCommand::Ban(text)].endpoint(ban))
+        .branch(case![AdminCommand::Unban(text)].endpoint(unban))
+        .branch(case![AdminCommand::Invite].endpoint(invite))
+        .branch(case![AdminCommand::Limits(text)].endpoint(user_limits))
+        .branch(case![AdminCommand::Backup(text)].endpoint(backup))
+}
+
+async fn stats(
+    bot: Bot,
+    rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    control: Arc<PollerControl>,
+    lang: Lang,
+    msg: Message,
+) -> HandlerResult {
+    let text = match format_stats(&rzd_api, &*rzd_db, &control, lang).await {
+        Ok(text) => text,
+        Err(err) => format!("{}: {err}", lang.t(Key::Error)),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn format_stats(
+    rzd_api: &RZDApi,
+    rzd_db: &dyn TaskStore,
+    control: &PollerControl,
+    lang: Lang,
+) -> Result<String, String> {
+    let mut users = rzd_db.list_user_ids().await?;
+    let mut task_count = 0;
+    let mut pages = rzd_db.task_pages();
+    while let Some(tasks) = pages.next_page().await? {
+        task_count += tasks.len();
+        users.extend(
+            tasks
+                .iter()
+                .filter_map(|(_, task)| task.get("user_id"))
+                .filter_map(|id| id.parse::<u64>().ok()),
+        );
+    }
+    let (polls, poll_errors) = control.polls_last_hour();
+    let (rzd_calls, rzd_errors) = rzd_api.call_stats();
+    let rzd_error_rate = if rzd_calls == 0 {
+        0.0
+    } else {
+        rzd_errors as f64 * 100.0 / rzd_calls as f64
+    };
+    let poller = if control.is_paused() {
+        lang.t(Key::PollerPausedState)
+    } else {
+        lang.t(Key::PollerRunning)
+    };
+    Ok(lang.tf(
+        Key::AdminStats,
+        &[
+            ("users", &users.len()),
+            ("tasks", &task_count),
+            ("polls", &polls),
+            ("poll_errors", &poll_errors),
+            ("rzd_calls", &rzd_calls),
+            ("rzd_errors", &rzd_errors),
+            ("rzd_error_rate", &format!("{rzd_error_rate:.1}")),
+            ("poller", &poller),
+        ],
+    ))
+}
+
+/// Tasks of every user, or only of the user whose id is given. Sent a page at a time, in id order.
+async fn all_tasks(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let user_id = text.trim();
+    if !user_id.is_empty() {
+        return user_tasks(&bot, &*rzd_db, lang, user_id, &msg).await;
+    }
+    let mut sent = 0;
+    let mut pages = rzd_db.task_pages();
+    loop {
+        let tasks = match pages.next_page().await {
+            Ok(Some(tasks)) => tasks,
+            Ok(None) => break,
+            Err(err) => {
+                bot.send_message(msg.chat.id, lang.tf(Key::TasksError, &[("err", &err)]))
+                    .await?;
+                return Ok(());
+            }
+        };
+        sent += tasks.len();
+        send_tasks(&bot, msg.chat.id, &tasks, lang).await?;
+    }
+    if sent == 0 {
+        bot.send_message(msg.chat.id, lang.t(Key::NoTasks)).await?;
+    }
+    Ok(())
+}
+
+/// Tasks of one user, looked up in the owner index.
+async fn user_tasks(
+    bot: &Bot,
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    user_id: &str,
+    msg: &Message,
+) -> HandlerResult {
+    let user_id = match user_id.parse::<u64>() {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            bot.send_message(msg.chat.id, lang.t(Key::UserIdUsage))
+                .await?;
+            return Ok(());
+        }
+    };
+    match rzd_db.list_user_tasks(user_id).await {
+        Ok(tasks) if tasks.is_empty() => {
+            bot.send_message(msg.chat.id, lang.t(Key::NoTasks)).await?;
+        }
+        Ok(tasks) => send_tasks(bot, msg.chat.id, &tasks, lang).await?,
+        Err(err) => {
+            bot.send_message(msg.chat.id, lang.tf(Key::TasksError, &[("err", &err)]))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn send_tasks(
+    bot: &Bot,
+    chat_id: ChatId,
+    tasks: &[(String, HashMap<String, String>)],
+    lang: Lang,
+) -> HandlerResult {
+    for chunk in tasks.chunks(TASKS_PER_MESSAGE) {
+        let text = chunk
+            .iter()
+            .map(|(task_id, task)| {
+                let owner = match task.get("user_id") {
+                    Some(user_id) => user_id.clone(),
+                    None => lang.t(Key::UnknownOwner).to_string(),
+                };
+                format!(
+                    "{}\n{}",
+                    format_task(task_id, task, lang),
+                    lang.tf(Key::TaskOwner, &[("user_id", &owner)])
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+        bot.send_message(chat_id, truncate_message(&text)).await?;
+    }
+    Ok(())
+}
+
+async fn delete_any_task(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let task_id = text.trim();
+    if task_id.is_empty() {
+        bot.send_message(msg.chat.id, lang.t(Key::DeleteTaskUsage))
+            .await?;
+        return Ok(());
+    }
+    let result = match rzd_db.get_task(task_id).await {
+        Ok(Some(_)) => rzd_db.delete_task_by_id(task_id.to_string()).await,
+        Ok(None) => Err(format!("unknown task {task_id}")),
+        Err(err) => Err(err),
+    };
+    let text = match result {
+        Ok(task_id) => lang.tf(Key::TaskDeleted, &[("task_id", &task_id)]),
+        Err(err) => lang.tf(Key::TaskDeleteError, &[("err", &err)]),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn pause(bot: Bot, control: Arc<PollerControl>, lang: Lang, msg: Message) -> HandlerResult {
+    let key = if control.pause() {
+        tracing::info!(by = ?msg.from().map(|user| user.id.0), "poller is paused");
+        Key::PollerPaused
+    } else {
+        Key::PollerAlreadyPaused
+    };
+    bot.send_message(msg.chat.id, lang.t(key)).await?;
+    Ok(())
+}
+
+async fn resume(bot: Bot, control: Arc<PollerControl>, lang: Lang, msg: Message) -> HandlerResult {
+    let key = if control.resume() {
+        tracing::info!(by = ?msg.from().map(|user| user.id.0), "poller is resumed");
+        Key::PollerResumed
+    } else {
+        Key::PollerNotPaused
+    };
+    bot.send_message(msg.chat.id, lang.t(key)).await?;
+    Ok(())
+}
+
+/// Sends the text as is to every chat that owns an active task.
+async fn broadcast(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let text = text.trim();
+    if text.is_empty() {
+        bot.send_message(msg.chat.id, lang.t(Key::BroadcastUsage))
+            .await?;
+        return Ok(());
+    }
+    let chat_ids = match broadcast_chats(&*rzd_db).await {
+        Ok(chat_ids) => chat_ids,
+        Err(err) => {
+            bot.send_message(msg.chat.id, lang.tf(Key::TasksError, &[("err", &err)]))
+                .await?;
+            return Ok(());
+        }
+    };
+    let (mut sent, mut failed) = (0, 0);
+    for chat_id in chat_ids {
+        match bot.send_message(ChatId(chat_id), text).await {
+            Ok(_) => sent += 1,
+            Err(err) => {
+                tracing::warn!(chat_id, error = %err, "cant broadcast");
+                failed += 1;
+            }
+        }
+        tokio::time::sleep(BROADCAST_DELAY).await;
+    }
+    bot.send_message(
+        msg.chat.id,
+        lang.tf(Key::BroadcastDone, &[("sent", &sent), ("failed", &failed)]),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Chats with a task that is not paused and whose owner is not banned.
+async fn broadcast_chats(rzd_db: &dyn TaskStore) -> Result<BTreeSet<i64>, String> {
+    let mut chat_ids = BTreeSet::new();
+    let mut banned = HashMap::new();
+    let mut pages = rzd_db.task_pages();
+    while let Some(tasks) = pages.next_page().await? {
+        for (_, task) in tasks.iter().filter(|(_, task)| !is_paused(task)) {
+            let chat_id = match task.get("chat_id").and_then(|id| id.parse::<i64>().ok()) {
+                Some(chat_id) => chat_id,
+                None => continue,
+            };
+            if let Some(user_id) = task.get("user_id").and_then(|id| id.parse::<u64>().ok()) {
+                let is_banned = match banned.get(&user_id) {
+                    Some(is_banned) => *is_banned,
+                    None => {
+                        let is_banned =
+                            rzd_db.get_access(user_id).await? == Some(AccessStatus::Banned);
+                        banned.insert(user_id, is_banned);
+                        is_banned
+                    }
+                };
+                if is_banned {
+                    continue;
+                }
+            }
+            chat_ids.insert(chat_id);
+        }
+    }
+    Ok(chat_ids)
+}
+
+async fn rzd_errors(
+    bot: Bot,
+    rzd_api: Arc<RZDApi>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let count = match text.trim() {
+        "" => DEFAULT_ERRORS_COUNT,
+        count => match count.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                bot.send_message(msg.chat.id, lang.t(Key::ErrorsUsage))
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+    let errors = rzd_api.recent_errors(count);
+    if errors.is_empty() {
+        bot.send_message(msg.chat.id, lang.t(Key::NoRZDErrors))
+            .await?;
+        return Ok(());
+    }
+    let text = errors
+        .iter()
+        .map(|err| {
+            format!(
+                "{} {}: {}",
+                err.at.format("%d.%m %H:%M:%S"),
+                err.method,
+                err.error
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    bot.send_message(msg.chat.id, truncate_message(&text))
+        .await?;
+    Ok(())
+}
+
+async fn approve(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    change_access(
+        &bot,
+        &*rzd_db,
+        lang,
+        &text,
+        &msg,
+        Some(AccessStatus::Allowed),
+    )
+    .await
+}
+
+async fn ban(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    change_access(
+        &bot,
+        &*rzd_db,
+        lang,
+        &text,
+        &msg,
+        Some(AccessStatus::Banned),
+    )
+    .await
+}
+
+/// Removes the user from the registry, in allowlist and invite modes this also revokes an approval.
+async fn unban(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    change_access(&bot, &*rzd_db, lang, &text, &msg, None).await
+}
+
+async fn change_access(
+    bot: &Bot,
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    text: &str,
+    msg: &Message,
+    status: Option<AccessStatus>,
+) -> HandlerResult {
+    let user_id = match text.trim().parse::<u64>() {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            bot.send_message(msg.chat.id, lang.t(Key::UserIdUsage))
+                .await?;
+            return Ok(());
+        }
+    };
+    let (result, key) = match status {
+        Some(status) => (
+            rzd_db.set_access(user_id, status).await,
+            match status {
+                AccessStatus::Allowed => Key::UserApproved,
+                AccessStatus::Banned => Key::UserBanned,
+            },
+        ),
+        None => (rzd_db.reset_access(user_id).await, Key::UserAccessReset),
+    };
+    let text = match result {
+        Ok(()) => {
+            tracing::info!(
+                user_id,
+                ?status,
+                by = ?msg.from().map(|user| user.id.0),
+                "user access is changed"
+            );
+            lang.tf(key, &[("user_id", &user_id)])
+        }
+        Err(err) => format!("{}: {err}", lang.t(Key::Error)),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn invite(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    me: Me,
+    lang: Lang,
+    msg: Message,
+) -> HandlerResult {
+    let created_by = msg.from().map(|user| user.id.0).unwrap_or_default();
+    let text = match rzd_db.create_invite(created_by).await {
+        Ok(code) => {
+            let link = format!("https://t.me/{}?start={code}", me.username());
+            lang.tf(Key::InviteCreated, &[("code", &code), ("link", &link)])
+        }
+        Err(err) => format!("{}: {err}", lang.t(Key::Error)),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+/// Shows the limits of the user. With `tasks=N searches=N interval=SECS` overrides them first,
+/// with `reset` brings back the defaults.
+async fn user_limits(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let mut words = text.split_whitespace();
+    let user_id = words.next().and_then(|id| id.parse::<u64>().ok());
+    let rest = words.collect::<Vec<&str>>().join(" ");
+    let overrides = match rest.as_str() {
+        "" | "reset" => Some(LimitOverrides::default()),
+        rest => LimitOverrides::parse(rest),
+    };
+    let (user_id, overrides) = match (user_id, overrides) {
+        (Some(user_id), Some(overrides)) => (user_id, overrides),
+        _ => {
+            bot.send_message(msg.chat.id, lang.t(Key::LimitsUsage))
+                .await?;
+            return Ok(());
+        }
+    };
+    let result = async {
+        match rest.as_str() {
+            "" => {}
+            "reset" => rzd_db.set_limit_overrides(user_id, &overrides).await?,
+            _ => {
+                let overrides = rzd_db.get_limit_overrides(user_id).await?.merge(overrides);
+                rzd_db.set_limit_overrides(user_id, &overrides).await?;
+            }
+        }
+        rzd_db.user_limits(user_id).await
+    }
+    .await;
+    let text = match result {
+        Ok(limits) => lang.tf(
+            Key::UserLimits,
+            &[
+                ("user_id", &user_id),
+                ("tasks", &limits.max_tasks),
+                ("searches", &limits.searches_per_minute),
+                ("interval", &limits.min_poll_interval.as_secs()),
+            ],
+        ),
+        Err(err) => format!("{}: {err}", lang.t(Key::Error)),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+/// Makes a backup, with `list` shows the kept ones, with `export` sends the whole database as an archive.
+async fn backup(
+    bot: Bot,
+    backups: Arc<Backups>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let text = match text.trim() {
+        "" => match backups.create().await {
+            Ok(info) => lang.tf(
+                Key::BackupCreated,
+                &[("id", &info.id), ("size", &info.size)],
+            ),
+            Err(err) => format!("{}: {err}", lang.t(Key::Error)),
+        },
+        "list" => match backups.list().await {
+            Ok(list) if list.is_empty() => lang.t(Key::NoBackups).to_string(),
+            Ok(list) => {
+                let mut text = lang.t(Key::BackupsList).to_string();
+                for info in list {
+                    text.push_str(&format!(
+                        "\n{} {} {}",
+                        info.id,
+                        info.created_at(),
+                        info.size
+                    ));
+                }
+                text
+            }
+            Err(err) => format!("{}: {err}", lang.t(Key::Error)),
+        },
+        "export" => match backups.export().await {
+            Ok(archive) => {
+                let sent = bot
+                    .send_document(msg.chat.id, InputFile::file(&archive))
+                    .await;
+                // The archive is a copy of the database, it is not kept on the server
+                if let Err(err) = std::fs::remove_file(&archive) {
+                    tracing::warn!(archive = %archive.display(), error = %err, "cant remove exported archive");
+                }
+                sent?;
+                return Ok(());
+            }
+            Err(err) => format!("{}: {err}", lang.t(Key::Error)),
+        },
+        _ => lang.t(Key::BackupUsage).to_string(),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::RZDDb;
+    use crate::limits::Limits;
+    use crate::storage::MemoryStorage;
+
+    fn task(chat_id: &str, user_id: &str, status: &str) -> HashMap<String, String> {
+        [
+            ("type", "day"),
+            ("from_point_code", "2000000"),
+            ("to_point_code", "2004000"),
+            ("date", "21.10.2026"),
+            ("chat_id", chat_id),
+            ("user_id", user_id),
+            ("status", status),
+        ]
+        .iter()
+        .map(|(field, value)| (field.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn broadcasts_to_chats_with_active_tasks() {
+        let limits = Limits {
+            max_tasks: 0,
+            searches_per_minute: 0,
+            min_poll_interval: Duration::from_secs(60),
+        };
+        let rzd_db = RZDDb::new(Arc::<MemoryStorage>::default(), limits);
+        for task in [
+            task("1", "1", "active"),
+            task("2", "2", "paused"),
+            task("3", "3", "active"),
+            task("-4", "1", "paused"),
+            task("-4", "4", "active"),
+        ] {
+            rzd_db.create_task(task).await.unwrap();
+        }
+        rzd_db.set_access(3, AccessStatus::Banned).await.unwrap();
+
+        let chat_ids = broadcast_chats(&*rzd_db).await.unwrap();
+        assert_eq!(chat_ids, BTreeSet::from([-4, 1]));
+    }
+}
@@ -0,0 +1,388 @@
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Local, TimeZone};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use speedb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
+use speedb::checkpoint::Checkpoint;
+use speedb::{Env, DB};
+use tokio::sync::Mutex;
+use tokio::time::MissedTickBehavior;
+
+use crate::shutdown::Shutdown;
+
+const DEFAULT_BACKUP_KEEP: usize = 7;
+const DEFAULT_BACKUP_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Backup kept by the backup engine.
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    pub id: u32,
+    pub timestamp: i64,
+    pub size: u64,
+}
+
+impl BackupInfo {
+    pub fn created_at(&self) -> String {
+        match Local.timestamp_opt(self.timestamp, 0).single() {
+            Some(created_at) => created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => self.timestamp.to_string(),
+        }
+    }
+}
+
+/// Incremental backups of the database made with speedb's backup engine.
+pub struct Backups {
+    dir: PathBuf,
+    keep: usize,
+    interval: Option<Duration>,
+    restore_from: String,
+    /// The database backups are made of, `None` with the in-memory storage.
+    db: Option<Arc<DB>>,
+    /// Only one backup engine may work with the directory at a time.
+    engine: Mutex<()>,
+}
+
+impl Backups {
+    /// `BACKUP_DIR` defaults to `<DB_PATH>-backups`, `BACKUP_KEEP` backups are retained and a new one is made every
+    /// `BACKUP_INTERVAL_SECS`, 0 turns the schedule off. `RESTORE_BACKUP` is what fills a missing database at startup.
+    pub fn from_env(db_path: &str) -> Self {
+        let dir = env::var("BACKUP_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or_else(|| format!("{}-backups", db_path.trim_end_matches('/')));
+        let keep = env::var("BACKUP_KEEP")
+            .ok()
+            .and_then(|keep| keep.parse().ok())
+            .filter(|keep| *keep > 0)
+            .unwrap_or(DEFAULT_BACKUP_KEEP);
+        let interval = env::var("BACKUP_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_BACKUP_INTERVAL_SECS);
+        Self {
+            dir: PathBuf::from(dir),
+            keep,
+            interval: (interval > 0).then(|| Duration::from_secs(interval)),
+            restore_from: env::var("RESTORE_BACKUP")
+                .ok()
+                .filter(|source| !source.is_empty())
+                .unwrap_or_else(|| "latest".to_string()),
+            db: None,
+            engine: Mutex::new(()),
+        }
+    }
+
+    /// Backs up `db`, without it only listing and restoring work.
+    pub fn with_db(mut self, db: Arc<DB>) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    fn db(&self) -> Result<Arc<DB>, String> {
+        self.db
+            .clone()
+            .ok_or_else(|| "backups need the speedb storage".to_string())
+    }
+
+    /// Makes a new backup and removes the ones beyond the retention, on the blocking pool.
+    pub async fn create(&self) -> Result<BackupInfo, String> {
+        let db = self.db()?;
+        let _engine = self.engine.lock().await;
+        let dir = self.dir.clone();
+        let keep = self.keep;
+        tokio::task::spawn_blocking(move || {
+            let mut engine = open_engine(&dir)?;
+            engine
+                .create_new_backup_flush(&db, true)
+                .map_err(|err| format!("cant create backup {err}"))?;
+            engine
+                .purge_old_backups(keep)
+                .map_err(|err| format!("cant purge old backups {err}"))?;
+            match list_backups(&engine).pop() {
+                Some(info) => Ok(info),
+                None => Err("backup is missing after it was created".to_string()),
+            }
+        })
+        .await
+        .map_err(|err| err.to_string())?
+    }
+
+    /// Oldest backups first, read on the blocking pool.
+    pub async fn list(&self) -> Result<Vec<BackupInfo>, String> {
+        let _engine = self.engine.lock().await;
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || {
+            if !dir.exists() {
+                return Ok(vec![]);
+            }
+            Ok(list_backups(&open_engine(&dir)?))
+        })
+        .await
+        .map_err(|err| err.to_string())?
+    }
+
+    /// Writes a consistent copy of the database into a single `.tar.gz` archive,
+    /// the archive can be passed to `RESTORE_BACKUP`.
+    pub async fn export(&self) -> Result<PathBuf, String> {
+        let db = self.db()?;
+        let name = format!("rzd-{}", Local::now().format("%Y%m%d-%H%M%S"));
+        let checkpoint = self.dir.join(&name);
+        let archive = self.dir.join(format!("{name}.tar.gz"));
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking({
+            let archive = archive.clone();
+            move || {
+                fs::create_dir_all(&dir).map_err(|err| format!("cant create {dir:?} {err}"))?;
+                // Hard links the current sst files, `checkpoint` must not exist
+                let result = Checkpoint::new(&db)
+                    .and_then(|created| created.create_checkpoint(&checkpoint))
+                    .map_err(|err| format!("cant create checkpoint {err}"))
+                    .and_then(|()| pack(&checkpoint, &archive));
+                if checkpoint.exists() {
+                    if let Err(err) = fs::remove_dir_all(&checkpoint) {
+                        tracing::warn!(checkpoint = %checkpoint.display(), error = %err, "cant remove checkpoint");
+                    }
+                }
+                result
+            }
+        })
+        .await
+        .map_err(|err| err.to_string())??;
+        Ok(archive)
+    }
+
+    /// Makes backups on schedule until the shutdown.
+    pub async fn run(self: Arc<Self>, shutdown: Arc<Shutdown>) {
+        let interval = match self.interval {
+            Some(interval) if self.db.is_some() => interval,
+            _ => {
+                tracing::info!("scheduled backups are disabled");
+                return;
+            }
+        };
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            match self.create().await {
+                Ok(info) => tracing::info!(backup_id = info.id, size = info.size, "backup is created"),
+                Err(err) => tracing::error!(error = %err, "cant create backup"),
+            }
+        }
+    }
+
+    /// Replaces the database at `db_path` before it is opened. `source` is `latest`, a backup id or a path to an
+    /// exported archive. The replaced database is kept next to it.
+    pub fn restore(&self, db_path: &str, source: &str) -> Result<(), String> {
+        let db_path = Path::new(db_path);
+        let backup_id = match source {
+            "latest" => None,
+            source => match source.parse::<u32>() {
+                Ok(id) => Some(id),
+                Err(_) => {
+                    let archive = Path::new(source);
+                    if !archive.is_file() {
+                        return Err(format!("archive {archive:?} does not exist"));
+                    }
+                    move_away(db_path)?;
+                    return unpack(archive, db_path);
+                }
+            },
+        };
+        let mut engine = open_engine(&self.dir)?;
+        if !list_backups(&engine)
+            .iter()
+            .any(|info| backup_id.is_none() || backup_id == Some(info.id))
+        {
+            return Err(format!("backup {source} does not exist"));
+        }
+        move_away(db_path)?;
+        let options = RestoreOptions::default();
+        match backup_id {
+            Some(id) => engine.restore_from_backup(db_path, db_path, &options, id),
+            None => engine.restore_from_latest_backup(db_path, db_path, &options),
+        }
+        .map_err(|err| format!("cant restore backup {source} {err}"))
+    }
+
+    /// Fills the database directory when it is gone, e.g. with a lost volume. An existing database is only replaced
+    /// with the `db restore` command, so a restart never rolls it back.
+    pub fn restore_missing(&self, db_path: &str) -> Result<Option<&str>, String> {
+        if Path::new(db_path).exists() {
+            return Ok(None);
+        }
+        if self.restore_from == "latest"
+            && (!self.dir.exists() || list_backups(&open_engine(&self.dir)?).is_empty())
+        {
+            return Ok(None);
+        }
+        self.restore(db_path, &self.restore_from)?;
+        Ok(Some(&self.restore_from))
+    }
+}
+
+/// Keeps the database that is about to be replaced next to it.
+fn move_away(db_path: &Path) -> Result<(), String> {
+    if !db_path.exists() {
+        return Ok(());
+    }
+    let replaced = PathBuf::from(format!(
+        "{}.before-restore-{}",
+        db_path.display(),
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::rename(db_path, &replaced).map_err(|err| format!("cant move away {db_path:?} {err}"))?;
+    tracing::warn!(path = %replaced.display(), "current db is moved away");
+    Ok(())
+}
+
+fn open_engine(dir: &Path) -> Result<BackupEngine, String> {
+    let options = BackupEngineOptions::new(dir)
+        .map_err(|err| format!("cant use backup dir {dir:?} {err}"))?;
+    let env = Env::new().map_err(|err| err.to_string())?;
+    BackupEngine::open(&options, &env).map_err(|err| format!("cant open backups in {dir:?} {err}"))
+}
+
+fn list_backups(engine: &BackupEngine) -> Vec<BackupInfo> {
+    let mut backups: Vec<BackupInfo> = engine
+        .get_backup_info()
+        .into_iter()
+        .map(|info| BackupInfo {
+            id: info.backup_id,
+            timestamp: info.timestamp,
+            size: info.size,
+        })
+        .collect();
+    backups.sort_by_key(|info| info.id);
+    backups
+}
+
+fn pack(dir: &Path, archive: &Path) -> Result<(), String> {
+    let file = File::create(archive).map_err(|err| format!("cant create {archive:?} {err}"))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder
+        .append_dir_all(".", dir)
+        .map_err(|err| format!("cant archive {dir:?} {err}"))?;
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map(|_| ())
+        .map_err(|err| format!("cant write {archive:?} {err}"))
+}
+
+fn unpack(archive: &Path, db_path: &Path) -> Result<(), String> {
+    let file = File::open(archive).map_err(|err| format!("cant open {archive:?} {err}"))?;
+    tar::Archive::new(GzDecoder::new(file))
+        .unpack(db_path)
+        .map_err(|err| format!("cant unpack {archive:?} {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use speedb::Options;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn backups(tmp: &TempDir) -> Backups {
+        Backups {
+            dir: tmp.path().join("backups"),
+            keep: DEFAULT_BACKUP_KEEP,
+            interval: None,
+            restore_from: "latest".to_string(),
+            db: None,
+            engine: Mutex::new(()),
+        }
+    }
+
+    fn open_db(path: &Path) -> Arc<DB> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        Arc::new(DB::open(&options, path).unwrap())
+    }
+
+    fn stored(path: &Path) -> Option<Vec<u8>> {
+        DB::open(&Options::default(), path)
+            .unwrap()
+            .get("task")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn restores_backups_and_archives() {
+        let tmp = TempDir::new().unwrap();
+        let db = open_db(&tmp.path().join("db"));
+        let backups = backups(&tmp).with_db(db.clone());
+        db.put("task", "first").unwrap();
+        let first = backups.create().await.unwrap();
+        db.put("task", "second").unwrap();
+        backups.create().await.unwrap();
+        db.put("task", "exported").unwrap();
+        let archive = backups.export().await.unwrap();
+        assert_eq!(backups.list().await.unwrap().len(), 2);
+
+        let restored = |name: &str| tmp.path().join(name);
+        backups
+            .restore(restored("latest").to_str().unwrap(), "latest")
+            .unwrap();
+        assert_eq!(stored(&restored("latest")), Some(b"second".to_vec()));
+        backups
+            .restore(restored("by-id").to_str().unwrap(), &first.id.to_string())
+            .unwrap();
+        assert_eq!(stored(&restored("by-id")), Some(b"first".to_vec()));
+        backups
+            .restore(
+                restored("archive").to_str().unwrap(),
+                archive.to_str().unwrap(),
+            )
+            .unwrap();
+        assert_eq!(stored(&restored("archive")), Some(b"exported".to_vec()));
+
+        let missing = restored("missing");
+        assert!(backups.restore(missing.to_str().unwrap(), "99").is_err());
+        assert!(backups
+            .restore(missing.to_str().unwrap(), "/nonexistent.tar.gz")
+            .is_err());
+        assert!(!missing.exists());
+    }
+
+    #[tokio::test]
+    async fn leaves_a_missing_db_without_backups_to_be_created() {
+        let tmp = TempDir::new().unwrap();
+        let backups = backups(&tmp);
+        let db_path = tmp.path().join("db");
+        assert_eq!(backups.restore_missing(db_path.to_str().unwrap()), Ok(None));
+
+        fs::create_dir_all(&backups.dir).unwrap();
+        assert!(backups.list().await.unwrap().is_empty());
+        assert_eq!(backups.restore_missing(db_path.to_str().unwrap()), Ok(None));
+        assert!(!db_path.exists());
+    }
+
+    #[tokio::test]
+    async fn restores_a_missing_db_from_the_latest_backup() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("db");
+        let db = open_db(&db_path);
+        let backups = backups(&tmp).with_db(db.clone());
+        db.put("task", "backed up").unwrap();
+        backups.create().await.unwrap();
+
+        assert_eq!(backups.restore_missing(db_path.to_str().unwrap()), Ok(None));
+        let lost = tmp.path().join("lost");
+        assert_eq!(
+            backups.restore_missing(lost.to_str().unwrap()),
+            Ok(Some("latest"))
+        );
+        assert_eq!(stored(&lost), Some(b"backed up".to_vec()));
+    }
+}
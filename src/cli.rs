@@ -0,0 +1,277 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use speedb::{Options, DB};
+use uuid::Uuid;
+
+use crate::backup::Backups;
+use crate::db::RZDDb;
+use crate::limits::Limits;
+
+const USAGE: &str = "usage: rzd_tg_bot db [--write] <command>
+
+commands:
+    tasks [field=value ...]          list tasks, optionally only the ones with matching fields
+    show <task_id>                   print a task
+    delete <task_id>                 delete a task with its snapshot and pending notification, needs --write
+    edit <task_id> field=value ...   change task fields, an empty value removes the field, needs --write
+    dump [prefix]                    print keys with their decoded values
+    stats                            print record counts and the database size
+    reindex                          rebuild the task indexes, needs --write
+    backups                          list the backups in BACKUP_DIR
+    backup                           make a backup now, needs --write
+    export                           write the database into a .tar.gz archive in BACKUP_DIR, needs --write
+    restore <latest|id|archive>      replace the database with a backup or an exported archive,
+                                     the bot must be stopped
+
+The database at DB_PATH is opened read-only unless --write is given,
+a read-write open fails while the bot is running.";
+
+/// Offline maintenance of the database, `args` are the ones after `db`.
+pub async fn run(db_path: &str, args: &[String]) -> Result<(), String> {
+    let write = args.iter().any(|arg| arg == "--write");
+    let args: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| *arg != "--write")
+        .collect();
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (*command, args),
+        None => {
+            println!("{USAGE}");
+            return Ok(());
+        }
+    };
+    let backups = Backups::from_env(db_path);
+    match command {
+        "backups" => return list_backups(&backups).await,
+        "restore" => {
+            let source = args.first().ok_or("backup id or archive is missing")?;
+            backups.restore(db_path, source)?;
+            println!("restored {source} into {db_path}");
+            return Ok(());
+        }
+        _ => {}
+    }
+    if matches!(command, "delete" | "edit" | "reindex" | "backup" | "export") && !write {
+        return Err(format!(
+            "{command} changes the database, run it with --write"
+        ));
+    }
+    let db = open(db_path, write)?;
+    let rzd_db = RZDDb::new(db.clone(), Limits::from_env());
+    let backups = backups.with_db(db);
+    match command {
+        "tasks" => list_tasks(&rzd_db, &parse_fields(args)?).await,
+        "show" => show_task(&rzd_db, task_id(args)?).await,
+        "delete" => delete_task(&rzd_db, task_id(args)?).await,
+        "edit" => edit_task(&rzd_db, task_id(args)?, &parse_fields(&args[1..])?).await,
+        "dump" => dump(&rzd_db, args.first().copied().unwrap_or_default()).await,
+        "stats" => stats(&rzd_db).await,
+        "reindex" => {
+            let entries = rzd_db.rebuild_indexes().await?;
+            rzd_db.flush().await?;
+            println!("rebuilt {entries} index entries");
+            Ok(())
+        }
+        "backup" => {
+            let info = backups.create().await?;
+            println!("created backup {} of {} bytes", info.id, info.size);
+            Ok(())
+        }
+        "export" => {
+            let archive = backups.export().await?;
+            println!("exported to {}", archive.display());
+            Ok(())
+        }
+        "help" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(format!("unknown command {command}\n\n{USAGE}")),
+    }
+}
+
+fn open(db_path: &str, write: bool) -> Result<Arc<DB>, String> {
+    let db = if write {
+        DB::open(&Options::default(), db_path)
+    } else {
+        DB::open_for_read_only(&Options::default(), db_path, false)
+    }
+    .map_err(|err| format!("cant open db {db_path}: {err}"))?;
+    Ok(Arc::new(db))
+}
+
+fn task_id<'a>(args: &[&'a str]) -> Result<&'a str, String> {
+    match args.first() {
+        Some(task_id) => Ok(task_id),
+        None => Err("task id is missing".to_string()),
+    }
+}
+
+/// `field=value` pairs, the value may be empty.
+fn parse_fields(args: &[&str]) -> Result<BTreeMap<String, String>, String> {
+    args.iter()
+        .map(|arg| match arg.split_once('=') {
+            Some((field, value)) if !field.is_empty() => Ok((field.to_string(), value.to_string())),
+            _ => Err(format!("expected field=value, got {arg}")),
+        })
+        .collect()
+}
+
+fn format_task(task_id: &str, task: &HashMap<String, String>) -> String {
+    let fields: BTreeMap<&String, &String> = task.iter().collect();
+    format!(
+        "{task_id} {}",
+        serde_json::to_string(&fields).unwrap_or_default()
+    )
+}
+
+/// A filter naming the whole route is looked up in the route index instead of scanning all tasks.
+async fn list_tasks(rzd_db: &RZDDb, filter: &BTreeMap<String, String>) -> Result<(), String> {
+    let field = |name: &str| filter.get(name).map(String::as_str);
+    let (tasks, scope): (BTreeMap<String, HashMap<String, String>>, &str) = match (
+        field("from_point_code"),
+        field("to_point_code"),
+        field("date"),
+    ) {
+        (Some(from), Some(to), Some(date)) => (
+            rzd_db
+                .list_route_tasks(from, to, date)
+                .await?
+                .into_iter()
+                .collect(),
+            " on the route",
+        ),
+        _ => (rzd_db.list_tasks().await?.into_iter().collect(), ""),
+    };
+    let mut matched = 0;
+    for (task_id, task) in &tasks {
+        if filter
+            .iter()
+            .all(|(field, value)| task.get(field) == Some(value))
+        {
+            println!("{}", format_task(task_id, task));
+            matched += 1;
+        }
+    }
+    eprintln!("{matched} of {} tasks{scope}", tasks.len());
+    Ok(())
+}
+
+async fn show_task(rzd_db: &RZDDb, task_id: &str) -> Result<(), String> {
+    let task = match rzd_db.get_task(task_id).await? {
+        Some(task) => task,
+        None => return Err(format!("unknown task {task_id}")),
+    };
+    let fields: BTreeMap<String, String> = task.into_iter().collect();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&fields).map_err(|err| err.to_string())?
+    );
+    for prefix in ["snapshot:", "notification:", "history:"] {
+        for (key, value) in rzd_db.dump(&format!("{prefix}{task_id}")).await? {
+            println!("{key} {}", decode(&value));
+        }
+    }
+    Ok(())
+}
+
+async fn delete_task(rzd_db: &RZDDb, task_id: &str) -> Result<(), String> {
+    if rzd_db.get_task(task_id).await?.is_none() {
+        return Err(format!("unknown task {task_id}"));
+    }
+    rzd_db.delete_task_by_id(task_id.to_string()).await?;
+    rzd_db.flush().await?;
+    println!("deleted {task_id}");
+    Ok(())
+}
+
+async fn edit_task(
+    rzd_db: &RZDDb,
+    task_id: &str,
+    fields: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    if fields.is_empty() {
+        return Err("nothing to change, expected field=value".to_string());
+    }
+    let mut task = match rzd_db.get_task(task_id).await? {
+        Some(task) => task,
+        None => return Err(format!("unknown task {task_id}")),
+    };
+    for (field, value) in fields {
+        if value.is_empty() {
+            task.remove(field);
+        } else {
+            task.insert(field.clone(), value.clone());
+        }
+    }
+    rzd_db.put_task(task_id, &task).await?;
+    rzd_db.flush().await?;
+    println!("{}", format_task(task_id, &task));
+    Ok(())
+}
+
+async fn dump(rzd_db: &RZDDb, prefix: &str) -> Result<(), String> {
+    for (key, value) in rzd_db.dump(prefix).await? {
+        println!("{key} {}", decode(&value));
+    }
+    Ok(())
+}
+
+/// Values are JSON, anything else is printed lossily.
+fn decode(value: &[u8]) -> String {
+    match serde_json::from_slice::<serde_json::Value>(value) {
+        Ok(value) => value.to_string(),
+        Err(_) => format!("{:?}", String::from_utf8_lossy(value)),
+    }
+}
+
+/// Record kind used in the stats, tasks are the only keys without a prefix.
+fn key_kind(key: &str) -> &str {
+    match key.split_once(':') {
+        Some((prefix, _)) => prefix,
+        None if Uuid::parse_str(key).is_ok() => "task",
+        None => "other",
+    }
+}
+
+async fn stats(rzd_db: &RZDDb) -> Result<(), String> {
+    let mut kinds: BTreeMap<String, usize> = BTreeMap::new();
+    for (key, _) in rzd_db.dump("").await? {
+        *kinds.entry(key_kind(&key).to_string()).or_default() += 1;
+    }
+    let mut task_types: BTreeMap<String, usize> = BTreeMap::new();
+    let mut owners: BTreeMap<String, usize> = BTreeMap::new();
+    for task in rzd_db.list_tasks().await?.values() {
+        *task_types
+            .entry(task.get("type").cloned().unwrap_or_default())
+            .or_default() += 1;
+        if let Some(user_id) = task.get("user_id") {
+            *owners.entry(user_id.clone()).or_default() += 1;
+        }
+    }
+
+    println!("records:");
+    for (kind, count) in &kinds {
+        println!("    {kind}: {count}");
+    }
+    println!("tasks by type:");
+    for (task_type, count) in &task_types {
+        println!("    {task_type}: {count}");
+    }
+    println!("task owners: {}", owners.len());
+    if let Some((user_id, count)) = owners.iter().max_by_key(|(_, count)| **count) {
+        println!("most tasks: user {user_id} with {count}");
+    }
+    println!("users: {}", rzd_db.list_user_ids().await?.len());
+    println!("size: {} bytes", rzd_db.size_bytes().await?);
+    Ok(())
+}
+
+async fn list_backups(backups: &Backups) -> Result<(), String> {
+    for info in backups.list().await? {
+        println!("{} {} {} bytes", info.id, info.created_at(), info.size);
+    }
+    Ok(())
+}
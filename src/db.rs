@@ -1,72 +1,1706 @@
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
-use speedb::{IteratorMode, DB};
-use tokio::sync::Mutex;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, MutexGuard};
 use uuid::Uuid;
 
+use crate::access::AccessStatus;
+use crate::expiry::{expires_at, moscow_now};
+use crate::i18n::Lang;
+use crate::limits::{LimitOverrides, Limits};
+use crate::rzd::GetRZDPointCodes;
+use crate::schema::{decode_task, encode_task, DecodeError};
+use crate::storage::{Batch, Storage};
+use crate::store::TaskStore;
+use crate::task_edit::is_paused;
+
+const STATION_PREFIX: &str = "station:";
+const SNAPSHOT_PREFIX: &str = "snapshot:";
+const NOTIFICATION_PREFIX: &str = "notification:";
+const HEALTH_CHECK_KEY: &str = "health:check";
+const INVITE_PREFIX: &str = "invite:";
+const USER_PREFIX: &str = "user:";
+const QUARANTINE_PREFIX: &str = "quarantine:";
+const NOTIFIED_PREFIX: &str = "notified:";
+const ARCHIVE_PREFIX: &str = "archive:";
+/// Secondary indexes of the tasks, `index:<name>:<value>:<task_id>` with an empty value.
+const INDEX_PREFIX: &str = "index:";
+const HISTORY_PREFIX: &str = "history:";
+const FAVOURITE_STATIONS_LIMIT: usize = 8;
+/// Sent notifications kept per task, older ones are pruned.
+const NOTIFICATION_HISTORY_LIMIT: usize = 50;
+const RECENT_ROUTES_LIMIT: usize = 5;
+const SEARCH_WINDOW_SECS: i64 = 60;
+/// Task records `TaskPages` reads at a time.
+const TASK_PAGE_SIZE: usize = 500;
+/// Task ids are lowercase hex uuids, so every task sorts below this key. Prefixed records sort among them too,
+/// e.g. `archive:`, so `scan_tasks` skips every key that isn't a uuid.
+const TASK_KEYS_END: &[u8] = b"g";
+const WRITE_LOCK_SHARDS: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecentRoute {
+    pub(crate) from: GetRZDPointCodes,
+    pub(crate) to: GetRZDPointCodes,
+}
+
+/// Notification the poller found but did not deliver yet.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PendingNotification {
+    pub(crate) chat_id: i64,
+    pub(crate) text: String,
+    pub(crate) task_type: String,
+    #[serde(default)]
+    pub(crate) attempts: u32,
+    #[serde(default)]
+    pub(crate) seats: Vec<FoundSeats>,
+    /// Places that appeared and disappeared since the previous poll.
+    #[serde(default)]
+    pub(crate) added: usize,
+    #[serde(default)]
+    pub(crate) removed: usize,
+}
+
+/// Free seats a poll found in a train, or in one car of it for train tasks.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FoundSeats {
+    pub(crate) train: String,
+    pub(crate) car: Option<String>,
+    pub(crate) seats: usize,
+}
+
+/// Notification delivered to the owner of a task, kept in the history of the task.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SentNotification {
+    pub(crate) sent_at: String,
+    pub(crate) seats: Vec<FoundSeats>,
+    pub(crate) added: usize,
+    pub(crate) removed: usize,
+    pub(crate) message_id: i32,
+}
+
+/// Record that could not be decoded, kept as it was stored.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuarantinedRecord {
+    pub(crate) key: String,
+    pub(crate) value: String,
+    pub(crate) error: String,
+    pub(crate) at: String,
+}
+
+/// Tasks with their ids in the order of the index they were looked up in.
+pub type TaskList = Vec<(String, HashMap<String, String>)>;
+
+/// Outcome of a pass over the stored tasks.
+#[derive(Debug, Default)]
+pub struct TaskScan {
+    pub tasks: TaskList,
+    pub upgraded: usize,
+    pub quarantined: usize,
+    pub newer: usize,
+    /// Last task key looked at when the scan stopped at its limit, `None` if it reached the end.
+    pub next: Option<String>,
+}
+
+/// Entries are ordered by the time they were sent.
+fn history_prefix(task_id: &str) -> String {
+    format!("{HISTORY_PREFIX}{task_id}:")
+}
+
+/// Zero padded sequence number of the entry within the history of its task.
+fn history_key(prefix: &str, seq: u64) -> String {
+    format!("{prefix}{seq:020}")
+}
+
+fn user_key(user_id: u64, suffix: &str) -> String {
+    format!("{USER_PREFIX}{user_id}:{suffix}")
+}
+
+fn chat_index(chat_id: &str) -> String {
+    format!("{INDEX_PREFIX}chat:{chat_id}:")
+}
+
+fn owner_index(user_id: &str) -> String {
+    format!("{INDEX_PREFIX}owner:{user_id}:")
+}
+
+fn route_index(from: &str, to: &str, date: &str) -> String {
+    format!("{INDEX_PREFIX}route:{from}:{to}:{date}:")
+}
+
+/// Zero padded, so the keys are ordered by time.
+fn due_index(next_poll_at: u64) -> String {
+    format!("{INDEX_PREFIX}due:{next_poll_at:020}:")
+}
+
+/// Active tasks by type, moved along with the task writes so the metrics never scan the tasks.
+#[derive(Default)]
+struct ActiveTasks(std::sync::Mutex<BTreeMap<String, f64>>);
+
+impl ActiveTasks {
+    fn reset(&self, tasks: &TaskList) {
+        let mut counts = self.0.lock().unwrap();
+        counts.clear();
+        for (_, task) in tasks {
+            if let Some(task_type) = active_type(Some(task)) {
+                *counts.entry(task_type).or_default() += 1.0;
+            }
+        }
+    }
+
+    /// A task counted under `from` is now counted under `to`.
+    fn shift(&self, from: Option<String>, to: Option<String>) {
+        if from == to {
+            return;
+        }
+        let mut counts = self.0.lock().unwrap();
+        if let Some(from) = from {
+            *counts.entry(from).or_default() -= 1.0;
+        }
+        if let Some(to) = to {
+            *counts.entry(to).or_default() += 1.0;
+        }
+    }
+}
+
+/// Serializes the changes computed from what is stored per key. Keys are spread over a fixed set of shards, so
+/// writes of unrelated tasks and users rarely wait for each other.
+struct WriteLocks(Vec<Mutex<()>>);
+
+impl WriteLocks {
+    fn new() -> Self {
+        Self((0..WRITE_LOCK_SHARDS).map(|_| Mutex::new(())).collect())
+    }
+
+    fn shard(&self, key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize % self.0.len()
+    }
+
+    async fn lock(&self, key: &str) -> MutexGuard<'_, ()> {
+        self.0[self.shard(key)].lock().await
+    }
+
+    /// Every shard in order, for the passes over all the tasks. Other callers hold one shard at a time, so the
+    /// order keeps them from deadlocking.
+    async fn lock_all(&self) -> Vec<MutexGuard<'_, ()>> {
+        let mut guards = Vec::with_capacity(self.0.len());
+        for shard in &self.0 {
+            guards.push(shard.lock().await);
+        }
+        guards
+    }
+}
+
+/// Type the task is counted under in the active tasks, `None` for missing and paused tasks.
+fn active_type(task: Option<&HashMap<String, String>>) -> Option<String> {
+    task.filter(|task| !is_paused(task))
+        .map(|task| task.get("type").cloned().unwrap_or_default())
+}
+
+/// The storage is shared without a lock and its calls run on the blocking pool, speedb does its disk I/O
+/// synchronously. Changes computed from what is stored are serialized per key with `writes`, reads never wait for
+/// them.
 pub struct RZDDb {
-    inner: Mutex<DB>,
+    db: Arc<dyn Storage>,
+    writes: WriteLocks,
+    limits: Limits,
+    active_tasks: ActiveTasks,
 }
 
 impl RZDDb {
     #[must_use]
-    pub fn new(db: DB) -> Arc<Self> {
+    pub fn new(db: Arc<dyn Storage>, limits: Limits) -> Arc<Self> {
         Arc::new(Self {
-            inner: Mutex::new(db),
+            db,
+            writes: WriteLocks::new(),
+            limits,
+            active_tasks: ActiveTasks::default(),
         })
     }
 
-    pub async fn create_task(&self, data: HashMap<String, String>) -> Result<String, String> {
+    /// Runs `f` with the storage on the blocking pool.
+    async fn blocking<T, F>(&self, f: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn Storage) -> Result<T, String> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&*db))
+            .await
+            .map_err(|err| format!("storage call failed {err}"))?
+    }
+
+    /// Fills in a missing `expires_at` from the travel date and the owner's TTL.
+    async fn fill_expiry(&self, data: &mut HashMap<String, String>) -> Result<(), String> {
+        if data.contains_key("expires_at") {
+            return Ok(());
+        }
+        let ttl = match data.get("user_id").and_then(|id| id.parse::<u64>().ok()) {
+            Some(user_id) => self.get_task_ttl(user_id).await?,
+            None => None,
+        };
+        let ttl = ttl.map(|days| chrono::Duration::days(days.into()));
+        if let Some(expires_at) = expires_at(data, ttl, moscow_now()) {
+            data.insert("expires_at".to_string(), expires_at.to_rfc3339());
+        }
+        Ok(())
+    }
+
+    /// Refuses to store more tasks than the owner's quota allows. Sets `expires_at` from the travel date
+    /// and the owner's TTL unless it is given.
+    pub async fn create_task(&self, mut data: HashMap<String, String>) -> Result<String, String> {
         let key = Uuid::new_v4().to_string();
-        let data_slice = serde_json::to_vec(&data);
-        if data_slice.is_err() {
-            return Err(format!("cant serialize data {:?}", data_slice));
+        let user_id = data.get("user_id").and_then(|id| id.parse::<u64>().ok());
+        self.fill_expiry(&mut data).await?;
+        let data_slice = encode_task(&data)?;
+        let quota = match user_id {
+            Some(user_id) => Some((user_id, self.user_limits(user_id).await?.max_tasks)),
+            None => None,
+        };
+        let active = active_type(Some(&data));
+        // The quota counts every task of the owner
+        let _writes = match user_id {
+            Some(user_id) => self.writes.lock(&owner_index(&user_id.to_string())).await,
+            None => self.writes.lock(&key).await,
+        };
+        let key = self
+            .blocking(move |db| {
+                if let Some((user_id, max_tasks)) = quota {
+                    if max_tasks > 0 && count_owned_tasks(db, user_id)? >= max_tasks {
+                        return Err(format!("task limit of {max_tasks} is reached"));
+                    }
+                }
+                let mut batch = Batch::default();
+                batch.put(&key, data_slice);
+                index_task(&mut batch, &key, None, Some(&data));
+                match db.write(batch) {
+                    Ok(_) => Ok(key),
+                    Err(err) => Err(err.to_string()),
+                }
+            })
+            .await?;
+        self.active_tasks.shift(None, active);
+        Ok(key)
+    }
+
+    pub async fn delete_task_by_id(&self, task_id: String) -> Result<String, String> {
+        let _writes = self.writes.lock(&task_id).await;
+        let (task_id, previous) = self
+            .blocking(move |db| {
+                let mut batch = Batch::default();
+                for prefix in [SNAPSHOT_PREFIX, NOTIFICATION_PREFIX, NOTIFIED_PREFIX] {
+                    batch.delete(format!("{prefix}{task_id}"));
+                }
+                for (key, _) in prefixed(db, &history_prefix(&task_id))? {
+                    batch.delete(key);
+                }
+                batch.delete(&task_id);
+                let previous = stored_task(db, &task_id)?;
+                index_task(&mut batch, &task_id, previous.as_ref(), None);
+                match db.write(batch) {
+                    Ok(()) => Ok((task_id, active_type(previous.as_ref()))),
+                    Err(err) => Err(err.to_string()),
+                }
+            })
+            .await?;
+        self.active_tasks.shift(previous, None);
+        Ok(task_id)
+    }
+
+    /// Removes the task the sweeper found expired, with `archive` keeps a copy under `archive:<task_id>`
+    /// together with its notification history. A pending notification stays to be delivered.
+    pub async fn expire_task(&self, task_id: &str, archive: bool) -> Result<(), String> {
+        let task_id = task_id.to_string();
+        let _writes = self.writes.lock(&task_id).await;
+        let previous = self.blocking(move |db| {
+            let task = match db.get(&task_id) {
+                Ok(Some(value)) => {
+                    decode_task(&value)
+                        .map_err(|err| format!("cant decode task {task_id} {err}"))?
+                        .data
+                }
+                Ok(None) => return Ok(None),
+                Err(err) => return Err(err.to_string()),
+            };
+            let mut batch = Batch::default();
+            if archive {
+                let mut archived = task.clone();
+                archived.insert("expired_at".to_string(), moscow_now().to_rfc3339());
+                batch.put(
+                    format!("{ARCHIVE_PREFIX}{task_id}"),
+                    encode_task(&archived)?,
+                );
+            } else {
+                for (key, _) in prefixed(db, &history_prefix(&task_id))? {
+                    batch.delete(key);
+                }
+            }
+            batch.delete(format!("{SNAPSHOT_PREFIX}{task_id}"));
+            batch.delete(format!("{NOTIFIED_PREFIX}{task_id}"));
+            batch.delete(&task_id);
+            index_task(&mut batch, &task_id, Some(&task), None);
+            db.write(batch).map_err(|err| err.to_string())?;
+            Ok(active_type(Some(&task)))
+        })
+        .await?;
+        self.active_tasks.shift(previous, None);
+        Ok(())
+    }
+
+    /// Whether the owner was ever notified about free places for the task.
+    pub async fn was_notified(&self, task_id: &str) -> Result<bool, String> {
+        Ok(self
+            .get_value::<String>(format!("{NOTIFIED_PREFIX}{task_id}"))
+            .await?
+            .is_some())
+    }
+
+    /// Free places the poller found for the task last time, used to notify only about changes.
+    pub async fn get_snapshot(&self, task_id: &str) -> Result<Option<Vec<String>>, String> {
+        self.get_value(format!("{SNAPSHOT_PREFIX}{task_id}")).await
+    }
+
+    /// Skipped if the task was deleted or expired since the poller read it.
+    pub async fn put_snapshot(&self, task_id: &str, snapshot: &[String]) -> Result<(), String> {
+        let snapshot = encode(&snapshot)?;
+        let task_id = task_id.to_string();
+        let _writes = self.writes.lock(&task_id).await;
+        self.blocking(move |db| {
+            if !exists(db, &task_id)? {
+                return Ok(());
+            }
+            db.put(format!("{SNAPSHOT_PREFIX}{task_id}"), snapshot)
+        })
+        .await
+    }
+
+    /// Stores the new snapshot together with the notification about it,
+    /// so a restart between finding places and notifying neither repeats nor loses the alert.
+    /// A newer notification for the task replaces the undelivered one. Skipped if the task was deleted or
+    /// expired since the poller read it.
+    pub async fn queue_notification(
+        &self,
+        task_id: &str,
+        snapshot: &[String],
+        notification: &PendingNotification,
+    ) -> Result<(), String> {
+        let snapshot = encode(&snapshot)?;
+        let notification = encode(notification)?;
+        let notified_at = encode(&moscow_now().to_rfc3339())?;
+        let task_id = task_id.to_string();
+        let _writes = self.writes.lock(&task_id).await;
+        self.blocking(move |db| {
+            if !exists(db, &task_id)? {
+                return Ok(());
+            }
+            let mut batch = Batch::default();
+            batch.put(format!("{SNAPSHOT_PREFIX}{task_id}"), snapshot);
+            batch.put(format!("{NOTIFIED_PREFIX}{task_id}"), notified_at);
+            batch.put(format!("{NOTIFICATION_PREFIX}{task_id}"), notification);
+            db.write(batch)
+        })
+        .await
+    }
+
+    /// Undelivered notifications by task id.
+    pub async fn list_pending_notifications(
+        &self,
+    ) -> Result<Vec<(String, PendingNotification)>, String> {
+        self.blocking(|db| {
+            let mut notifications = Vec::new();
+            for r in db.scan(NOTIFICATION_PREFIX.as_bytes()) {
+                let (key, value) =
+                    r.map_err(|err| format!("cant iterate over notifications {err}"))?;
+                let task_id = match key.strip_prefix(NOTIFICATION_PREFIX.as_bytes()) {
+                    Some(task_id) => String::from_utf8_lossy(task_id).to_string(),
+                    None => break,
+                };
+                match serde_json::from_slice::<PendingNotification>(&value) {
+                    Ok(notification) => notifications.push((task_id, notification)),
+                    Err(err) => return Err(format!("cant decode notification {task_id} {err}")),
+                }
+            }
+            Ok(notifications)
+        })
+        .await
+    }
+
+    /// Updates the undelivered notification, one the task deletion dropped meanwhile is not brought back.
+    pub async fn put_pending_notification(
+        &self,
+        task_id: &str,
+        notification: &PendingNotification,
+    ) -> Result<(), String> {
+        let value = encode(notification)?;
+        let task_id = task_id.to_string();
+        let _writes = self.writes.lock(&task_id).await;
+        self.blocking(move |db| {
+            let key = format!("{NOTIFICATION_PREFIX}{task_id}");
+            if !exists(db, &key)? {
+                return Ok(());
+            }
+            db.put(key, value)
+        })
+        .await
+    }
+
+    /// Moves the delivered notification of the task into its history, pruning the oldest entries
+    /// beyond the retention limit. Entries are numbered after the latest one, so notifications recorded within
+    /// the same millisecond don't overwrite each other. Older builds keyed them by the time in milliseconds,
+    /// the numbers carry on from there. Nothing is kept for a task that is gone and not archived.
+    pub async fn record_notification(
+        &self,
+        task_id: &str,
+        notification: &SentNotification,
+    ) -> Result<(), String> {
+        let value = encode(notification)?;
+        let task_id = task_id.to_string();
+        let _writes = self.writes.lock(&task_id).await;
+        self.blocking(move |db| {
+            if !exists(db, &task_id)? && !exists(db, &format!("{ARCHIVE_PREFIX}{task_id}"))? {
+                return db.delete(format!("{NOTIFICATION_PREFIX}{task_id}"));
+            }
+            let prefix = history_prefix(&task_id);
+            let history = prefixed(db, &prefix)?;
+            let seq = match history.last() {
+                Some((key, _)) => match key[prefix.len()..].parse::<u64>() {
+                    Ok(seq) => seq + 1,
+                    Err(err) => return Err(format!("cant parse history key {key} {err}")),
+                },
+                None => 0,
+            };
+            let mut batch = Batch::default();
+            batch.delete(format!("{NOTIFICATION_PREFIX}{task_id}"));
+            batch.put(history_key(&prefix, seq), value);
+            let outdated = (history.len() + 1).saturating_sub(NOTIFICATION_HISTORY_LIMIT);
+            for (key, _) in history.into_iter().take(outdated) {
+                batch.delete(key);
+            }
+            db.write(batch).map_err(|err| err.to_string())
+        })
+        .await
+    }
+
+    /// Sent notifications of the task, the latest first.
+    pub async fn list_notification_history(
+        &self,
+        task_id: &str,
+    ) -> Result<Vec<SentNotification>, String> {
+        let mut history = Vec::new();
+        for (key, value) in self.dump(&history_prefix(task_id)).await?.into_iter().rev() {
+            match serde_json::from_slice(&value) {
+                Ok(notification) => history.push(notification),
+                Err(err) => return Err(format!("cant decode notification {key} {err}")),
+            }
         }
-        match self
-            .inner
-            .lock()
+        Ok(history)
+    }
+
+    pub async fn delete_pending_notification(&self, task_id: &str) -> Result<(), String> {
+        self.delete_value(format!("{NOTIFICATION_PREFIX}{task_id}"))
             .await
-            .put(key.clone(), data_slice.unwrap())
-        {
-            Ok(_) => Ok(key),
-            Err(err) => Err(err.to_string()),
+    }
+
+    /// Makes the written data durable, called before the process exits.
+    pub async fn flush(&self) -> Result<(), String> {
+        self.blocking(|db| db.flush()).await
+    }
+
+    /// Writes, reads back and deletes a probe record.
+    pub async fn check_read_write(&self) -> Result<(), String> {
+        let probe = Uuid::new_v4().to_string();
+        let _writes = self.writes.lock(HEALTH_CHECK_KEY).await;
+        self.blocking(move |db| {
+            if let Err(err) = db.put(HEALTH_CHECK_KEY, probe.as_bytes()) {
+                return Err(format!("cant write {err}"));
+            }
+            match db.get(HEALTH_CHECK_KEY) {
+                Ok(Some(value)) if value == probe.as_bytes() => {}
+                Ok(_) => return Err("read value differs from the written one".to_string()),
+                Err(err) => return Err(format!("cant read {err}")),
+            }
+            db.delete(HEALTH_CHECK_KEY)
+                .map_err(|err| format!("cant delete {err}"))
+        })
+        .await
+    }
+
+    /// Approximate size of the database.
+    pub async fn size_bytes(&self) -> Result<u64, String> {
+        self.blocking(|db| db.size_bytes()).await
+    }
+
+    /// Every task at once, for the offline tools. The bot walks them with `task_pages` instead.
+    pub async fn list_tasks(&self) -> Result<HashMap<String, HashMap<String, String>>, String> {
+        let mut tasks = HashMap::new();
+        let mut pages = self.task_pages();
+        while let Some(page) = pages.next_page().await? {
+            tasks.extend(page);
         }
+        Ok(tasks)
     }
 
-    pub async fn delete_task_by_id(&self, task_id: String) -> Result<String, String> {
-        match self.inner.lock().await.delete(task_id.clone()) {
-            Ok(()) => Ok(task_id),
+    /// All tasks in id order, `TASK_PAGE_SIZE` records at a time.
+    pub fn task_pages(&self) -> TaskPages<'_> {
+        TaskPages::new(self)
+    }
+
+    /// Up to `TASK_PAGE_SIZE` tasks after the `after` key.
+    pub async fn task_page(&self, after: Option<String>) -> Result<TaskScan, String> {
+        self.blocking(move |db| scan_tasks(db, after.as_deref(), TASK_PAGE_SIZE, false))
+            .await
+    }
+
+    pub async fn get_task(&self, task_id: &str) -> Result<Option<HashMap<String, String>>, String> {
+        let key = task_id.to_string();
+        match self.blocking(move |db| db.get(key)).await {
+            Ok(Some(value)) => match decode_task(&value) {
+                Ok(task) => Ok(Some(task.data)),
+                Err(err) => Err(format!("cant decode task {task_id} {err}")),
+            },
+            Ok(None) => Ok(None),
             Err(err) => Err(err.to_string()),
         }
     }
 
-    pub async fn list_tasks(&self) -> Result<HashMap<String, HashMap<String, String>>, String> {
-        let mut results: HashMap<String, HashMap<String, String>> = HashMap::new();
-        for r in self
-            .inner
-            .lock()
+    /// Overwrites an existing task as is, without the quota check of `create_task`.
+    pub async fn put_task(
+        &self,
+        task_id: &str,
+        data: &HashMap<String, String>,
+    ) -> Result<(), String> {
+        let data_slice = encode_task(data)?;
+        let task_id = task_id.to_string();
+        let active = active_type(Some(data));
+        let data = data.clone();
+        let _writes = self.writes.lock(&task_id).await;
+        let previous = self
+            .blocking(move |db| {
+                let mut batch = Batch::default();
+                batch.put(&task_id, data_slice);
+                let previous = stored_task(db, &task_id)?;
+                index_task(&mut batch, &task_id, previous.as_ref(), Some(&data));
+                match db.write(batch) {
+                    Ok(()) => Ok(active_type(previous.as_ref())),
+                    Err(err) => Err(err.to_string()),
+                }
+            })
+            .await?;
+        self.active_tasks.shift(previous, active);
+        Ok(())
+    }
+
+    /// Rewrites a task its owner changed. A removed `expires_at` is computed again and the snapshot is dropped, so
+    /// the next notification is not a diff against what the old filters found.
+    pub async fn update_task(
+        &self,
+        task_id: &str,
+        mut data: HashMap<String, String>,
+    ) -> Result<(), String> {
+        self.fill_expiry(&mut data).await?;
+        let data_slice = encode_task(&data)?;
+        let task_id = task_id.to_string();
+        let active = active_type(Some(&data));
+        let _writes = self.writes.lock(&task_id).await;
+        let previous = self
+            .blocking(move |db| {
+                let mut batch = Batch::default();
+                batch.put(&task_id, data_slice);
+                batch.delete(format!("{SNAPSHOT_PREFIX}{task_id}"));
+                let previous = stored_task(db, &task_id)?;
+                index_task(&mut batch, &task_id, previous.as_ref(), Some(&data));
+                db.write(batch)?;
+                Ok(active_type(previous.as_ref()))
+            })
+            .await?;
+        self.active_tasks.shift(previous, active);
+        Ok(())
+    }
+
+    /// Tasks that notify the chat, ordered by id.
+    pub async fn list_chat_tasks(&self, chat_id: i64) -> Result<TaskList, String> {
+        self.indexed_tasks(chat_index(&chat_id.to_string()), None)
             .await
-            .iterator(IteratorMode::Start)
-            .collect::<Vec<_>>()
-        {
-            match r {
-                Ok(r) => {
-                    let key = String::from_utf8(r.0.to_vec());
-                    if key.is_err() {
-                        return Err(format!("cant decode key {:?}", key));
-                    }
-                    let value =
-                        serde_json::from_slice::<HashMap<String, String>>(r.1.to_vec().as_ref());
-                    if value.is_err() {
-                        return Err(format!("cant decode value {:?}", key));
-                    }
-                    results.insert(key.unwrap(), value.unwrap());
+    }
+
+    /// Tasks watching the same route on the same date.
+    pub async fn list_route_tasks(
+        &self,
+        from_point_code: &str,
+        to_point_code: &str,
+        date: &str,
+    ) -> Result<TaskList, String> {
+        self.indexed_tasks(route_index(from_point_code, to_point_code, date), None)
+            .await
+    }
+
+    /// Tasks with `next_poll_at` up to `now`, the ones that were never polled come first.
+    pub async fn list_due_tasks(&self, now: u64) -> Result<TaskList, String> {
+        self.indexed_tasks(format!("{INDEX_PREFIX}due:"), Some(due_index(now + 1)))
+            .await
+    }
+
+    /// Stores when the poller should check the task next, `at` is a unix timestamp.
+    pub async fn schedule_poll(&self, task_id: &str, at: u64) -> Result<(), String> {
+        let task_id = task_id.to_string();
+        let _writes = self.writes.lock(&task_id).await;
+        self.blocking(move |db| {
+            let previous = match stored_task(db, &task_id)? {
+                Some(task) => task,
+                None => return Ok(()),
+            };
+            let mut task = previous.clone();
+            task.insert("next_poll_at".to_string(), at.to_string());
+            let mut batch = Batch::default();
+            batch.put(&task_id, encode_task(&task)?);
+            index_task(&mut batch, &task_id, Some(&previous), Some(&task));
+            db.write(batch).map_err(|err| err.to_string())
+        })
+        .await
+    }
+
+    /// Drops every index entry and writes them anew from the stored tasks, returns the number of entries.
+    /// Run at startup, indexes of records changed outside of `RZDDb` are stale until then.
+    pub async fn rebuild_indexes(&self) -> Result<usize, String> {
+        let _writes = self.writes.lock_all().await;
+        self.blocking(|db| {
+            let mut batch = Batch::default();
+            for r in db.scan(INDEX_PREFIX.as_bytes()) {
+                let (key, _) = r.map_err(|err| format!("cant iterate over indexes {err}"))?;
+                if !key.starts_with(INDEX_PREFIX.as_bytes()) {
+                    break;
+                }
+                batch.delete(key);
+            }
+            let mut entries = 0;
+            for (task_id, task) in scan_tasks(db, None, usize::MAX, false)?.tasks {
+                for key in index_keys(&task_id, &task) {
+                    batch.put(key, b"");
+                    entries += 1;
+                }
+            }
+            db.write(batch)
+                .map_err(|err| format!("cant write indexes {err}"))?;
+            Ok(entries)
+        })
+        .await
+    }
+
+    /// Rewrites tasks stored in older versions and quarantines the undecodable ones, run once at startup.
+    /// Counts the active tasks from the ones it read.
+    pub async fn migrate_tasks(&self) -> Result<TaskScan, String> {
+        let _writes = self.writes.lock_all().await;
+        let scan = self
+            .blocking(|db| scan_tasks(db, None, usize::MAX, true))
+            .await?;
+        self.active_tasks.reset(&scan.tasks);
+        Ok(scan)
+    }
+
+    /// Tasks the poller checks by type, as counted since `migrate_tasks`.
+    pub fn active_tasks(&self) -> BTreeMap<String, f64> {
+        self.active_tasks.0.lock().unwrap().clone()
+    }
+
+    /// Raw keys and values starting with `prefix`, in key order.
+    pub async fn dump(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let prefix = prefix.to_string();
+        self.blocking(move |db| prefixed(db, &prefix)).await
+    }
+
+    pub async fn count_user_tasks(&self, user_id: u64) -> Result<usize, String> {
+        self.blocking(move |db| count_owned_tasks(db, user_id))
+            .await
+    }
+
+    /// Tasks the user created in any chat, ordered by id.
+    pub async fn list_user_tasks(&self, user_id: u64) -> Result<TaskList, String> {
+        self.indexed_tasks(owner_index(&user_id.to_string()), None)
+            .await
+    }
+
+    async fn indexed_tasks(
+        &self,
+        prefix: String,
+        until: Option<String>,
+    ) -> Result<TaskList, String> {
+        self.blocking(move |db| indexed_tasks(db, &prefix, until.as_deref()))
+            .await
+    }
+
+    /// Default limits with the admin overrides of the user applied.
+    pub async fn user_limits(&self, user_id: u64) -> Result<Limits, String> {
+        Ok(self
+            .limits
+            .with_overrides(&self.get_limit_overrides(user_id).await?))
+    }
+
+    pub async fn get_limit_overrides(&self, user_id: u64) -> Result<LimitOverrides, String> {
+        Ok(self
+            .get_value(user_key(user_id, "limits"))
+            .await?
+            .unwrap_or_default())
+    }
+
+    pub async fn set_limit_overrides(
+        &self,
+        user_id: u64,
+        overrides: &LimitOverrides,
+    ) -> Result<(), String> {
+        self.put_value(user_key(user_id, "limits"), overrides).await
+    }
+
+    /// Counts a train search of the user. Returns the seconds to wait instead if the user
+    /// already searched as many times as allowed during the last minute.
+    pub async fn register_search(&self, user_id: u64) -> Result<Option<i64>, String> {
+        let limit = self.user_limits(user_id).await?.searches_per_minute;
+        if limit == 0 {
+            return Ok(None);
+        }
+        let key = user_key(user_id, "searches");
+        let _writes = self.writes.lock(&key).await;
+        let now = Local::now().timestamp();
+        let mut searches: Vec<i64> = self
+            .get_value(key.clone())
+            .await?
+            .unwrap_or_default();
+        searches.retain(|at| now - at < SEARCH_WINDOW_SECS);
+        if searches.len() >= limit {
+            return Ok(Some(SEARCH_WINDOW_SECS - (now - searches[0])));
+        }
+        searches.push(now);
+        self.put_value(key, &searches).await?;
+        Ok(None)
+    }
+
+    /// Users who have stored settings, favourites or recent routes.
+    pub async fn list_user_ids(&self) -> Result<BTreeSet<u64>, String> {
+        self.blocking(|db| {
+            let mut user_ids = BTreeSet::new();
+            for r in db.scan(USER_PREFIX.as_bytes()) {
+                let (key, _) = r.map_err(|err| format!("cant iterate over users {err}"))?;
+                let key = match key.strip_prefix(USER_PREFIX.as_bytes()) {
+                    Some(key) => String::from_utf8_lossy(key).to_string(),
+                    None => break,
+                };
+                if let Some(user_id) = key.split(':').next().and_then(|id| id.parse().ok()) {
+                    user_ids.insert(user_id);
+                }
+            }
+            Ok(user_ids)
+        })
+        .await
+    }
+
+    pub async fn remember_stations(&self, stations: &[GetRZDPointCodes]) -> Result<(), String> {
+        let mut batch = Batch::default();
+        for station in stations {
+            let data_slice = serde_json::to_vec(station);
+            if data_slice.is_err() {
+                return Err(format!("cant serialize station {:?}", data_slice));
+            }
+            batch.put(
+                format!("{STATION_PREFIX}{}", station.code),
+                data_slice.unwrap(),
+            );
+        }
+        self.blocking(move |db| db.write(batch)).await
+    }
+
+    pub async fn get_station(&self, code: &str) -> Result<Option<GetRZDPointCodes>, String> {
+        self.get_value(format!("{STATION_PREFIX}{code}")).await
+    }
+
+    pub async fn list_favourite_stations(
+        &self,
+        user_id: u64,
+    ) -> Result<Vec<GetRZDPointCodes>, String> {
+        Ok(self
+            .get_value(user_key(user_id, "favourites"))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Adds the station to the user's favourites or removes it if it is already there.
+    /// Returns `true` if the station is a favourite after the call.
+    pub async fn toggle_favourite_station(&self, user_id: u64, code: &str) -> Result<bool, String> {
+        let _writes = self.writes.lock(&user_key(user_id, "favourites")).await;
+        let mut favourites = self.list_favourite_stations(user_id).await?;
+        let added = if let Some(idx) = favourites.iter().position(|s| s.code == code) {
+            favourites.remove(idx);
+            false
+        } else {
+            match self.get_station(code).await? {
+                Some(station) => favourites.insert(0, station),
+                None => return Err(format!("unknown station code {code}")),
+            }
+            favourites.truncate(FAVOURITE_STATIONS_LIMIT);
+            true
+        };
+        self.put_value(user_key(user_id, "favourites"), &favourites)
+            .await?;
+        Ok(added)
+    }
+
+    pub async fn list_recent_routes(&self, user_id: u64) -> Result<Vec<RecentRoute>, String> {
+        Ok(self
+            .get_value(user_key(user_id, "recent_routes"))
+            .await?
+            .unwrap_or_default())
+    }
+
+    pub async fn push_recent_route(
+        &self,
+        user_id: u64,
+        from_point_code: &str,
+        to_point_code: &str,
+    ) -> Result<(), String> {
+        let (from, to) = match (
+            self.get_station(from_point_code).await?,
+            self.get_station(to_point_code).await?,
+        ) {
+            (Some(from), Some(to)) => (from, to),
+            // Stations picked before the cache existed, nothing to show for them
+            _ => return Ok(()),
+        };
+        let _writes = self.writes.lock(&user_key(user_id, "recent_routes")).await;
+        let mut routes = self.list_recent_routes(user_id).await?;
+        routes.retain(|r| !(r.from.code == from.code && r.to.code == to.code));
+        routes.insert(0, RecentRoute { from, to });
+        routes.truncate(RECENT_ROUTES_LIMIT);
+        self.put_value(user_key(user_id, "recent_routes"), &routes)
+            .await
+    }
+
+    /// Language chosen with `/lang`, `None` if the user never picked one.
+    pub async fn get_user_lang(&self, user_id: u64) -> Result<Option<Lang>, String> {
+        self.get_value(user_key(user_id, "lang")).await
+    }
+
+    pub async fn set_user_lang(&self, user_id: u64, lang: Lang) -> Result<(), String> {
+        self.put_value(user_key(user_id, "lang"), &lang).await
+    }
+
+    /// Days the new tasks of the user are watched at most, `None` watches them until the travel date.
+    pub async fn get_task_ttl(&self, user_id: u64) -> Result<Option<u32>, String> {
+        self.get_value(user_key(user_id, "ttl")).await
+    }
+
+    pub async fn set_task_ttl(&self, user_id: u64, days: Option<u32>) -> Result<(), String> {
+        match days {
+            Some(days) => self.put_value(user_key(user_id, "ttl"), &days).await,
+            None => self.delete_value(user_key(user_id, "ttl")).await,
+        }
+    }
+
+    /// Approved or banned, `None` if the user is not in the registry.
+    pub async fn get_access(&self, user_id: u64) -> Result<Option<AccessStatus>, String> {
+        self.get_value(user_key(user_id, "access")).await
+    }
+
+    pub async fn set_access(&self, user_id: u64, status: AccessStatus) -> Result<(), String> {
+        self.put_value(user_key(user_id, "access"), &status).await
+    }
+
+    /// Removes the user from the registry, neither approved nor banned after that.
+    pub async fn reset_access(&self, user_id: u64) -> Result<(), String> {
+        self.delete_value(user_key(user_id, "access")).await
+    }
+
+    /// One time invite code, the value keeps the admin who created it.
+    pub async fn create_invite(&self, created_by: u64) -> Result<String, String> {
+        let code = Uuid::new_v4().simple().to_string();
+        self.put_value(format!("{INVITE_PREFIX}{code}"), &created_by)
+            .await?;
+        Ok(code)
+    }
+
+    /// Deletes the code, returns `false` if it did not exist or was already used.
+    pub async fn redeem_invite(&self, code: &str) -> Result<bool, String> {
+        let key = format!("{INVITE_PREFIX}{code}");
+        let _writes = self.writes.lock(&key).await;
+        self.blocking(move |db| {
+            match db.get(&key) {
+                Ok(Some(_)) => {}
+                Ok(None) => return Ok(false),
+                Err(err) => return Err(err.to_string()),
+            }
+            match db.delete(&key) {
+                Ok(()) => Ok(true),
+                Err(err) => Err(err.to_string()),
+            }
+        })
+        .await
+    }
+
+    async fn get_value<T: for<'de> Deserialize<'de>>(
+        &self,
+        key: String,
+    ) -> Result<Option<T>, String> {
+        let value = self.blocking({
+            let key = key.clone();
+            move |db| db.get(key)
+        });
+        match value.await {
+            Ok(Some(value)) => match serde_json::from_slice::<T>(value.as_ref()) {
+                Ok(v) => Ok(Some(v)),
+                Err(err) => Err(format!("cant decode value {key} {err}")),
+            },
+            Ok(None) => Ok(None),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// Blind writes, callers that write what they computed from a stored value hold the lock of its key around
+    /// both.
+    async fn put_value<T: Serialize>(&self, key: String, value: &T) -> Result<(), String> {
+        let data_slice = encode(value)?;
+        match self.blocking(move |db| db.put(key, data_slice)).await {
+            Ok(()) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    async fn delete_value(&self, key: String) -> Result<(), String> {
+        match self.blocking(move |db| db.delete(key)).await {
+            Ok(()) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+/// Walks the tasks page by page, each page is read on its own so the whole table is never in memory.
+/// Tasks written during the walk may or may not show up.
+pub struct TaskPages<'a> {
+    store: &'a dyn TaskStore,
+    after: Option<String>,
+    done: bool,
+}
+
+impl<'a> TaskPages<'a> {
+    pub fn new(store: &'a dyn TaskStore) -> Self {
+        Self {
+            store,
+            after: None,
+            done: false,
+        }
+    }
+
+    /// `None` once the tasks are over. A page can come out short or even empty when some of its records
+    /// were skipped.
+    pub async fn next_page(&mut self) -> Result<Option<TaskList>, String> {
+        if self.done {
+            return Ok(None);
+        }
+        let scan = self.store.task_page(self.after.clone()).await?;
+        self.done = scan.next.is_none();
+        self.after = scan.next;
+        Ok(Some(scan.tasks))
+    }
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec(value).map_err(|err| format!("cant serialize data {err}"))
+}
+
+/// Keys and values starting with `prefix`, in key order.
+fn prefixed(db: &dyn Storage, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut entries = Vec::new();
+    for r in db.scan(prefix.as_bytes()) {
+        let (key, value) = r.map_err(|err| format!("cant iterate over keys {err}"))?;
+        if !key.starts_with(prefix.as_bytes()) {
+            break;
+        }
+        entries.push((String::from_utf8_lossy(&key).to_string(), value));
+    }
+    Ok(entries)
+}
+
+/// Tasks are stored under bare uuid keys, everything else has a prefix. Records that can't be decoded are skipped
+/// instead of failing the whole listing. With `upgrade`, which only `migrate_tasks` passes under all the write
+/// locks, they are moved to quarantine and the ones in older versions are rewritten. The changes are written
+/// together after the scan, a failed migration leaves every record as it was.
+/// Looks at up to `limit` task records with keys after `after`.
+fn scan_tasks(
+    db: &dyn Storage,
+    after: Option<&str>,
+    limit: usize,
+    upgrade: bool,
+) -> Result<TaskScan, String> {
+    let mut scan = TaskScan::default();
+    let mut batch = Batch::default();
+    let from = match after {
+        Some(after) => format!("{after}\0"),
+        None => String::new(),
+    };
+    let mut seen = 0;
+    let mut last = None;
+    for r in db.scan(from.as_bytes()) {
+        let (key, value) = r.map_err(|err| format!("cant iterate over tasks {err}"))?;
+        if key.as_slice() >= TASK_KEYS_END {
+            break;
+        }
+        // Prefixed records sorting among the tasks, e.g. `archive:<id>`
+        let key = match std::str::from_utf8(&key) {
+            Ok(key) if Uuid::parse_str(key).is_ok() => key.to_string(),
+            _ => continue,
+        };
+        if seen == limit {
+            scan.next = last;
+            break;
+        }
+        seen += 1;
+        last = Some(key.clone());
+        match decode_task(&value) {
+            Ok(task) => {
+                if task.upgraded && upgrade {
+                    batch.put(&key, encode_task(&task.data)?);
+                    scan.upgraded += 1;
                 }
-                Err(err) => return Err(format!("cant iterate over tasks {err}")),
+                scan.tasks.push((key, task.data));
+            }
+            Err(DecodeError::Newer(version)) => {
+                tracing::warn!(
+                    task_id = %key,
+                    version,
+                    "task is stored by a newer build, skipping it"
+                );
+                scan.newer += 1;
+            }
+            Err(DecodeError::Invalid(err)) if upgrade => {
+                tracing::error!(task_id = %key, error = %err, "cant decode task, moving it to quarantine");
+                quarantine(&mut batch, &key, &value, &err)?;
+                scan.quarantined += 1;
+            }
+            Err(DecodeError::Invalid(err)) => {
+                tracing::error!(task_id = %key, error = %err, "cant decode task, skipping it");
             }
         }
+    }
+    if batch.is_empty() {
+        return Ok(scan);
+    }
+    db.write(batch)
+        .map_err(|err| format!("cant migrate tasks {err}"))?;
+    Ok(scan)
+}
+
+/// Keeps the raw record under `quarantine:<key>` for a manual look and removes it from the tasks.
+fn quarantine(batch: &mut Batch, key: &str, value: &[u8], error: &str) -> Result<(), String> {
+    let record = QuarantinedRecord {
+        key: key.to_string(),
+        value: String::from_utf8_lossy(value).to_string(),
+        error: error.to_string(),
+        at: Local::now().to_rfc3339(),
+    };
+    batch.put(format!("{QUARANTINE_PREFIX}{key}"), encode(&record)?);
+    batch.delete(key);
+    Ok(())
+}
+
+fn exists(db: &dyn Storage, key: &str) -> Result<bool, String> {
+    Ok(db.get(key)?.is_some())
+}
+
+/// Counts the owner index entries without reading the tasks, the index is written together with them.
+fn count_owned_tasks(db: &dyn Storage, user_id: u64) -> Result<usize, String> {
+    let prefix = owner_index(&user_id.to_string());
+    let mut count = 0;
+    for r in db.scan(prefix.as_bytes()) {
+        let (key, _) = r.map_err(|err| format!("cant iterate over index {prefix} {err}"))?;
+        if !key.starts_with(prefix.as_bytes()) {
+            break;
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Index entries of a task, a task without `next_poll_at` is due right away. Paused tasks are never due, resuming
+/// one puts it back at its `next_poll_at`.
+fn index_keys(task_id: &str, task: &HashMap<String, String>) -> Vec<String> {
+    let field = |name: &str| task.get(name).map(String::as_str);
+    let mut keys = Vec::new();
+    if let Some(chat_id) = field("chat_id") {
+        keys.push(format!("{}{task_id}", chat_index(chat_id)));
+    }
+    if let Some(user_id) = field("user_id") {
+        keys.push(format!("{}{task_id}", owner_index(user_id)));
+    }
+    if let (Some(from), Some(to), Some(date)) = (
+        field("from_point_code"),
+        field("to_point_code"),
+        field("date"),
+    ) {
+        keys.push(format!("{}{task_id}", route_index(from, to, date)));
+    }
+    if !is_paused(task) {
+        let next_poll_at = field("next_poll_at")
+            .and_then(|at| at.parse().ok())
+            .unwrap_or_default();
+        keys.push(format!("{}{task_id}", due_index(next_poll_at)));
+    }
+    keys
+}
+
+/// Moves the index entries of the task from its `previous` version to the new one in the same batch
+/// that writes the task, `None` stands for a missing task.
+fn index_task(
+    batch: &mut Batch,
+    task_id: &str,
+    previous: Option<&HashMap<String, String>>,
+    task: Option<&HashMap<String, String>>,
+) {
+    let previous = previous.map_or_else(Vec::new, |previous| index_keys(task_id, previous));
+    let current = task.map_or_else(Vec::new, |task| index_keys(task_id, task));
+    for key in previous.iter().filter(|key| !current.contains(key)) {
+        batch.delete(key);
+    }
+    for key in current.iter().filter(|key| !previous.contains(key)) {
+        batch.put(key, b"");
+    }
+}
+
+/// The stored task, undecodable ones have no index entries and count as missing.
+fn stored_task(db: &dyn Storage, task_id: &str) -> Result<Option<HashMap<String, String>>, String> {
+    match db.get(task_id) {
+        Ok(Some(value)) => Ok(decode_task(&value).ok().map(|task| task.data)),
+        Ok(None) => Ok(None),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Tasks the index entries starting with `prefix` point to, up to the `until` key. Entries of tasks that are
+/// gone, e.g. quarantined, are skipped.
+fn indexed_tasks(db: &dyn Storage, prefix: &str, until: Option<&str>) -> Result<TaskList, String> {
+    let mut tasks = Vec::new();
+    for r in db.scan(prefix.as_bytes()) {
+        let (key, _) = r.map_err(|err| format!("cant iterate over index {prefix} {err}"))?;
+        if !key.starts_with(prefix.as_bytes())
+            || until.is_some_and(|until| key.as_slice() >= until.as_bytes())
+        {
+            break;
+        }
+        let key = String::from_utf8_lossy(&key);
+        let task_id = key.rsplit(':').next().unwrap_or_default();
+        if let Some(task) = stored_task(db, task_id)? {
+            tasks.push((task_id.to_string(), task));
+        }
+    }
+    Ok(tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::storage::{Entries, MemoryStorage};
+
+    const TASK_ID: &str = "0b6c2a52-1d3e-4c8e-9f0a-4a1f3b2c5d6e";
+    const OTHER_TASK_ID: &str = "7f1e9d4c-2b3a-4e5f-8a6b-9c0d1e2f3a4b";
+
+    struct TestDb {
+        rzd_db: Arc<RZDDb>,
+    }
+
+    impl TestDb {
+        /// In-memory database holding the given raw records.
+        fn with_records(records: &[(&str, &str)]) -> Self {
+            Self::with_storage(Arc::<MemoryStorage>::default(), records)
+        }
+
+        fn with_storage(storage: Arc<dyn Storage>, records: &[(&str, &str)]) -> Self {
+            for (key, value) in records {
+                storage.put(key, value).unwrap();
+            }
+            let limits = Limits {
+                max_tasks: 0,
+                searches_per_minute: 0,
+                min_poll_interval: Duration::from_secs(60),
+            };
+            Self {
+                rzd_db: RZDDb::new(storage, limits),
+            }
+        }
+
+        fn db(&self) -> &RZDDb {
+            &self.rzd_db
+        }
+
+        async fn raw(&self, key: &str) -> Option<String> {
+            self.db()
+                .dump(key)
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|(stored_key, _)| stored_key == key)
+                .map(|(_, value)| String::from_utf8(value).unwrap())
+        }
+    }
+
+    /// Fails every write once frozen.
+    #[derive(Default)]
+    struct FreezableStorage {
+        inner: MemoryStorage,
+        frozen: Arc<AtomicBool>,
+    }
+
+    impl Storage for FreezableStorage {
+        fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+            self.inner.read(key)
+        }
+
+        fn write(&self, batch: Batch) -> Result<(), String> {
+            if self.frozen.load(Ordering::SeqCst) {
+                return Err("storage is frozen".to_string());
+            }
+            self.inner.write(batch)
+        }
+
+        fn scan(&self, from: &[u8]) -> Entries<'_> {
+            self.inner.scan(from)
+        }
+
+        fn size_bytes(&self) -> Result<u64, String> {
+            self.inner.size_bytes()
+        }
+    }
+
+    fn task(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn day_task() -> HashMap<String, String> {
+        task(&[
+            ("type", "day"),
+            ("from_point_code", "2000000"),
+            ("to_point_code", "2004000"),
+            ("date", "21.10.2026"),
+            ("car_types", "купе"),
+            ("chat_id", "42"),
+            ("user_id", "42"),
+        ])
+    }
+
+    const CURRENT_DAY_TASK: &str = r#"{"version":1,"data":{"type":"day","from_point_code":"2000000","to_point_code":"2004000","date":"21.10.2026","car_types":"купе","chat_id":"42","user_id":"42"}}"#;
+    const UNVERSIONED_DAY_TASK: &str = r#"{"type":"day","from_point_code":"2000000","to_point_code":"2004000","date":"21.10.2026","car_types":"купе","chat_id":"42","user_id":"42"}"#;
+
+    #[tokio::test]
+    async fn reads_tasks_written_in_the_current_format() {
+        let test_db = TestDb::with_records(&[
+            (TASK_ID, CURRENT_DAY_TASK),
+            ("snapshot:0b6c2a52-1d3e-4c8e-9f0a-4a1f3b2c5d6e", r#"["1"]"#),
+            ("user:42:lang", r#""ru""#),
+        ]);
+
+        let tasks = test_db.db().list_tasks().await.unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[TASK_ID], day_task());
+        assert_eq!(
+            test_db.db().get_task(TASK_ID).await.unwrap(),
+            Some(day_task())
+        );
+
+        let scan = test_db.db().migrate_tasks().await.unwrap();
+        assert_eq!((scan.upgraded, scan.quarantined, scan.newer), (0, 0, 0));
+        assert_eq!(test_db.raw(TASK_ID).await.unwrap(), CURRENT_DAY_TASK);
+    }
+
+    #[tokio::test]
+    async fn writes_tasks_in_the_current_format() {
+        let test_db = TestDb::with_records(&[]);
+
+        let task_id = test_db.db().create_task(day_task()).await.unwrap();
+        let stored: serde_json::Value =
+            serde_json::from_str(&test_db.raw(&task_id).await.unwrap()).unwrap();
+        let mut expected: serde_json::Value = serde_json::from_str(CURRENT_DAY_TASK).unwrap();
+        expected["data"]["expires_at"] = "2026-10-21T23:59:59+03:00".into();
+        assert_eq!(stored, expected);
+    }
+
+    #[tokio::test]
+    async fn archives_expired_tasks() {
+        let test_db = TestDb::with_records(&[
+            (TASK_ID, CURRENT_DAY_TASK),
+            ("snapshot:0b6c2a52-1d3e-4c8e-9f0a-4a1f3b2c5d6e", r#"["1"]"#),
+        ]);
+
+        test_db.db().expire_task(TASK_ID, true).await.unwrap();
+        assert!(test_db.db().list_tasks().await.unwrap().is_empty());
+        assert!(test_db.db().get_snapshot(TASK_ID).await.unwrap().is_none());
+        let archived = decode_task(
+            test_db
+                .raw(&format!("{ARCHIVE_PREFIX}{TASK_ID}"))
+                .await
+                .unwrap()
+                .as_bytes(),
+        )
+        .unwrap()
+        .data;
+        assert_eq!(archived["date"], "21.10.2026");
+        assert!(archived.contains_key("expired_at"));
+    }
+
+    fn ids(tasks: TaskList) -> Vec<String> {
+        tasks.into_iter().map(|(task_id, _)| task_id).collect()
+    }
+
+    #[tokio::test]
+    async fn keeps_indexes_in_step_with_tasks() {
+        let test_db = TestDb::with_records(&[]);
+        let db = test_db.db();
+
+        let task_id = db.create_task(day_task()).await.unwrap();
+        assert_eq!(
+            ids(db.list_chat_tasks(42).await.unwrap()),
+            vec![task_id.clone()]
+        );
+        assert_eq!(db.count_user_tasks(42).await.unwrap(), 1);
+        assert_eq!(
+            ids(db
+                .list_route_tasks("2000000", "2004000", "21.10.2026")
+                .await
+                .unwrap()),
+            vec![task_id.clone()]
+        );
+        assert_eq!(
+            ids(db.list_due_tasks(0).await.unwrap()),
+            vec![task_id.clone()]
+        );
+
+        db.schedule_poll(&task_id, 100).await.unwrap();
+        assert!(db.list_due_tasks(99).await.unwrap().is_empty());
+        assert_eq!(
+            ids(db.list_due_tasks(100).await.unwrap()),
+            vec![task_id.clone()]
+        );
+
+        let mut paused = db.get_task(&task_id).await.unwrap().unwrap();
+        paused.insert("status".to_string(), "paused".to_string());
+        db.put_task(&task_id, &paused).await.unwrap();
+        assert!(db.list_due_tasks(100).await.unwrap().is_empty());
+        paused.insert("status".to_string(), "active".to_string());
+        db.put_task(&task_id, &paused).await.unwrap();
+        assert_eq!(
+            ids(db.list_due_tasks(100).await.unwrap()),
+            vec![task_id.clone()]
+        );
+
+        let mut moved = db.get_task(&task_id).await.unwrap().unwrap();
+        moved.insert("date".to_string(), "22.10.2026".to_string());
+        moved.insert("chat_id".to_string(), "-7".to_string());
+        db.put_task(&task_id, &moved).await.unwrap();
+        assert!(db.list_chat_tasks(42).await.unwrap().is_empty());
+        assert_eq!(
+            ids(db.list_chat_tasks(-7).await.unwrap()),
+            vec![task_id.clone()]
+        );
+        assert!(db
+            .list_route_tasks("2000000", "2004000", "21.10.2026")
+            .await
+            .unwrap()
+            .is_empty());
+
+        db.delete_task_by_id(task_id).await.unwrap();
+        assert!(db.dump(INDEX_PREFIX).await.unwrap().is_empty());
+        assert_eq!(db.count_user_tasks(42).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn rebuilds_indexes() {
+        let test_db = TestDb::with_records(&[
+            (TASK_ID, CURRENT_DAY_TASK),
+            ("index:chat:1:7f1e9d4c-2b3a-4e5f-8a6b-9c0d1e2f3a4b", ""),
+        ]);
+        let db = test_db.db();
+        assert!(db.list_chat_tasks(1).await.unwrap().is_empty());
+
+        assert_eq!(db.rebuild_indexes().await.unwrap(), 4);
+        assert_eq!(ids(db.list_chat_tasks(42).await.unwrap()), vec![TASK_ID]);
+    }
+
+    #[tokio::test]
+    async fn counts_active_tasks() {
+        let test_db = TestDb::with_records(&[(TASK_ID, CURRENT_DAY_TASK)]);
+        let db = test_db.db();
+        let counts = |pairs: &[(&str, f64)]| {
+            pairs
+                .iter()
+                .map(|(task_type, count)| (task_type.to_string(), *count))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        db.migrate_tasks().await.unwrap();
+        assert_eq!(db.active_tasks(), counts(&[("day", 1.0)]));
+
+        let mut train = day_task();
+        train.insert("type".to_string(), "train".to_string());
+        let task_id = db.create_task(train).await.unwrap();
+        assert_eq!(db.active_tasks(), counts(&[("day", 1.0), ("train", 1.0)]));
+
+        let mut paused = db.get_task(&task_id).await.unwrap().unwrap();
+        paused.insert("status".to_string(), "paused".to_string());
+        db.put_task(&task_id, &paused).await.unwrap();
+        assert_eq!(db.active_tasks(), counts(&[("day", 1.0), ("train", 0.0)]));
+        db.delete_task_by_id(task_id).await.unwrap();
+        assert_eq!(db.active_tasks(), counts(&[("day", 1.0), ("train", 0.0)]));
+
+        db.expire_task(TASK_ID, false).await.unwrap();
+        assert_eq!(db.active_tasks(), counts(&[("day", 0.0), ("train", 0.0)]));
+    }
+
+    fn sent(message_id: i32) -> SentNotification {
+        SentNotification {
+            sent_at: "2026-10-18T12:00:00+03:00".to_string(),
+            seats: vec![FoundSeats {
+                train: "016А".to_string(),
+                car: None,
+                seats: 3,
+            }],
+            added: 1,
+            removed: 0,
+            message_id,
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_a_limited_notification_history() {
+        let test_db = TestDb::with_records(&[(TASK_ID, CURRENT_DAY_TASK)]);
+        let db = test_db.db();
+
+        for message_id in 0..NOTIFICATION_HISTORY_LIMIT as i32 + 2 {
+            db.record_notification(TASK_ID, &sent(message_id))
+                .await
+                .unwrap();
+        }
+        let history = db.list_notification_history(TASK_ID).await.unwrap();
+        assert_eq!(history.len(), NOTIFICATION_HISTORY_LIMIT);
+        assert_eq!(history[0].message_id, NOTIFICATION_HISTORY_LIMIT as i32 + 1);
+        assert_eq!(history.last().unwrap().message_id, 2);
+        let prefix = history_prefix(TASK_ID);
+        assert!(test_db
+            .raw(&history_key(&prefix, NOTIFICATION_HISTORY_LIMIT as u64 + 1))
+            .await
+            .is_some());
+
+        db.delete_task_by_id(TASK_ID.to_string()).await.unwrap();
+        assert!(db
+            .list_notification_history(TASK_ID)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn continues_a_history_keyed_by_time() {
+        let legacy = serde_json::to_string(&sent(1)).unwrap();
+        let test_db = TestDb::with_records(&[
+            (TASK_ID, CURRENT_DAY_TASK),
+            (
+                "history:0b6c2a52-1d3e-4c8e-9f0a-4a1f3b2c5d6e:00000001792314000000",
+                &legacy,
+            ),
+        ]);
+        let db = test_db.db();
+
+        db.record_notification(TASK_ID, &sent(2)).await.unwrap();
+        let history = db.list_notification_history(TASK_ID).await.unwrap();
+        let message_ids: Vec<i32> = history.iter().map(|sent| sent.message_id).collect();
+        assert_eq!(message_ids, vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn does_not_bring_back_deleted_tasks() {
+        let test_db = TestDb::with_records(&[(TASK_ID, CURRENT_DAY_TASK)]);
+        let db = test_db.db();
+        let notification = PendingNotification {
+            chat_id: 1,
+            text: "places".to_string(),
+            task_type: "day".to_string(),
+            attempts: 0,
+            seats: vec![],
+            added: 1,
+            removed: 0,
+        };
+
+        db.delete_task_by_id(TASK_ID.to_string()).await.unwrap();
+        db.put_snapshot(TASK_ID, &["016А".to_string()])
+            .await
+            .unwrap();
+        db.queue_notification(TASK_ID, &["016А".to_string()], &notification)
+            .await
+            .unwrap();
+        db.put_pending_notification(TASK_ID, &notification)
+            .await
+            .unwrap();
+        db.record_notification(TASK_ID, &sent(1)).await.unwrap();
+
+        for prefix in [SNAPSHOT_PREFIX, NOTIFIED_PREFIX, NOTIFICATION_PREFIX] {
+            assert!(test_db.raw(&format!("{prefix}{TASK_ID}")).await.is_none());
+        }
+        assert!(db
+            .list_notification_history(TASK_ID)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn upgrades_unversioned_tasks() {
+        let test_db = TestDb::with_records(&[(TASK_ID, UNVERSIONED_DAY_TASK)]);
+
+        assert_eq!(
+            test_db.db().list_tasks().await.unwrap()[TASK_ID],
+            day_task()
+        );
+        let scan = test_db.db().migrate_tasks().await.unwrap();
+        assert_eq!(scan.upgraded, 1);
+        let stored: serde_json::Value =
+            serde_json::from_str(&test_db.raw(TASK_ID).await.unwrap()).unwrap();
+        assert_eq!(stored["version"], 1);
+        assert_eq!(
+            test_db.db().get_task(TASK_ID).await.unwrap(),
+            Some(day_task())
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_migration_leaves_every_record_as_it_was() {
+        let storage = FreezableStorage::default();
+        let frozen = storage.frozen.clone();
+        let test_db = TestDb::with_storage(
+            Arc::new(storage),
+            &[
+                (TASK_ID, UNVERSIONED_DAY_TASK),
+                (OTHER_TASK_ID, r#"{"type":"day","seats":3}"#),
+            ],
+        );
+        frozen.store(true, Ordering::SeqCst);
+
+        assert!(test_db.db().migrate_tasks().await.is_err());
+        assert_eq!(
+            test_db.raw(TASK_ID).await.as_deref(),
+            Some(UNVERSIONED_DAY_TASK)
+        );
+        assert_eq!(
+            test_db.raw(OTHER_TASK_ID).await.as_deref(),
+            Some(r#"{"type":"day","seats":3}"#)
+        );
+        assert!(test_db
+            .raw(&format!("{QUARANTINE_PREFIX}{OTHER_TASK_ID}"))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn quarantines_undecodable_tasks() {
+        let test_db = TestDb::with_records(&[
+            (TASK_ID, CURRENT_DAY_TASK),
+            (OTHER_TASK_ID, r#"{"type":"day","seats":3}"#),
+        ]);
+
+        let db = test_db.db();
+
+        let tasks = db.list_tasks().await.unwrap();
+        assert_eq!(tasks.keys().collect::<Vec<_>>(), vec![TASK_ID]);
+        assert!(test_db.raw(OTHER_TASK_ID).await.is_some());
+
+        let scan = db.migrate_tasks().await.unwrap();
+        assert_eq!(scan.quarantined, 1);
+        assert!(test_db.raw(OTHER_TASK_ID).await.is_none());
+        let quarantined: QuarantinedRecord = serde_json::from_str(
+            &test_db
+                .raw(&format!("{QUARANTINE_PREFIX}{OTHER_TASK_ID}"))
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(quarantined.key, OTHER_TASK_ID);
+        assert_eq!(quarantined.value, r#"{"type":"day","seats":3}"#);
+    }
+
+    #[tokio::test]
+    async fn keeps_tasks_of_a_newer_version() {
+        let newer = r#"{"version":99,"data":{"type":"day"}}"#;
+        let test_db = TestDb::with_records(&[(TASK_ID, newer)]);
+
+        let scan = test_db.db().migrate_tasks().await.unwrap();
+        assert!(scan.tasks.is_empty());
+        assert_eq!(scan.newer, 1);
+        assert_eq!(test_db.raw(TASK_ID).await.unwrap(), newer);
+    }
+
+    #[tokio::test]
+    async fn walks_tasks_in_pages() {
+        let broken_task_id = "3c9d8e7f-6a5b-4c3d-9e2f-1a0b9c8d7e6f";
+        let archived = format!("{ARCHIVE_PREFIX}{TASK_ID}");
+        let test_db = TestDb::with_records(&[
+            (TASK_ID, CURRENT_DAY_TASK),
+            (broken_task_id, "{"),
+            (OTHER_TASK_ID, UNVERSIONED_DAY_TASK),
+            (&archived, CURRENT_DAY_TASK),
+            ("user:42:lang", r#""ru""#),
+        ]);
+
+        let mut after: Option<String> = None;
+        let mut pages = Vec::new();
+        loop {
+            let scan = scan_tasks(&*test_db.db().db, after.as_deref(), 1, false).unwrap();
+            pages.push(
+                scan.tasks
+                    .into_iter()
+                    .map(|(task_id, _)| task_id)
+                    .collect::<Vec<_>>(),
+            );
+            after = scan.next;
+            if after.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, vec![vec![TASK_ID], vec![], vec![OTHER_TASK_ID]]);
+        assert!(test_db.raw(broken_task_id).await.is_some());
+
+        let mut task_ids = Vec::new();
+        let store: &dyn TaskStore = test_db.db();
+        let mut pages = store.task_pages();
+        while let Some(page) = pages.next_page().await.unwrap() {
+            task_ids.extend(page.into_iter().map(|(task_id, _)| task_id));
+        }
+        assert_eq!(task_ids, vec![TASK_ID, OTHER_TASK_ID]);
+    }
+
+    #[tokio::test]
+    async fn reads_do_not_wait_for_writes() {
+        let test_db = TestDb::with_records(&[(TASK_ID, CURRENT_DAY_TASK)]);
+
+        let _writes = test_db.db().writes.lock_all().await;
+        let read = tokio::time::timeout(Duration::from_secs(5), test_db.db().get_task(TASK_ID));
+        assert_eq!(read.await.unwrap().unwrap(), Some(day_task()));
+    }
+
+    #[tokio::test]
+    async fn writes_of_other_tasks_do_not_wait() {
+        let test_db = TestDb::with_records(&[(TASK_ID, CURRENT_DAY_TASK)]);
+        let db = test_db.db();
+        assert_ne!(db.writes.shard(TASK_ID), db.writes.shard(OTHER_TASK_ID));
 
-        Ok(results)
+        let _writes = db.writes.lock(OTHER_TASK_ID).await;
+        let write = tokio::time::timeout(Duration::from_secs(5), db.schedule_poll(TASK_ID, 100));
+        write.await.unwrap().unwrap();
+        assert_eq!(
+            ids(db.list_due_tasks(100).await.unwrap()),
+            vec![TASK_ID.to_string()]
+        );
     }
 }
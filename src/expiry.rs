@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
+use teloxide::prelude::*;
+use tokio::time::MissedTickBehavior;
+
+use crate::db::TaskList;
+use crate::i18n::{Key, Lang};
+use crate::metrics::{Metrics, TASKS_EXPIRED};
+use crate::shutdown::Shutdown;
+use crate::store::TaskStore;
+use crate::utils::{format_task, truncate_message};
+
+const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 5 * 60;
+/// RZD shows departures in Moscow time, which has no daylight saving.
+const MOSCOW_OFFSET_SECS: i32 = 3 * 60 * 60;
+/// Longest TTL `/ttl` accepts.
+pub const MAX_TASK_TTL_DAYS: u32 = 365;
+
+pub fn moscow_now() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&moscow())
+}
+
+fn moscow() -> FixedOffset {
+    FixedOffset::east_opt(MOSCOW_OFFSET_SECS).unwrap()
+}
+
+/// Departure of the watched train, or the end of the travel day for day tasks.
+pub fn travel_ends_at(task: &HashMap<String, String>) -> Option<DateTime<FixedOffset>> {
+    let date = NaiveDate::parse_from_str(task.get("date")?, "%d.%m.%Y").ok()?;
+    let time = task
+        .get("time")
+        .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+        .unwrap_or(NaiveTime::from_hms_opt(23, 59, 59).unwrap());
+    moscow().from_local_datetime(&date.and_time(time)).single()
+}
+
+/// When the task stops being polled, the user's TTL can only make it earlier than the travel date.
+/// A TTL reaching past the dates chrono can represent does not bound the task.
+pub fn expires_at(
+    task: &HashMap<String, String>,
+    ttl: Option<chrono::Duration>,
+    now: DateTime<FixedOffset>,
+) -> Option<DateTime<FixedOffset>> {
+    let ttl_ends_at = ttl.and_then(|ttl| now.checked_add_signed(ttl));
+    match (travel_ends_at(task), ttl_ends_at) {
+        (Some(travel), Some(ttl)) => Some(travel.min(ttl)),
+        (travel, ttl) => travel.or(ttl),
+    }
+}
+
+/// Tasks created before `expires_at` was stored expire with the travel date.
+pub fn is_expired(task: &HashMap<String, String>, now: DateTime<FixedOffset>) -> bool {
+    let expires_at = match task.get("expires_at") {
+        Some(expires_at) => DateTime::parse_from_rfc3339(expires_at).ok(),
+        None => travel_ends_at(task),
+    };
+    expires_at.is_some_and(|expires_at| expires_at <= now)
+}
+
+/// Removes expired tasks and tells their owners the watch is over.
+pub struct Sweeper {
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    metrics: Arc<Metrics>,
+    interval: Duration,
+    archive: bool,
+}
+
+impl Sweeper {
+    /// Sweeps every `EXPIRY_SWEEP_INTERVAL_SECS`, `EXPIRED_TASKS=delete` drops the tasks instead of archiving them.
+    pub fn new(bot: Bot, rzd_db: Arc<dyn TaskStore>, metrics: Arc<Metrics>) -> Self {
+        let interval = env::var("EXPIRY_SWEEP_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_SWEEP_INTERVAL_SECS);
+        let archive = match env::var("EXPIRED_TASKS").unwrap_or_default().as_str() {
+            "" | "archive" => true,
+            "delete" => false,
+            mode => panic!("invalid EXPIRED_TASKS {mode}, expected archive or delete"),
+        };
+        Self {
+            bot,
+            rzd_db,
+            metrics,
+            interval: Duration::from_secs(interval),
+            archive,
+        }
+    }
+
+    pub async fn run(self, shutdown: Arc<Shutdown>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            self.sweep().await;
+        }
+    }
+
+    async fn sweep(&self) {
+        let now = moscow_now();
+        let mut pages = self.rzd_db.task_pages();
+        loop {
+            match pages.next_page().await {
+                Ok(Some(tasks)) => self.expire(&tasks, now).await,
+                Ok(None) => break,
+                Err(err) => {
+                    tracing::error!(error = %err, "cant list tasks for expiry");
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn expire(&self, tasks: &TaskList, now: DateTime<FixedOffset>) {
+        for (task_id, task) in tasks.iter().filter(|(_, task)| is_expired(task, now)) {
+            let found = match self.rzd_db.was_notified(task_id).await {
+                Ok(found) => found,
+                Err(err) => {
+                    tracing::warn!(task_id = %task_id, error = %err, "cant check notifications");
+                    false
+                }
+            };
+            if let Err(err) = self.rzd_db.expire_task(task_id, self.archive).await {
+                tracing::error!(task_id = %task_id, error = %err, "cant expire task");
+                continue;
+            }
+            let task_type = task.get("type").map(String::as_str).unwrap_or_default();
+            self.metrics.inc(TASKS_EXPIRED, &[("type", task_type)]);
+            tracing::info!(task_id = %task_id, "task expired");
+            self.notify_owner(task_id, task, found).await;
+        }
+    }
+
+    async fn notify_owner(&self, task_id: &str, task: &HashMap<String, String>, found: bool) {
+        let chat_id = match task.get("chat_id").and_then(|id| id.parse::<i64>().ok()) {
+            Some(chat_id) => ChatId(chat_id),
+            None => return,
+        };
+        let lang = task
+            .get("lang")
+            .and_then(|code| Lang::parse(code))
+            .unwrap_or_default();
+        let key = if found {
+            Key::WatchEnded
+        } else {
+            Key::WatchEndedNothingFound
+        };
+        let text = lang.tf(key, &[("task", &format_task(task_id, task, lang))]);
+        if let Err(err) = self
+            .bot
+            .send_message(chat_id, truncate_message(&text))
+            .await
+        {
+            tracing::warn!(task_id = %task_id, chat_id = chat_id.0, error = %err, "cant send expiry notice");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn at(text: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(text).unwrap()
+    }
+
+    #[test]
+    fn day_tasks_end_with_the_travel_day() {
+        let task = task(&[("type", "day"), ("date", "20.10.2026")]);
+        assert_eq!(travel_ends_at(&task), Some(at("2026-10-20T23:59:59+03:00")));
+        assert!(!is_expired(&task, at("2026-10-20T23:00:00+03:00")));
+        assert!(is_expired(&task, at("2026-10-21T00:00:00+03:00")));
+    }
+
+    #[test]
+    fn train_tasks_end_with_the_departure() {
+        let task = task(&[("type", "train"), ("date", "20.10.2026"), ("time", "08:15")]);
+        assert!(!is_expired(&task, at("2026-10-20T08:14:00+03:00")));
+        assert!(is_expired(&task, at("2026-10-20T05:15:00Z")));
+    }
+
+    #[test]
+    fn ttl_only_shortens_the_watch() {
+        let task = task(&[("type", "day"), ("date", "20.10.2026")]);
+        let now = at("2026-10-18T12:00:00+03:00");
+        assert_eq!(
+            expires_at(&task, Some(chrono::Duration::days(1)), now),
+            Some(at("2026-10-19T12:00:00+03:00"))
+        );
+        assert_eq!(
+            expires_at(&task, Some(chrono::Duration::days(5)), now),
+            travel_ends_at(&task)
+        );
+    }
+
+    #[test]
+    fn huge_ttl_does_not_bound_the_watch() {
+        let ttl = Some(chrono::Duration::days(100_000_000));
+        let now = at("2026-10-18T12:00:00+03:00");
+        let day = task(&[("type", "day"), ("date", "20.10.2026")]);
+        assert_eq!(expires_at(&day, ttl, now), travel_ends_at(&day));
+        assert_eq!(expires_at(&task(&[("type", "day")]), ttl, now), None);
+    }
+
+    #[test]
+    fn stored_expiry_wins_over_the_travel_date() {
+        let task = task(&[
+            ("type", "day"),
+            ("date", "20.10.2026"),
+            ("expires_at", "2026-10-19T12:00:00+03:00"),
+        ]);
+        assert!(is_expired(&task, at("2026-10-19T12:00:00+03:00")));
+        assert!(!is_expired(&task, at("2026-10-19T11:59:59+03:00")));
+    }
+}
@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::i18n::{Key, Lang};
+use crate::quick_search::{parse_car_type, parse_seat_filter};
+use crate::task_edit::{STATUS_ACTIVE, STATUS_PAUSED};
+
+/// Version of the JSON exports written by this build.
+pub const EXPORT_VERSION: u32 = 1;
+/// Import files are not downloaded beyond this size.
+pub const MAX_IMPORT_BYTES: u32 = 256 * 1024;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` export to version `n + 2`.
+type Migration = fn(Value) -> Result<Value, String>;
+
+const MIGRATIONS: [Migration; EXPORT_VERSION as usize - 1] = [];
+
+/// CSV columns are matched by name, so older files with fewer columns still import.
+const CSV_COLUMNS: [&str; 10] = [
+    "type",
+    "from_point_code",
+    "to_point_code",
+    "date",
+    "time",
+    "tnum",
+    "car_types",
+    "seats",
+    "max_price",
+    "status",
+];
+
+/// A task as it leaves the bot, without the id, owner and schedule, which belong to the account it was exported
+/// from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportedTask {
+    #[serde(rename = "type")]
+    pub task_type: String,
+    pub from_point_code: String,
+    pub to_point_code: String,
+    pub date: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tnum: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub car_types: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seats: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_price: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+impl ExportedTask {
+    pub fn from_task(task: &HashMap<String, String>) -> Self {
+        let field = |name: &str| task.get(name).cloned();
+        Self {
+            task_type: field("type").unwrap_or_default(),
+            from_point_code: field("from_point_code").unwrap_or_default(),
+            to_point_code: field("to_point_code").unwrap_or_default(),
+            date: field("date").unwrap_or_default(),
+            time: field("time"),
+            tnum: field("tnum"),
+            car_types: field("car_types"),
+            seats: field("seats"),
+            max_price: field("max_price").and_then(|price| price.parse().ok()),
+            status: field("status"),
+        }
+    }
+
+    /// Task fields to store, the owner is set by the importing chat.
+    pub fn into_task(self) -> HashMap<String, String> {
+        let mut task = HashMap::from([
+            ("type".to_string(), self.task_type),
+            ("from_point_code".to_string(), self.from_point_code),
+            ("to_point_code".to_string(), self.to_point_code),
+            ("date".to_string(), self.date),
+        ]);
+        let optional = [
+            ("time", self.time),
+            ("tnum", self.tnum),
+            ("car_types", self.car_types),
+            ("seats", self.seats),
+            ("max_price", self.max_price.map(|price| price.to_string())),
+            ("status", self.status),
+        ];
+        for (field, value) in optional {
+            if let Some(value) = value {
+                task.insert(field.to_string(), value);
+            }
+        }
+        task
+    }
+
+    /// Accepts only what the bot itself could have created.
+    fn validate(&self, today: NaiveDate) -> Result<(), EntryError> {
+        if !matches!(self.task_type.as_str(), "day" | "train") {
+            return Err(EntryError::UnknownType(self.task_type.clone()));
+        }
+        for (field, code) in [
+            ("from_point_code", &self.from_point_code),
+            ("to_point_code", &self.to_point_code),
+        ] {
+            if code.is_empty() {
+                return Err(EntryError::MissingField(field));
+            }
+            if !code.chars().all(|c| c.is_ascii_digit()) {
+                return Err(EntryError::InvalidField(field, code.clone()));
+            }
+        }
+        if self.date.is_empty() {
+            return Err(EntryError::MissingField("date"));
+        }
+        let date = NaiveDate::parse_from_str(&self.date, "%d.%m.%Y")
+            .map_err(|_| EntryError::InvalidField("date", self.date.clone()))?;
+        if date < today {
+            return Err(EntryError::DateInPast(date));
+        }
+        if self.task_type == "train" {
+            let time = self.time.as_ref().ok_or(EntryError::MissingField("time"))?;
+            if NaiveTime::parse_from_str(time, "%H:%M").is_err() {
+                return Err(EntryError::InvalidField("time", time.clone()));
+            }
+            match &self.tnum {
+                Some(tnum) if !tnum.is_empty() => {}
+                _ => return Err(EntryError::MissingField("tnum")),
+            }
+        }
+        if let Some(car_types) = &self.car_types {
+            if car_types
+                .split(',')
+                .any(|car_type| parse_car_type(car_type) != Some(car_type))
+            {
+                return Err(EntryError::InvalidField("car_types", car_types.clone()));
+            }
+        }
+        if let Some(seats) = &self.seats {
+            // Train tasks watch whole compartments, only day polls apply a seat filter
+            match parse_seat_filter(seats) {
+                Some(filter) if filter.as_str() == seats && self.task_type == "day" => {}
+                _ => return Err(EntryError::InvalidField("seats", seats.clone())),
+            }
+        }
+        if self.max_price == Some(0) {
+            return Err(EntryError::InvalidField("max_price", "0".to_string()));
+        }
+        if let Some(status) = &self.status {
+            if status != STATUS_ACTIVE && status != STATUS_PAUSED {
+                return Err(EntryError::InvalidField("status", status.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct Export<'a> {
+    version: u32,
+    exported_at: &'a str,
+    tasks: &'a [ExportedTask],
+}
+
+pub fn export_json(tasks: &[ExportedTask], exported_at: &str) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(&Export {
+        version: EXPORT_VERSION,
+        exported_at,
+        tasks,
+    })
+    .map_err(|err| format!("cant serialize export {err}"))
+}
+
+pub fn export_csv(tasks: &[ExportedTask]) -> String {
+    let mut csv = CSV_COLUMNS.join(",");
+    csv.push('\n');
+    for task in tasks {
+        let fields = task.clone().into_task();
+        let row: Vec<String> = CSV_COLUMNS
+            .iter()
+            .map(|column| csv_field(fields.get(*column).map(String::as_str).unwrap_or_default()))
+            .collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Entries of an import file, the ones that can't be created are listed with their number in the file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Import {
+    pub tasks: Vec<ExportedTask>,
+    pub rejected: Vec<(usize, EntryError)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportError {
+    Unreadable(String),
+    /// Exported by a newer build.
+    Newer(u32),
+}
+
+impl ImportError {
+    pub fn to_text(&self, lang: Lang) -> String {
+        match self {
+            ImportError::Unreadable(err) => lang.tf(Key::ImportUnreadable, &[("err", err)]),
+            ImportError::Newer(version) => lang.tf(Key::ImportNewer, &[("version", version)]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryError {
+    Unreadable(String),
+    UnknownType(String),
+    MissingField(&'static str),
+    InvalidField(&'static str, String),
+    DateInPast(NaiveDate),
+}
+
+impl EntryError {
+    pub fn to_text(&self, lang: Lang) -> String {
+        match self {
+            EntryError::Unreadable(err) => lang.tf(Key::ImportUnreadable, &[("err", err)]),
+            EntryError::UnknownType(task_type) => {
+                lang.tf(Key::ImportUnknownType, &[("type", task_type)])
+            }
+            EntryError::MissingField(field) => {
+                lang.tf(Key::ImportMissingField, &[("field", field)])
+            }
+            EntryError::InvalidField(field, value) => lang.tf(
+                Key::ImportInvalidField,
+                &[("field", field), ("value", value)],
+            ),
+            EntryError::DateInPast(date) => {
+                lang.tf(Key::DateInPast, &[("date", &date.format("%d.%m.%Y"))])
+            }
+        }
+    }
+}
+
+/// Reads a JSON export of any known version or a CSV export and checks every entry.
+pub fn parse_import(data: &[u8], today: NaiveDate) -> Result<Import, ImportError> {
+    let text = std::str::from_utf8(data)
+        .map_err(|_| ImportError::Unreadable("not a utf-8 text".to_string()))?;
+    let text = text.trim_start_matches('\u{feff}');
+    let entries = if text.trim_start().starts_with('{') {
+        json_entries(text)?
+    } else {
+        csv_entries(text)?
+    };
+    let mut import = Import::default();
+    for (idx, entry) in entries.into_iter().enumerate() {
+        match entry.and_then(|task| task.validate(today).map(|()| task)) {
+            Ok(task) => import.tasks.push(task),
+            Err(err) => import.rejected.push((idx + 1, err)),
+        }
+    }
+    Ok(import)
+}
+
+type Entries = Vec<Result<ExportedTask, EntryError>>;
+
+fn json_entries(text: &str) -> Result<Entries, ImportError> {
+    let mut export: Value = serde_json::from_str(text)
+        .map_err(|err| ImportError::Unreadable(format!("not a json {err}")))?;
+    let version = export
+        .get("version")
+        .and_then(Value::as_u64)
+        .and_then(|version| u32::try_from(version).ok())
+        .filter(|version| *version > 0)
+        .ok_or_else(|| ImportError::Unreadable("invalid version".to_string()))?;
+    if version > EXPORT_VERSION {
+        return Err(ImportError::Newer(version));
+    }
+    for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        export = migrate(export).map_err(|err| {
+            ImportError::Unreadable(format!("cant migrate from version {} {err}", from + 1))
+        })?;
+    }
+    match export.get_mut("tasks").map(Value::take) {
+        Some(Value::Array(tasks)) => Ok(tasks
+            .into_iter()
+            .map(|task| {
+                serde_json::from_value(task).map_err(|err| EntryError::Unreadable(err.to_string()))
+            })
+            .collect()),
+        _ => Err(ImportError::Unreadable("tasks are missing".to_string())),
+    }
+}
+
+fn csv_entries(text: &str) -> Result<Entries, ImportError> {
+    let mut records = parse_csv(text).into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| ImportError::Unreadable("the file is empty".to_string()))?;
+    if let Some(column) = header
+        .iter()
+        .find(|column| !CSV_COLUMNS.contains(&column.as_str()))
+    {
+        return Err(ImportError::Unreadable(format!("unknown column {column}")));
+    }
+    Ok(records
+        .map(|record| {
+            let field = |name: &str| {
+                header
+                    .iter()
+                    .position(|column| column == name)
+                    .and_then(|idx| record.get(idx))
+                    .filter(|value| !value.is_empty())
+                    .cloned()
+            };
+            let max_price = match field("max_price") {
+                Some(price) => Some(
+                    price
+                        .parse()
+                        .map_err(|_| EntryError::InvalidField("max_price", price))?,
+                ),
+                None => None,
+            };
+            Ok(ExportedTask {
+                task_type: field("type").unwrap_or_default(),
+                from_point_code: field("from_point_code").unwrap_or_default(),
+                to_point_code: field("to_point_code").unwrap_or_default(),
+                date: field("date").unwrap_or_default(),
+                time: field("time"),
+                tnum: field("tnum"),
+                car_types: field("car_types"),
+                seats: field("seats"),
+                max_price,
+                status: field("status"),
+            })
+        })
+        .collect())
+}
+
+/// Records of an RFC 4180 text, blank lines are skipped.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|field| !field.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    record.push(field);
+    if record.iter().any(|field| !field.is_empty()) {
+        records.push(record);
+    }
+    records
+}
+
+/// Tasks about to be created and the entries left out.
+pub fn format_import_preview(import: &Import, lang: Lang) -> String {
+    let mut text = lang.tf(Key::ImportPreview, &[("count", &import.tasks.len())]);
+    for (idx, task) in import.tasks.iter().enumerate() {
+        let entry = match task.task_type.as_str() {
+            "train" => lang.tf(
+                Key::ImportTrainEntry,
+                &[
+                    ("from", &task.from_point_code),
+                    ("to", &task.to_point_code),
+                    ("date", &task.date),
+                    ("time", &task.time.clone().unwrap_or_default()),
+                    ("tnum", &task.tnum.clone().unwrap_or_default()),
+                ],
+            ),
+            _ => lang.tf(
+                Key::ImportDayEntry,
+                &[
+                    ("from", &task.from_point_code),
+                    ("to", &task.to_point_code),
+                    ("date", &task.date),
+                ],
+            ),
+        };
+        text.push_str(&format!("\n{}. {entry}", idx + 1));
+    }
+    if !import.rejected.is_empty() {
+        text.push_str("\n\n");
+        text.push_str(lang.t(Key::ImportRejected));
+        for (entry, err) in import.rejected.iter() {
+            text.push_str(&format!("\n{entry}: {}", err.to_text(lang)));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 16).unwrap()
+    }
+
+    fn tasks() -> Vec<ExportedTask> {
+        vec![
+            ExportedTask {
+                task_type: "day".to_string(),
+                from_point_code: "2000000".to_string(),
+                to_point_code: "2004000".to_string(),
+                date: "21.10.2026".to_string(),
+                car_types: Some("купе,плацкартный".to_string()),
+                seats: Some("lower".to_string()),
+                max_price: Some(3500),
+                ..Default::default()
+            },
+            ExportedTask {
+                task_type: "train".to_string(),
+                from_point_code: "2000000".to_string(),
+                to_point_code: "2004000".to_string(),
+                date: "22.10.2026".to_string(),
+                time: Some("00:20".to_string()),
+                tnum: Some("020У".to_string()),
+                status: Some("paused".to_string()),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trips_json_and_csv_exports() {
+        let json = export_json(&tasks(), "2026-10-16T12:00:00+03:00").unwrap();
+        assert_eq!(
+            parse_import(&json, today()).unwrap(),
+            Import {
+                tasks: tasks(),
+                rejected: vec![],
+            }
+        );
+        let csv = export_csv(&tasks());
+        assert!(csv.contains("\"купе,плацкартный\""));
+        assert_eq!(
+            parse_import(csv.as_bytes(), today()).unwrap(),
+            Import {
+                tasks: tasks(),
+                rejected: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_entries_and_keeps_the_rest() {
+        let json = r#"{"version":1,"exported_at":"","tasks":[
+            {"type":"day","from_point_code":"2000000","to_point_code":"2004000","date":"21.10.2026"},
+            {"type":"bus","from_point_code":"2000000","to_point_code":"2004000","date":"21.10.2026"},
+            {"type":"day","from_point_code":"2000000","to_point_code":"2004000","date":"01.10.2026"},
+            {"type":"train","from_point_code":"2000000","to_point_code":"2004000","date":"21.10.2026"},
+            {"type":"day","from_point_code":"Москва","to_point_code":"2004000","date":"21.10.2026"},
+            {"type":"day","from_point_code":"2000000","to_point_code":"2004000","date":"21.10.2026","max_price":"cheap"},
+            {"type":"day","from_point_code":"2000000","to_point_code":"2004000","date":"21.10.2026","seats":"нижние"},
+            {"type":"train","from_point_code":"2000000","to_point_code":"2004000","date":"21.10.2026","time":"00:20","tnum":"020У","seats":"lower"}
+        ]}"#;
+        let import = parse_import(json.as_bytes(), today()).unwrap();
+        assert_eq!(import.tasks.len(), 1);
+        let rejected: Vec<usize> = import.rejected.iter().map(|(entry, _)| *entry).collect();
+        assert_eq!(rejected, vec![2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            import.rejected[0].1,
+            EntryError::UnknownType("bus".to_string())
+        );
+        assert_eq!(import.rejected[2].1, EntryError::MissingField("time"));
+        assert_eq!(
+            import.rejected[6].1,
+            EntryError::InvalidField("seats", "lower".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_csv_with_fewer_columns() {
+        let csv =
+            "type,from_point_code,to_point_code,date\r\nday,2000000,2004000,21.10.2026\r\n\r\n";
+        let import = parse_import(csv.as_bytes(), today()).unwrap();
+        assert_eq!(
+            import.tasks,
+            vec![ExportedTask {
+                task_type: "day".to_string(),
+                from_point_code: "2000000".to_string(),
+                to_point_code: "2004000".to_string(),
+                date: "21.10.2026".to_string(),
+                ..Default::default()
+            }]
+        );
+        assert_eq!(
+            parse_import(b"type,owner\nday,1\n", today()),
+            Err(ImportError::Unreadable("unknown column owner".to_string()))
+        );
+    }
+
+    #[test]
+    fn refuses_exports_of_newer_versions() {
+        assert_eq!(
+            parse_import(br#"{"version":2,"tasks":[]}"#, today()),
+            Err(ImportError::Newer(2))
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+/// How many poll intervals may pass without a finished cycle before the poller counts as stuck.
+const POLLER_STALL_INTERVALS: u32 = 3;
+
+/// Timestamps of the last observed activity, reported by the health endpoints.
+pub struct Health {
+    started_at: Instant,
+    poll_interval: Duration,
+    max_update_age: Option<Duration>,
+    max_rzd_success_age: Option<Duration>,
+    last_update: Mutex<Option<Instant>>,
+    last_rzd_success: Mutex<Option<Instant>>,
+    last_poll: Mutex<Option<Instant>>,
+}
+
+impl Health {
+    /// `HEALTH_MAX_UPDATE_AGE_SECS` and `HEALTH_MAX_RZD_AGE_SECS` turn the telegram and RZD ages into failing checks,
+    /// without them the ages are only reported.
+    pub fn new(poll_interval: Duration) -> Arc<Self> {
+        let max_age = |name: &str| {
+            env::var(name)
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+        };
+        Arc::new(Self {
+            started_at: Instant::now(),
+            poll_interval,
+            max_update_age: max_age("HEALTH_MAX_UPDATE_AGE_SECS"),
+            max_rzd_success_age: max_age("HEALTH_MAX_RZD_AGE_SECS"),
+            last_update: Mutex::new(None),
+            last_rzd_success: Mutex::new(None),
+            last_poll: Mutex::new(None),
+        })
+    }
+
+    pub fn mark_update(&self) {
+        *self.last_update.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn mark_rzd_success(&self) {
+        *self.last_rzd_success.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn mark_poll(&self) {
+        *self.last_poll.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn telegram_check(&self) -> Value {
+        self.age_check(*self.last_update.lock().unwrap(), self.max_update_age)
+    }
+
+    pub fn rzd_check(&self) -> Value {
+        self.age_check(
+            *self.last_rzd_success.lock().unwrap(),
+            self.max_rzd_success_age,
+        )
+    }
+
+    /// Before the first cycle finishes the age is counted from the start of the bot.
+    pub fn poller_check(&self) -> Value {
+        let last_poll = self.last_poll.lock().unwrap().unwrap_or(self.started_at);
+        let age = last_poll.elapsed();
+        let max_age = self.poll_interval * POLLER_STALL_INTERVALS;
+        json!({
+            "status": if age <= max_age { "ok" } else { "fail" },
+            "seconds_since_last_cycle": age.as_secs(),
+            "max_seconds": max_age.as_secs(),
+        })
+    }
+
+    fn age_check(&self, last: Option<Instant>, max_age: Option<Duration>) -> Value {
+        let age = last.unwrap_or(self.started_at).elapsed();
+        let status = match max_age {
+            Some(max_age) if age > max_age => "fail",
+            _ => "ok",
+        };
+        json!({
+            "status": status,
+            "seconds_since_last": last.map(|last| last.elapsed().as_secs()),
+            "max_seconds": max_age.map(|max_age| max_age.as_secs()),
+        })
+    }
+}
+
+pub fn is_ok(check: &Value) -> bool {
+    check["status"] == "ok"
+}
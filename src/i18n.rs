@@ -0,0 +1,771 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Lang {
+    #[default]
+    Ru,
+    En,
+}
+
+impl Lang {
+    /// Maps telegram's `language_code` to a catalog language, russian speakers get russian.
+    pub fn from_language_code(code: Option<&str>) -> Self {
+        match code.map(|code| code.split('-').next().unwrap_or(code)) {
+            None | Some("ru") | Some("uk") | Some("be") | Some("kk") => Lang::Ru,
+            Some(_) => Lang::En,
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "ru" | "рус" | "русский" => Some(Lang::Ru),
+            "en" | "eng" | "english" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::Ru => "ru",
+            Lang::En => "en",
+        }
+    }
+
+    pub fn t(self, key: Key) -> &'static str {
+        let (ru, en) = key.texts();
+        match self {
+            Lang::Ru => ru,
+            Lang::En => en,
+        }
+    }
+
+    /// Renders the message replacing `{name}` placeholders with the given arguments.
+    pub fn tf(self, key: Key, args: &[(&str, &(dyn Display + Sync))]) -> String {
+        let mut text = self.t(key).to_string();
+        for (name, value) in args {
+            text = text.replace(&format!("{{{name}}}"), &value.to_string());
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    // Keyboards
+    RZDButton,
+    SearchCupeButton,
+    TasksButton,
+    BackButton,
+    WatchDayButton,
+    WatchTrainButton,
+    DontWatchTrainButton,
+    DeleteTaskButton,
+    HistoryButton,
+    EditTaskButton,
+    PauseTaskButton,
+    ResumeTaskButton,
+    EditDateButton,
+    EditCarTypesButton,
+    EditSeatsButton,
+    EditMaxPriceButton,
+    CloneTaskButton,
+    ImportConfirmButton,
+    ImportCancelButton,
+    // Dialogue
+    ChooseService,
+    ChooseAction,
+    UnknownService,
+    Canceled,
+    DialogueReset,
+    SendPlainText,
+    InvalidCallback,
+    EnterFromPoint,
+    EnterToPoint,
+    ChooseFromPoint,
+    ChooseToPoint,
+    EnterDate,
+    FromCodesError,
+    ToCodesError,
+    StationCodesError,
+    FavouriteAdded,
+    FavouriteRemoved,
+    FavouriteError,
+    DateParseError,
+    TrainsNotFound,
+    TrainsError,
+    TrainsPageEntry,
+    NegativeIndex,
+    WrongIndex,
+    CarriagePlaces,
+    NoFreeCupe,
+    CarriagesError,
+    // Tasks
+    NoTasks,
+    TasksError,
+    TaskCreated,
+    TaskCreateError,
+    TaskDeleted,
+    TaskDeleteError,
+    DayTask,
+    TrainTask,
+    UnknownTask,
+    NotificationHistory,
+    NoNotificationHistory,
+    HistoryEntry,
+    HistoryTrainSeats,
+    HistoryCarSeats,
+    TaskPausedMark,
+    TaskSettings,
+    AnySeats,
+    LowerSeats,
+    UpperSeats,
+    NoPriceLimit,
+    TaskNotFound,
+    TaskUpdated,
+    TaskUpdateError,
+    TaskCloned,
+    EnterCarTypes,
+    EnterSeats,
+    EnterMaxPrice,
+    InvalidPrice,
+    // Export and import
+    ImportPrompt,
+    ImportNotADocument,
+    ImportTooLarge,
+    ImportUnreadable,
+    ImportNewer,
+    ImportUnknownType,
+    ImportMissingField,
+    ImportInvalidField,
+    ImportPreview,
+    ImportDayEntry,
+    ImportTrainEntry,
+    ImportRejected,
+    ImportNothing,
+    ImportDone,
+    // Notifications
+    SeatsFoundDay,
+    SeatsFoundTrain,
+    TrainSeatsLine,
+    WatchEnded,
+    WatchEndedNothingFound,
+    // Quick commands
+    ClarifyStation,
+    StationNotFound,
+    SingleDateRequired,
+    DatedTaskCreated,
+    DatedTaskCreateError,
+    DateMissing,
+    StationMissing,
+    StationsNotSeparated,
+    DateInPast,
+    RangeReversed,
+    RangeTooLong,
+    UnknownDate,
+    UnknownFilter,
+    SearchFilters,
+    // Inline mode
+    Error,
+    StationNotFoundTitle,
+    StationCodeText,
+    StationCodeDescription,
+    NoTrainsFound,
+    TrainSummary,
+    // Settings
+    LanguageChanged,
+    LanguageUsage,
+    TtlUsage,
+    TtlSet,
+    TtlOff,
+    TtlCurrent,
+    TtlTooLong,
+    // Admin
+    AdminStats,
+    PollerRunning,
+    PollerPausedState,
+    TaskOwner,
+    UnknownOwner,
+    DeleteTaskUsage,
+    PollerPaused,
+    PollerAlreadyPaused,
+    PollerResumed,
+    PollerNotPaused,
+    BroadcastUsage,
+    BroadcastDone,
+    ErrorsUsage,
+    NoRZDErrors,
+    UserIdUsage,
+    UserApproved,
+    UserBanned,
+    UserAccessReset,
+    InviteCreated,
+    LimitsUsage,
+    UserLimits,
+    BackupUsage,
+    BackupCreated,
+    BackupsList,
+    NoBackups,
+    // Access
+    AccessBanned,
+    AccessNotAllowed,
+    AccessInviteRequired,
+    InviteAccepted,
+    // Limits
+    TaskLimitReached,
+    SearchLimitReached,
+}
+
+impl Key {
+    fn texts(self) -> (&'static str, &'static str) {
+        match self {
+            Key::RZDButton => ("РЖД", "RZD"),
+            Key::SearchCupeButton => ("Поиск купе", "Search compartments"),
+            Key::TasksButton => ("Задачи", "Tasks"),
+            Key::BackButton => ("Назад", "Back"),
+            Key::WatchDayButton => ("Проверять этот день", "Watch this day"),
+            Key::WatchTrainButton => ("Проверять этот поезд", "Watch this train"),
+            Key::DontWatchTrainButton => ("Не проверять этот поезд", "Don't watch this train"),
+            Key::DeleteTaskButton => ("Удалить задачу {n}", "Delete task {n}"),
+            Key::HistoryButton => ("История {n}", "History {n}"),
+            Key::EditTaskButton => ("Изменить {n}", "Edit {n}"),
+            Key::PauseTaskButton => ("⏸ Приостановить", "⏸ Pause"),
+            Key::ResumeTaskButton => ("▶️ Возобновить", "▶️ Resume"),
+            Key::EditDateButton => ("Дата", "Date"),
+            Key::EditCarTypesButton => ("Типы вагонов", "Carriage types"),
+            Key::EditSeatsButton => ("Места", "Seats"),
+            Key::EditMaxPriceButton => ("Цена", "Price"),
+            Key::CloneTaskButton => ("Копия на другую дату", "Copy to another date"),
+            Key::ImportConfirmButton => ("Создать задачи", "Create the tasks"),
+            Key::ImportCancelButton => ("Отмена", "Cancel"),
+            Key::ChooseService => ("Выберите сервис", "Choose a service"),
+            Key::ChooseAction => ("Выберите действие", "Choose an action"),
+            Key::UnknownService => ("Неизвестный сервис", "Unknown service"),
+            Key::Canceled => ("Вы отменили действие", "You canceled it"),
+            Key::DialogueReset => ("Текущий диалог сброшен", "Current dialogue canceled"),
+            Key::SendPlainText => ("Отправь мне обычный текст", "Send me plain text"),
+            Key::InvalidCallback => ("Некорректные данные кнопки", "Invalid length of callback"),
+            Key::EnterFromPoint => ("Напишите точку отправления", "Enter the departure station"),
+            Key::EnterToPoint => ("Напишите точку прибытия", "Enter the arrival station"),
+            Key::ChooseFromPoint => ("Выбери точку отправления", "Choose the departure station"),
+            Key::ChooseToPoint => ("Выбери точку прибытия", "Choose the arrival station"),
+            Key::EnterDate => (
+                "Напиши мне дату в формате (день.месяц.год)",
+                "Send me the date as (day.month.year)",
+            ),
+            Key::FromCodesError => (
+                "Ошибка во время получения кодов отправления {err}",
+                "Error on getting departure station codes {err}",
+            ),
+            Key::ToCodesError => (
+                "Ошибка во время получения кодов прибытия {err}",
+                "Error on getting arrival station codes {err}",
+            ),
+            Key::StationCodesError => (
+                "Ошибка во время получения кодов станций {err}",
+                "Error on getting station codes {err}",
+            ),
+            Key::FavouriteAdded => ("Добавлено в избранное", "Added to favourites"),
+            Key::FavouriteRemoved => ("Удалено из избранного", "Removed from favourites"),
+            Key::FavouriteError => (
+                "Ошибка при изменении избранного: {err}",
+                "Error on changing favourites: {err}",
+            ),
+            Key::DateParseError => (
+                "Ошибка во время парсинга даты {err}. Текущий диалог сброшен",
+                "Error on parsing date {err}. Current dialogue canceled",
+            ),
+            Key::TrainsNotFound => (
+                "Не найдено. Пожалуйста, напиши /start чтобы заново начать. Текущий диалог сброшен",
+                "Nothing found. Please send /start to begin again. Current dialogue canceled",
+            ),
+            Key::TrainsError => (
+                "Ошибка при получении поездов {err}. Текущий диалог сброшен",
+                "Error on getting rzd trains {err}. Current dialogue canceled",
+            ),
+            Key::TrainsPageEntry => (
+                "{idx}. Поезд: {number}\nДата отбытия: {date} \nВремя отбытия: {time}\nКоличество свободных мест в купе: {seats}\n",
+                "{idx}. Train: {number}\nDeparture date: {date} \nDeparture time: {time}\nFree compartment seats: {seats}\n",
+            ),
+            Key::NegativeIndex => (
+                "Отрицательный индекс. Текущий диалог сброшен.",
+                "Negative index. Current dialogue canceled.",
+            ),
+            Key::WrongIndex => (
+                "Неправильный индекс. Текущий диалог сброшен",
+                "Wrong index. Current dialogue canceled",
+            ),
+            Key::CarriagePlaces => (
+                "Номер вагона: {car}\nНомер мест: {from} - {to}\n",
+                "Carriage: {car}\nSeats: {from} - {to}\n",
+            ),
+            Key::NoFreeCupe => ("Свободных купе не найдено", "No free compartments found"),
+            Key::CarriagesError => (
+                "Ошибка при получении вагонов {err}. Текущий диалог сброшен",
+                "Error on getting rzd train carriages {err}. Current dialogue canceled",
+            ),
+            Key::NoTasks => ("Нет задач", "No tasks"),
+            Key::TasksError => (
+                "Ошибка при получении задач {err}",
+                "Error on getting tasks {err}",
+            ),
+            Key::TaskCreated => (
+                "Создана задача с уникальным номером {task_id}",
+                "Created task with unique id {task_id}",
+            ),
+            Key::TaskCreateError => ("Невозможно создать задачу {err}", "Cant create task {err}"),
+            Key::TaskDeleted => ("Удалена задача с id: {task_id}", "Deleted task with id: {task_id}"),
+            Key::TaskDeleteError => (
+                "Ошибка при удалении задачи: {err}",
+                "Error on deleting task: {err}",
+            ),
+            Key::DayTask => (
+                "Проверка конкретного дня:\nId: {task_id}\nКод пункта отправления: {from}\nКод пункта прибытия: {to}\nДата: {date}",
+                "Day watch:\nId: {task_id}\nDeparture station code: {from}\nArrival station code: {to}\nDate: {date}",
+            ),
+            Key::TrainTask => (
+                "Проверка конкретного поезда:\nId: {task_id}\nКод пункта отправления: {from}\nКод пункта прибытия: {to}\nДата отправления: {date}\nВремя отправления: {time}\nНомер поезда отправления: {tnum}",
+                "Train watch:\nId: {task_id}\nDeparture station code: {from}\nArrival station code: {to}\nDeparture date: {date}\nDeparture time: {time}\nTrain number: {tnum}",
+            ),
+            Key::UnknownTask => (
+                "Неизвестный тип задачи:\nId: {task_id}",
+                "Unknown task type:\nId: {task_id}",
+            ),
+            Key::NotificationHistory => ("История уведомлений:", "Notification history:"),
+            Key::NoNotificationHistory => (
+                "Уведомлений по этой задаче ещё не было",
+                "There were no notifications for this task yet",
+            ),
+            Key::HistoryEntry => (
+                "{sent_at}, новых мест: {added}, пропало: {removed}",
+                "{sent_at}, new places: {added}, gone: {removed}",
+            ),
+            Key::HistoryTrainSeats => (
+                "Поезд {train}, мест: {seats}",
+                "Train {train}, seats: {seats}",
+            ),
+            Key::HistoryCarSeats => (
+                "Поезд {train}, вагон {car}, мест: {seats}",
+                "Train {train}, carriage {car}, seats: {seats}",
+            ),
+            Key::TaskPausedMark => ("⏸ Приостановлена", "⏸ Paused"),
+            Key::TaskSettings => (
+                "Типы вагонов: {car_types}\nМеста: {seats}\nЦена до: {max_price}",
+                "Carriage types: {car_types}\nSeats: {seats}\nPrice up to: {max_price}",
+            ),
+            Key::AnySeats => ("любые", "any"),
+            Key::LowerSeats => ("нижние", "lower"),
+            Key::UpperSeats => ("верхние", "upper"),
+            Key::NoPriceLimit => ("без ограничения", "no limit"),
+            Key::TaskNotFound => ("Задача не найдена", "Task not found"),
+            Key::TaskUpdated => ("Задача изменена:\n{task}", "Task updated:\n{task}"),
+            Key::TaskUpdateError => (
+                "Ошибка при изменении задачи: {err}",
+                "Error on updating task: {err}",
+            ),
+            Key::TaskCloned => ("Создана копия задачи:\n{task}", "Task copied:\n{task}"),
+            Key::EnterCarTypes => (
+                "Напиши типы вагонов: купе, плацкарт, св, сидячий",
+                "Send the carriage types: coupe, berth, sv, seated",
+            ),
+            Key::EnterSeats => (
+                "Напиши, какие места нужны: нижние, верхние или любые",
+                "Send which seats you need: lower, upper or any",
+            ),
+            Key::EnterMaxPrice => (
+                "Напиши максимальную цену в рублях, 0 снимает ограничение",
+                "Send the maximum price in roubles, 0 removes the limit",
+            ),
+            Key::InvalidPrice => ("Некорректная цена: {text}", "Invalid price: {text}"),
+            Key::ImportPrompt => (
+                "Пришли файл JSON или CSV, который выгрузила команда /export",
+                "Send the JSON or CSV file made by the /export command",
+            ),
+            Key::ImportNotADocument => (
+                "Пришли задачи файлом или отмени импорт командой /cancel",
+                "Send the tasks as a file or cancel the import with /cancel",
+            ),
+            Key::ImportTooLarge => (
+                "Файл больше {kb} КБ, такой не импортировать",
+                "The file is larger than {kb} KB and can't be imported",
+            ),
+            Key::ImportUnreadable => ("Не удалось прочитать: {err}", "Cant read it: {err}"),
+            Key::ImportNewer => (
+                "Файл выгружен более новой версией бота ({version}), обнови бота",
+                "The file was exported by a newer bot version ({version}), update the bot",
+            ),
+            Key::ImportUnknownType => ("неизвестный тип задачи {type}", "unknown task type {type}"),
+            Key::ImportMissingField => ("не заполнено поле {field}", "{field} is missing"),
+            Key::ImportInvalidField => (
+                "некорректное поле {field}: {value}",
+                "invalid {field}: {value}",
+            ),
+            Key::ImportPreview => ("Будут созданы задачи ({count}):", "Tasks to create ({count}):"),
+            Key::ImportDayEntry => ("{from} → {to}, {date}", "{from} → {to}, {date}"),
+            Key::ImportTrainEntry => (
+                "{from} → {to}, {date} {time}, поезд {tnum}",
+                "{from} → {to}, {date} {time}, train {tnum}",
+            ),
+            Key::ImportRejected => ("Пропущены записи:", "Skipped entries:"),
+            Key::ImportNothing => (
+                "В файле нет задач, которые можно создать",
+                "There are no tasks to create in the file",
+            ),
+            Key::ImportDone => ("Создано задач: {created} из {total}", "Created {created} of {total} tasks"),
+            Key::SeatsFoundDay => (
+                "Появились свободные места на {date}, {from} → {to}:\n\n{places}",
+                "Free seats on {date}, {from} → {to}:\n\n{places}",
+            ),
+            Key::SeatsFoundTrain => (
+                "Появились свободные купе в поезде {tnum} {date} {time}:\n\n{places}",
+                "Free compartments in train {tnum} {date} {time}:\n\n{places}",
+            ),
+            Key::TrainSeatsLine => (
+                "Поезд {number}, отправление {time}, мест: {seats}",
+                "Train {number}, departure {time}, seats: {seats}",
+            ),
+            Key::WatchEnded => ("Слежение завершено:\n{task}", "Watch ended:\n{task}"),
+            Key::WatchEndedNothingFound => (
+                "Слежение завершено, свободных мест так и не нашлось:\n{task}",
+                "Watch ended, no free seats were found:\n{task}",
+            ),
+            Key::ClarifyStation => ("Уточните станцию «{name}»", "Which station is “{name}”?"),
+            Key::StationNotFound => ("Станция не найдена: {name}", "Station not found: {name}"),
+            Key::SingleDateRequired => (
+                "Для поиска укажите одну дату",
+                "Search needs a single date",
+            ),
+            Key::DatedTaskCreated => (
+                "Создана задача на {date} с уникальным номером {task_id}\n",
+                "Created task for {date} with unique id {task_id}\n",
+            ),
+            Key::DatedTaskCreateError => (
+                "Невозможно создать задачу на {date} {err}\n",
+                "Cant create task for {date} {err}\n",
+            ),
+            Key::DateMissing => (
+                "Не найдена дата. Пример: /search Москва Санкт-Петербург 21.10",
+                "No date found. Example: /search Moscow Saint-Petersburg 21.10",
+            ),
+            Key::StationMissing => (
+                "Не указана станция отправления или прибытия",
+                "Departure or arrival station is missing",
+            ),
+            Key::StationsNotSeparated => (
+                "Не удалось разделить станции, разделите их через ' - '",
+                "Cant tell the stations apart, separate them with ' - '",
+            ),
+            Key::DateInPast => ("Дата {date} уже прошла", "Date {date} has already passed"),
+            Key::RangeReversed => (
+                "Конец диапазона раньше начала",
+                "The range ends before it starts",
+            ),
+            Key::RangeTooLong => (
+                "Диапазон дат не может быть длиннее {days} дней",
+                "The date range cant be longer than {days} days",
+            ),
+            Key::UnknownDate => ("Неизвестная дата: {text}", "Unknown date: {text}"),
+            Key::UnknownFilter => ("Неизвестный фильтр: {text}", "Unknown filter: {text}"),
+            Key::SearchFilters => (
+                "Поиск показывает только купе, фильтры вагонов и мест работают в /watch",
+                "Search only shows compartments, car and seat filters work with /watch",
+            ),
+            Key::Error => ("Ошибка", "Error"),
+            Key::StationNotFoundTitle => ("Станция не найдена", "Station not found"),
+            Key::StationCodeText => ("{name} — код станции {code}", "{name} — station code {code}"),
+            Key::StationCodeDescription => ("Код: {code}", "Code: {code}"),
+            Key::NoTrainsFound => ("Поездов не найдено", "No trains found"),
+            Key::TrainSummary => (
+                "Поезд: {number}\nОтправление: {date} {time}\n",
+                "Train: {number}\nDeparture: {date} {time}\n",
+            ),
+            Key::LanguageChanged => ("Язык: русский", "Language: English"),
+            Key::LanguageUsage => (
+                "Использование: /lang ru или /lang en",
+                "Usage: /lang ru or /lang en",
+            ),
+            Key::TtlUsage => (
+                "Использование: /ttl <дней> или /ttl off. Новые задачи будут удаляться через столько дней, если дата поездки не наступит раньше",
+                "Usage: /ttl <days> or /ttl off. New tasks are removed after that many days unless the travel date comes first",
+            ),
+            Key::TtlSet => (
+                "Новые задачи будут удаляться через {days} дн.",
+                "New tasks are removed after {days} days",
+            ),
+            Key::TtlOff => (
+                "Задачи удаляются после даты поездки",
+                "Tasks are removed after the travel date",
+            ),
+            Key::TtlCurrent => (
+                "Задачи удаляются через {days} дн. или после даты поездки, если она раньше",
+                "Tasks are removed after {days} days or after the travel date if it comes first",
+            ),
+            Key::TtlTooLong => (
+                "Срок не может быть больше {max} дн.",
+                "The TTL can't be longer than {max} days",
+            ),
+            Key::AdminStats => (
+                "Пользователей: {users}\nЗадач: {tasks}\nОпросов задач за час: {polls}, с ошибкой: {poll_errors}\nЗапросов к РЖД: {rzd_calls}, с ошибкой: {rzd_errors} ({rzd_error_rate}%)\nОпрос задач: {poller}",
+                "Users: {users}\nTasks: {tasks}\nTask polls in the last hour: {polls}, failed: {poll_errors}\nRZD calls: {rzd_calls}, failed: {rzd_errors} ({rzd_error_rate}%)\nTask polling: {poller}",
+            ),
+            Key::PollerRunning => ("работает", "running"),
+            Key::PollerPausedState => ("на паузе", "paused"),
+            Key::TaskOwner => ("Владелец: {user_id}", "Owner: {user_id}"),
+            Key::UnknownOwner => ("неизвестен", "unknown"),
+            Key::DeleteTaskUsage => (
+                "Использование: /deltask <id задачи>",
+                "Usage: /deltask <task id>",
+            ),
+            Key::PollerPaused => ("Опрос задач приостановлен", "Task polling is paused"),
+            Key::PollerAlreadyPaused => (
+                "Опрос задач уже приостановлен",
+                "Task polling is already paused",
+            ),
+            Key::PollerResumed => ("Опрос задач возобновлён", "Task polling is resumed"),
+            Key::PollerNotPaused => ("Опрос задач не был приостановлен", "Task polling is not paused"),
+            Key::BroadcastUsage => (
+                "Использование: /broadcast <текст сообщения>",
+                "Usage: /broadcast <message text>",
+            ),
+            Key::BroadcastDone => (
+                "Сообщение отправлено в {sent} чатов, не удалось: {failed}",
+                "Message sent to {sent} chats, failed: {failed}",
+            ),
+            Key::ErrorsUsage => (
+                "Использование: /errors [количество]",
+                "Usage: /errors [count]",
+            ),
+            Key::NoRZDErrors => ("Ошибок РЖД не было", "No RZD errors so far"),
+            Key::UserIdUsage => (
+                "Укажите числовой id пользователя, например /approve 123456",
+                "Specify a numeric user id, for example /approve 123456",
+            ),
+            Key::UserApproved => ("Пользователь {user_id} одобрен", "User {user_id} is approved"),
+            Key::UserBanned => ("Пользователь {user_id} заблокирован", "User {user_id} is banned"),
+            Key::UserAccessReset => (
+                "Пользователь {user_id} удалён из списков доступа",
+                "User {user_id} is removed from the access lists",
+            ),
+            Key::InviteCreated => (
+                "Одноразовый код приглашения: {code}\nСсылка: {link}",
+                "One time invite code: {code}\nLink: {link}",
+            ),
+            Key::LimitsUsage => (
+                "Использование: /limits <id пользователя> [tasks=N] [searches=N] [interval=секунды] или /limits <id пользователя> reset. 0 снимает ограничение задач или поисков",
+                "Usage: /limits <user id> [tasks=N] [searches=N] [interval=seconds] or /limits <user id> reset. 0 lifts the task or search limit",
+            ),
+            Key::UserLimits => (
+                "Лимиты пользователя {user_id}:\nАктивных задач: {tasks}\nПоисков в минуту: {searches}\nМинимальный интервал опроса: {interval} с",
+                "Limits of user {user_id}:\nActive tasks: {tasks}\nSearches per minute: {searches}\nMinimum poll interval: {interval} s",
+            ),
+            Key::BackupUsage => (
+                "Использование: /backup, /backup list или /backup export",
+                "Usage: /backup, /backup list or /backup export",
+            ),
+            Key::BackupCreated => (
+                "Резервная копия {id} создана, размер {size} байт",
+                "Backup {id} is created, {size} bytes",
+            ),
+            Key::BackupsList => ("Резервные копии:", "Backups:"),
+            Key::NoBackups => ("Резервных копий нет", "There are no backups"),
+            Key::AccessBanned => (
+                "Извините, доступ к боту для вас закрыт.",
+                "Sorry, your access to this bot has been revoked.",
+            ),
+            Key::AccessNotAllowed => (
+                "Извините, бот доступен только одобренным пользователям. Чтобы получить доступ, передайте администратору ваш id: {user_id}",
+                "Sorry, this bot is available to approved users only. To get access, send your id to the administrator: {user_id}",
+            ),
+            Key::AccessInviteRequired => (
+                "Извините, бот доступен только по приглашению. Откройте ссылку-приглашение или отправьте /start <код>. Ваш id для администратора: {user_id}",
+                "Sorry, this bot is available by invitation only. Open your invite link or send /start <code>. Your id for the administrator: {user_id}",
+            ),
+            Key::InviteAccepted => (
+                "Приглашение принято, добро пожаловать! Нажмите /start, чтобы начать.",
+                "Invitation accepted, welcome! Press /start to begin.",
+            ),
+            Key::TaskLimitReached => (
+                "Достигнут лимит активных задач: {limit}. Удалите ненужные задачи в разделе «Задачи», чтобы создать новую.",
+                "You have reached the limit of {limit} active tasks. Delete tasks you no longer need in «Tasks» to create a new one.",
+            ),
+            Key::SearchLimitReached => (
+                "Слишком много поисков за минуту, попробуйте снова через {secs} с.",
+                "Too many searches in a minute, please try again in {secs} s.",
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+
+    use super::*;
+
+    const SOURCE: &str = include_str!("i18n.rs");
+
+    /// Skips a string literal starting at `at`, returns its contents and the index after it.
+    fn literal(text: &str, at: usize) -> (&str, usize) {
+        let bytes = text.as_bytes();
+        assert_eq!(bytes[at], b'"', "expected a string literal at {at}");
+        let mut end = at + 1;
+        while bytes[end] != b'"' {
+            end += if bytes[end] == b'\\' { 2 } else { 1 };
+        }
+        (&text[at + 1..end], end + 1)
+    }
+
+    fn skip_whitespace(text: &str, at: usize) -> usize {
+        at + text[at..].len() - text[at..].trim_start().len()
+    }
+
+    /// Variant names of `Key` in declaration order.
+    fn key_names() -> Vec<&'static str> {
+        let start = SOURCE.find("pub enum Key {").unwrap();
+        let end = start + SOURCE[start..].find('}').unwrap();
+        SOURCE[start..end]
+            .lines()
+            .skip(1)
+            .map(str::trim)
+            .filter_map(|line| line.strip_suffix(','))
+            .collect()
+    }
+
+    /// Russian and english texts of every key as they are written in `Key::texts`.
+    fn texts() -> HashMap<&'static str, (&'static str, &'static str)> {
+        let start = SOURCE.find("fn texts(self)").unwrap();
+        let end = SOURCE.find("#[cfg(test)]").unwrap();
+        let mut texts = HashMap::new();
+        let mut at = start;
+        while let Some(found) = SOURCE[at..end].find("            Key::") {
+            let name_at = at + found + "            Key::".len();
+            let arrow = name_at + SOURCE[name_at..].find(" => (").unwrap();
+            let name = &SOURCE[name_at..arrow];
+            let (ru, next) = literal(SOURCE, skip_whitespace(SOURCE, arrow + " => (".len()));
+            let next = skip_whitespace(SOURCE, next);
+            assert_eq!(&SOURCE[next..next + 1], ",", "{name} has no english text");
+            let (en, next) = literal(SOURCE, skip_whitespace(SOURCE, next + 1));
+            texts.insert(name, (ru, en));
+            at = next;
+        }
+        texts
+    }
+
+    /// Index of the `)` closing the parenthesis opened right before `at`, string literals are skipped.
+    fn closing_paren(text: &str, mut at: usize) -> usize {
+        let mut depth = 1;
+        while depth > 0 {
+            match text.as_bytes()[at] {
+                b'"' => {
+                    at = literal(text, at).1;
+                    continue;
+                }
+                b'(' => depth += 1,
+                b')' => depth -= 1,
+                _ => {}
+            }
+            at += 1;
+        }
+        at - 1
+    }
+
+    /// Keys `tf` is called with in the source and the argument names passed, calls with a key from a variable
+    /// are left out.
+    fn tf_calls(source: &str) -> Vec<(String, Vec<String>)> {
+        let mut calls = Vec::new();
+        let mut from = 0;
+        while let Some(found) = source[from..].find(".tf(") {
+            let start = from + found + ".tf(".len();
+            let end = closing_paren(source, start);
+            from = start;
+            // Arguments may hold calls of their own, those are looked at separately
+            let mut args = String::new();
+            let mut at = start;
+            while let Some(nested) = source[at..end].find(".tf(") {
+                args.push_str(&source[at..at + nested]);
+                at = closing_paren(source, at + nested + ".tf(".len()) + 1;
+            }
+            args.push_str(&source[at..end]);
+            let key = match args.trim_start().strip_prefix("Key::") {
+                Some(key) => key
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>(),
+                None => continue,
+            };
+            let names = args
+                .split("(\"")
+                .skip(1)
+                .filter_map(|arg| arg.split_once("\","))
+                .map(|(name, _)| name.to_string())
+                .collect();
+            calls.push((key, names));
+        }
+        calls
+    }
+
+    #[test]
+    fn every_key_has_both_texts() {
+        let names = key_names();
+        let texts = texts();
+        assert_eq!(names.len(), texts.len());
+        for name in names {
+            let (ru, en) = texts[name];
+            assert!(!ru.trim().is_empty(), "{name} has no russian text");
+            assert!(!en.trim().is_empty(), "{name} has no english text");
+        }
+        assert_eq!(Lang::Ru.t(Key::BackButton), "Назад");
+        assert_eq!(Lang::En.t(Key::BackButton), "Back");
+    }
+
+    #[test]
+    fn both_texts_have_the_placeholders_of_tf_calls() {
+        let texts = texts();
+        let mut checked = 0;
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            // The catalog itself only calls it in these tests
+            if path.extension().and_then(|ext| ext.to_str()) != Some("rs")
+                || path.ends_with("i18n.rs")
+            {
+                continue;
+            }
+            for (key, names) in tf_calls(&fs::read_to_string(&path).unwrap()) {
+                let (ru, en) = texts[key.as_str()];
+                for name in names {
+                    let placeholder = format!("{{{name}}}");
+                    assert!(
+                        ru.contains(&placeholder),
+                        "{key} has no {placeholder} in russian"
+                    );
+                    assert!(
+                        en.contains(&placeholder),
+                        "{key} has no {placeholder} in english"
+                    );
+                }
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+        assert_eq!(
+            Lang::En.tf(Key::SearchLimitReached, &[("secs", &5)]),
+            "Too many searches in a minute, please try again in 5 s."
+        );
+    }
+
+    #[test]
+    fn picks_the_language_from_telegram_and_user_input() {
+        assert_eq!(Lang::from_language_code(Some("en-US")), Lang::En);
+        assert_eq!(Lang::from_language_code(Some("ru")), Lang::Ru);
+        assert_eq!(Lang::from_language_code(Some("ru-RU")), Lang::Ru);
+        assert_eq!(Lang::from_language_code(Some("de")), Lang::En);
+        assert_eq!(Lang::from_language_code(None), Lang::Ru);
+
+        assert_eq!(Lang::parse(" EN "), Some(Lang::En));
+        assert_eq!(Lang::parse("русский"), Some(Lang::Ru));
+        assert_eq!(Lang::parse("de"), None);
+    }
+}
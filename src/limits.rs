@@ -0,0 +1,84 @@
+use std::env;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MAX_TASKS: usize = 10;
+const DEFAULT_SEARCHES_PER_MINUTE: usize = 5;
+const DEFAULT_MIN_POLL_INTERVAL_SECS: u64 = 60;
+
+/// Per user quotas, a zero task or search limit turns the limit off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_tasks: usize,
+    pub searches_per_minute: usize,
+    /// Tasks of the user are polled at most this often, the poller never ticks faster than the global value.
+    pub min_poll_interval: Duration,
+}
+
+impl Limits {
+    /// Defaults from `MAX_TASKS_PER_USER`, `SEARCHES_PER_MINUTE` and `MIN_POLL_INTERVAL_SECS`.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        }
+        Self {
+            max_tasks: var("MAX_TASKS_PER_USER", DEFAULT_MAX_TASKS),
+            searches_per_minute: var("SEARCHES_PER_MINUTE", DEFAULT_SEARCHES_PER_MINUTE),
+            min_poll_interval: Duration::from_secs(var(
+                "MIN_POLL_INTERVAL_SECS",
+                DEFAULT_MIN_POLL_INTERVAL_SECS,
+            )),
+        }
+    }
+
+    pub fn with_overrides(self, overrides: &LimitOverrides) -> Self {
+        Self {
+            max_tasks: overrides.max_tasks.unwrap_or(self.max_tasks),
+            searches_per_minute: overrides
+                .searches_per_minute
+                .unwrap_or(self.searches_per_minute),
+            min_poll_interval: overrides
+                .min_poll_interval_secs
+                .map(Duration::from_secs)
+                .unwrap_or(self.min_poll_interval),
+        }
+    }
+}
+
+/// Limits an admin set for a specific user, unset fields fall back to the defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitOverrides {
+    pub max_tasks: Option<usize>,
+    pub searches_per_minute: Option<usize>,
+    pub min_poll_interval_secs: Option<u64>,
+}
+
+impl LimitOverrides {
+    /// Parses `tasks=N searches=N interval=SECS`, every part is optional.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut overrides = Self::default();
+        for part in text.split_whitespace() {
+            let (name, value) = part.split_once('=')?;
+            match name {
+                "tasks" => overrides.max_tasks = Some(value.parse().ok()?),
+                "searches" => overrides.searches_per_minute = Some(value.parse().ok()?),
+                "interval" => overrides.min_poll_interval_secs = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+        Some(overrides)
+    }
+
+    /// Fields set in `other` replace the ones set here.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            max_tasks: other.max_tasks.or(self.max_tasks),
+            searches_per_minute: other.searches_per_minute.or(self.searches_per_minute),
+            min_poll_interval_secs: other.min_poll_interval_secs.or(self.min_poll_interval_secs),
+        }
+    }
+}
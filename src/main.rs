@@ -1,30 +1,83 @@
+mod access;
+mod admin;
+mod backup;
+mod cli;
 mod db;
+mod expiry;
+mod export;
+mod health;
+mod i18n;
+mod limits;
+mod metrics;
+mod pagination;
+mod poller;
+mod quick_search;
+mod rate_limiter;
 mod rzd;
+mod schema;
+mod server;
+mod shutdown;
+mod storage;
+mod store;
+mod task_edit;
+mod telemetry;
 mod utils;
 
 use std::collections::HashMap;
 use std::{env, thread};
 use std::future::Future;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
 
+use crate::access::{access_handler, AccessMode};
+use crate::admin::{admin_handler, Admins};
+use crate::backup::Backups;
 use crate::db::RZDDb;
-use crate::rzd::RZDApi;
-use chrono::NaiveDate;
-use log::LevelFilter;
+use crate::expiry::MAX_TASK_TTL_DAYS;
+use crate::export::{
+    export_csv, export_json, format_import_preview, parse_import, ExportedTask, MAX_IMPORT_BYTES,
+};
+use crate::health::Health;
+use crate::i18n::{Key, Lang};
+use crate::limits::Limits;
+use crate::metrics::{Metrics, HANDLER_INVOCATIONS};
+use crate::pagination::{is_page_callback, parse_page};
+use crate::poller::{poll_interval_from_env, Poller, PollerControl};
+use crate::quick_search::{
+    parse_quick_query, resolve_station, QuickCommand, QuickQuery, QuickQueryError, ResolvedStation,
+};
+use crate::rzd::{GetRZDPointCodes, RZDApi};
+use crate::shutdown::{wait_for_signal, Shutdown};
+use crate::storage::{MemoryStorage, Storage};
+use crate::store::TaskStore;
+use crate::task_edit::{apply_edit, parse_task_action, set_paused, TaskAction, TaskEdit};
+use crate::telemetry::update_span;
+use chrono::{Local, NaiveDate};
 use speedb::{Options, DB};
-use teloxide::types::InputFile;
+use teloxide::types::{
+    InlineQueryResult, InlineQueryResultArticle, InputFile, InputMessageContent,
+    InputMessageContentText,
+};
 
 use teloxide::{
     dispatching::{dialogue, dialogue::InMemStorage, UpdateHandler},
     prelude::*,
-    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+    types::InlineKeyboardMarkup,
     utils::command::BotCommands,
 };
 use teloxide::dispatching::dialogue::GetChatId;
-use crate::utils::{make_start_keyboard, make_rzd_start_keyboard};
+use teloxide::net::Download;
+use crate::utils::{
+    find_free_compartments, format_carriages_page, format_history_page, format_task, format_task_menu,
+    format_tasks_page, format_trains_page, format_trains_summary, make_carriages_keyboard,
+    make_history_keyboard, make_import_keyboard, make_quick_pick_keyboard, make_rzd_start_keyboard,
+    make_start_keyboard, make_stations_keyboard, make_task_menu_keyboard, make_tasks_keyboard,
+    make_trains_keyboard, parse_history_callback, parse_short_date, truncate_message,
+};
 
 const CUPE_TYPE: &str = "купе";
+const DEFAULT_HTTP_ADDR: &str = "0.0.0.0:8080";
 
 type RZDDialogue = Dialogue<State, InMemStorage<State>>;
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
@@ -35,6 +88,12 @@ enum Command {
     Start,
     Cancel,
     Tasks,
+    Search(String),
+    Watch(String),
+    Lang(String),
+    Ttl(String),
+    Export,
+    Import,
     Niggers,
     Dimok,
     Ss,
@@ -47,6 +106,7 @@ pub struct Train {
     dt0: String,
     time0: String,
     tnum0: String,
+    cupe_free_seats: usize,
 }
 
 #[derive(Clone, Default)]
@@ -57,53 +117,219 @@ pub enum State {
     ChooseRZDService,
     DeleteTask,
     ReceiveFromPoint,
-    ChooseFromPointCode,
+    ChooseFromPointCode {
+        codes: Vec<GetRZDPointCodes>,
+    },
     ReceiveToPoint {
         from_point_code: String,
     },
     ChooseToPointCode {
         from_point_code: String,
+        codes: Vec<GetRZDPointCodes>,
     },
     ReceiveDate {
         from_point_code: String,
         to_point_code: String,
     },
     ChooseTrain {
+        date: String,
         trains: Vec<Train>,
     },
+    ChooseCarriage {
+        train: Train,
+        places: Vec<String>,
+    },
+    ChooseQuickStation {
+        query: QuickQuery,
+        codes: Vec<GetRZDPointCodes>,
+    },
+    EditTask {
+        task_id: String,
+        edit: TaskEdit,
+    },
+    ImportTasks,
+    ConfirmImport {
+        tasks: Vec<ExportedTask>,
+    },
+}
+
+impl State {
+    /// Variant name used as a metrics label.
+    fn name(&self) -> &'static str {
+        match self {
+            State::Start => "start",
+            State::ChooseService => "choose_service",
+            State::ChooseRZDService => "choose_rzd_service",
+            State::DeleteTask => "delete_task",
+            State::ReceiveFromPoint => "receive_from_point",
+            State::ChooseFromPointCode { .. } => "choose_from_point_code",
+            State::ReceiveToPoint { .. } => "receive_to_point",
+            State::ChooseToPointCode { .. } => "choose_to_point_code",
+            State::ReceiveDate { .. } => "receive_date",
+            State::ChooseTrain { .. } => "choose_train",
+            State::ChooseCarriage { .. } => "choose_carriage",
+            State::ChooseQuickStation { .. } => "choose_quick_station",
+            State::EditTask { .. } => "edit_task",
+            State::ImportTasks => "import_tasks",
+            State::ConfirmImport { .. } => "confirm_import",
+        }
+    }
+}
+
+/// speedb at `DB_PATH` unless `STORAGE=memory`, which keeps nothing after a restart and is meant for trying the
+/// bot out.
+fn open_speedb(db_path: &str, backups: &Backups) -> Option<DB> {
+    match env::var("STORAGE").unwrap_or_default().as_str() {
+        "" | "speedb" => {}
+        "memory" => {
+            tracing::warn!("STORAGE is memory, tasks and settings are lost on restart");
+            return None;
+        }
+        storage => panic!("invalid STORAGE {storage}, expected speedb or memory"),
+    }
+    match backups.restore_missing(db_path) {
+        Ok(Some(source)) => {
+            tracing::warn!(db_path, source, "DB_PATH does not exists, restored it from a backup")
+        }
+        Ok(None) => {}
+        Err(err) => panic!("cant restore db: {err}"),
+    }
+    if !Path::exists(db_path.as_ref()) {
+        tracing::warn!(db_path, "DB_PATH does not exists, creating");
+    }
+
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    Some(DB::open(&options, db_path).expect("cant create db"))
 }
 
 #[tokio::main]
 async fn main() {
-    env_logger::builder().filter_level(LevelFilter::Info).init();
+    telemetry::init();
     let mut db_path = env::var("DB_PATH").unwrap_or_default();
     if db_path.is_empty() {
-        log::warn!("DB_PATH is empty. Creating default file db.db");
+        tracing::warn!("DB_PATH is empty. Creating default file db.db");
         db_path = "db.db".to_string();
     }
 
-    if !Path::exists(db_path.clone().as_ref()) {
-        log::warn!("DB_PATH {db_path} does not exists, creating");
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "db") {
+        if let Err(err) = cli::run(&db_path, &args[1..]).await {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
     }
 
-    let mut options = Options::default();
-    options.create_if_missing(true);
-    let db = DB::open(&options, db_path).expect("cant create db");
-
-    let rzd_db = RZDDb::new(db);
+    let mut backups = Backups::from_env(&db_path);
+    let storage: Arc<dyn Storage> = match open_speedb(&db_path, &backups) {
+        Some(db) => {
+            let db = Arc::new(db);
+            backups = backups.with_db(db.clone());
+            db
+        }
+        None => Arc::<MemoryStorage>::default(),
+    };
+    let backups = Arc::new(backups);
+    let limits = Limits::from_env();
+    let rzd_db = RZDDb::new(storage, limits);
+    match rzd_db.migrate_tasks().await {
+        Ok(scan) => tracing::info!(
+            tasks = scan.tasks.len(),
+            upgraded = scan.upgraded,
+            quarantined = scan.quarantined,
+            newer = scan.newer,
+            "tasks loaded"
+        ),
+        Err(err) => panic!("cant migrate tasks: {err}"),
+    }
+    match rzd_db.rebuild_indexes().await {
+        Ok(entries) => tracing::info!(entries, "task index entries rebuilt"),
+        Err(err) => panic!("cant rebuild task indexes: {err}"),
+    }
 
+    // Past startup only the task operations are handed out, maintenance stays here
+    let store: Arc<dyn TaskStore> = rzd_db.clone();
     let bot = Bot::from_env();
 
+    let metrics = Metrics::new();
+    let poll_interval = poll_interval_from_env(limits.min_poll_interval);
+    let health = Health::new(poll_interval);
+    let rzd_api = rzd::RZDApi::new(metrics.clone(), health.clone());
+    let poller_control = PollerControl::new();
 
-    let rzd_api = rzd::RZDApi::new();
+    let http_addr = env::var("HTTP_ADDR")
+        .unwrap_or_else(|_| DEFAULT_HTTP_ADDR.to_string())
+        .parse::<SocketAddr>()
+        .expect("invalid HTTP_ADDR");
+    tokio::spawn(server::serve(
+        http_addr,
+        metrics.clone(),
+        store.clone(),
+        health.clone(),
+    ));
+    let shutdown = Shutdown::new();
+    tokio::spawn(backups.clone().run(shutdown.clone()));
+    tokio::spawn(
+        expiry::Sweeper::new(bot.clone(), store.clone(), metrics.clone()).run(shutdown.clone()),
+    );
+    let mut poller = tokio::spawn(
+        Poller::new(
+            bot.clone(),
+            rzd_api.clone(),
+            store.clone(),
+            metrics.clone(),
+            health.clone(),
+            poller_control.clone(),
+            poll_interval,
+        )
+        .run(shutdown.clone()),
+    );
 
-    log::info!("bot is starting");
-    Dispatcher::builder(bot, schema())
-        .dependencies(dptree::deps![InMemStorage::<State>::new(), rzd_api, rzd_db])
-        .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+    tracing::info!("bot is starting");
+    let mut dispatcher = Dispatcher::builder(bot, schema())
+        .dependencies(dptree::deps![
+            InMemStorage::<State>::new(),
+            rzd_api,
+            store.clone(),
+            metrics,
+            health,
+            poller_control,
+            backups,
+            Admins::from_env(),
+            AccessMode::from_env()
+        ])
+        .build();
+    let dispatcher_token = dispatcher.shutdown_token();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            wait_for_signal().await;
+            shutdown.trigger();
+            // Stops fetching updates, the dispatcher still waits for the handlers in progress
+            if dispatcher_token.shutdown().is_err() {
+                tracing::warn!("dispatcher is not running yet");
+            }
+        }
+    });
+    tokio::select! {
+        _ = dispatcher.dispatch() => {}
+        _ = shutdown.expired() => tracing::warn!("handlers did not finish in time, cancelling them"),
+    }
+
+    shutdown.trigger();
+    if tokio::time::timeout_at(shutdown.deadline(), &mut poller)
+        .await
+        .is_err()
+    {
+        // Notifications of the cycle stay queued in the db and are sent after the restart
+        tracing::warn!("poller did not stop in time, cancelling it");
+        poller.abort();
+    }
+    if let Err(err) = rzd_db.flush().await {
+        tracing::error!(error = %err, "cant flush db");
+    }
+    tracing::info!("bot is stopped");
 }
 
 fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
@@ -112,12 +338,20 @@ fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
     let command_handler = teloxide::filter_command::<Command, _>()
         .branch(case![Command::Start].endpoint(start))
         .branch(case![Command::Cancel].endpoint(cancel))
+        .branch(case![Command::Tasks].endpoint(tasks))
+        .branch(case![Command::Search(text)].endpoint(quick_search))
+        .branch(case![Command::Watch(text)].endpoint(quick_watch))
+        .branch(case![Command::Lang(text)].endpoint(set_lang))
+        .branch(case![Command::Ttl(text)].endpoint(set_ttl))
+        .branch(case![Command::Export].endpoint(export_tasks))
+        .branch(case![Command::Import].endpoint(import_tasks))
         .branch(case![Command::Niggers].endpoint(niggers))
         .branch(case![Command::Dimok].endpoint(dimok))
         .branch(case![Command::Ss].endpoint(ss));
 
     let message_handler = Update::filter_message()
         .branch(command_handler)
+        .branch(admin_handler())
         .branch(case![State::ReceiveFromPoint].endpoint(receive_from_point))
         .branch(case![State::ReceiveToPoint { from_point_code }].endpoint(receive_to_point))
         .branch(
@@ -127,68 +361,176 @@ fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
             }]
             .endpoint(receive_date),
         )
-        .branch(case![State::ChooseTrain { trains }].endpoint(receive_train_idx));
+        .branch(case![State::ChooseTrain { date, trains }].endpoint(receive_train_idx))
+        .branch(case![State::EditTask { task_id, edit }].endpoint(receive_task_edit))
+        .branch(case![State::ImportTasks].endpoint(receive_import));
 
     let callback_query_handler = Update::filter_callback_query()
+        .branch(dptree::filter(is_history_callback).endpoint(task_history))
+        .branch(dptree::filter(is_task_action_callback).endpoint(task_action))
         .branch(case![State::ChooseService].endpoint(choose_service))
         .branch(case![State::ChooseRZDService].endpoint(choose_rzd_service))
-        .branch(case![State::ChooseFromPointCode].endpoint(choose_from_point_code))
-        .branch(case![State::ChooseToPointCode { from_point_code }].endpoint(choose_to_point_code))
-        .branch(case![State::ChooseTrain { trains }].endpoint(poll_day));
+        .branch(case![State::ReceiveFromPoint].endpoint(choose_from_quick_pick))
+        .branch(case![State::ReceiveToPoint { from_point_code }].endpoint(choose_to_quick_pick))
+        .branch(
+            case![State::DeleteTask]
+                .branch(dptree::filter(is_page_callback).endpoint(tasks_page))
+                .branch(dptree::endpoint(delete_task)),
+        )
+        .branch(
+            case![State::ChooseFromPointCode { codes }]
+                .branch(dptree::filter(is_page_callback).endpoint(from_points_page))
+                .branch(dptree::endpoint(choose_from_point_code)),
+        )
+        .branch(
+            case![State::ChooseToPointCode {
+                from_point_code,
+                codes
+            }]
+            .branch(dptree::filter(is_page_callback).endpoint(to_points_page))
+            .branch(dptree::endpoint(choose_to_point_code)),
+        )
+        .branch(
+            case![State::ChooseTrain { date, trains }]
+                .branch(dptree::filter(is_page_callback).endpoint(trains_page))
+                .branch(dptree::endpoint(poll_day)),
+        )
+        .branch(
+            case![State::ChooseCarriage { train, places }]
+                .branch(dptree::filter(is_page_callback).endpoint(carriages_page))
+                .branch(dptree::endpoint(poll_train)),
+        )
+        .branch(case![State::ConfirmImport { tasks }].endpoint(confirm_import))
+        .branch(
+            case![State::ChooseQuickStation { query, codes }]
+                .branch(dptree::filter(is_page_callback).endpoint(quick_stations_page))
+                .branch(dptree::endpoint(choose_quick_station)),
+        );
+
+    let inline_query_handler = Update::filter_inline_query()
+        .inspect(|metrics: Arc<Metrics>| {
+            metrics.inc(HANDLER_INVOCATIONS, &[("state", "inline_query")])
+        })
+        .endpoint(inline_query);
+
+    update_span()
+        .inspect(|health: Arc<Health>| health.mark_update())
+        .map_async(resolve_lang)
+        .branch(access_handler())
+        .branch(inline_query_handler)
+        .branch(
+            dialogue::enter::<Update, InMemStorage<State>, State, _>()
+                .inspect(|state: State, metrics: Arc<Metrics>| {
+                    tracing::Span::current().record("state", state.name());
+                    metrics.inc(HANDLER_INVOCATIONS, &[("state", state.name())])
+                })
+                .branch(message_handler)
+                .branch(callback_query_handler),
+        )
+}
 
-    dialogue::enter::<Update, InMemStorage<State>, State, _>()
-        .branch(message_handler)
-        .branch(callback_query_handler)
+/// Language of the user who sent the update, `/lang` overrides telegram's `language_code`.
+async fn resolve_lang(update: Update, rzd_db: Arc<dyn TaskStore>) -> Lang {
+    let user = match update.user() {
+        Some(user) => user,
+        None => return Lang::default(),
+    };
+    let default_lang = Lang::from_language_code(user.language_code.as_deref());
+    match rzd_db.get_user_lang(user.id.0).await {
+        Ok(lang) => lang.unwrap_or(default_lang),
+        Err(err) => {
+            tracing::warn!(user_id = user.id.0, error = %err, "cant get user language");
+            default_lang
+        }
+    }
 }
 
-async fn start(bot: Bot, dialogue: RZDDialogue, msg: Message) -> HandlerResult {
-    bot.send_message(msg.chat.id, "Выберите сервис").reply_markup(make_start_keyboard()).await?;
+async fn start(bot: Bot, dialogue: RZDDialogue, lang: Lang, msg: Message) -> HandlerResult {
+    bot.send_message(msg.chat.id, lang.t(Key::ChooseService))
+        .reply_markup(make_start_keyboard(lang))
+        .await?;
     dialogue.update(State::ChooseService).await?;
     Ok(())
 }
 
-async fn choose_service(bot: Bot, dialogue: RZDDialogue, q: CallbackQuery) -> HandlerResult {
+async fn choose_service(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    lang: Lang,
+    q: CallbackQuery,
+) -> HandlerResult {
     bot.answer_callback_query(q.id.clone()).await?;
     if let Some(code) = &q.data {
         match code.as_str() {
             "rzd" => {
-                bot.send_message(q.chat_id().unwrap(), "Выберите действи").reply_markup(make_rzd_start_keyboard()).await?;
+                bot.send_message(q.chat_id().unwrap(), lang.t(Key::ChooseAction))
+                    .reply_markup(make_rzd_start_keyboard(lang))
+                    .await?;
                 dialogue.update(State::ChooseRZDService).await?;
             }
             &_ => {
-                bot.send_message(q.chat_id().unwrap(), "Неизвестный сервис").await?;
+                bot.send_message(q.chat_id().unwrap(), lang.t(Key::UnknownService))
+                    .await?;
             }
         }
     }
     Ok(())
 }
 
-async fn choose_rzd_service(bot: Bot, dialogue: RZDDialogue, q: CallbackQuery) -> HandlerResult {
+async fn choose_rzd_service(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    q: CallbackQuery,
+) -> HandlerResult {
     bot.answer_callback_query(q.id.clone()).await?;
     if let Some(code) = &q.data {
         match code.as_str() {
             "rzd_search" => {
-                bot.send_message(q.chat_id().unwrap(), "Напишите точку отправления").await?;
+                let favourites = rzd_db
+                    .list_favourite_stations(q.from.id.0)
+                    .await
+                    .unwrap_or_else(|err| {
+                        tracing::warn!(user_id = q.from.id.0, error = %err, "cant get favourite stations");
+                        Vec::new()
+                    });
+                let recent_routes =
+                    rzd_db
+                        .list_recent_routes(q.from.id.0)
+                        .await
+                        .unwrap_or_else(|err| {
+                            tracing::warn!(user_id = q.from.id.0, error = %err, "cant get recent routes");
+                            Vec::new()
+                        });
+                bot.send_message(q.chat_id().unwrap(), lang.t(Key::EnterFromPoint))
+                    .reply_markup(make_quick_pick_keyboard(&favourites, &recent_routes))
+                    .await?;
                 dialogue.update(State::ReceiveFromPoint).await?;
             }
             "rzd_tasks" => {
-
+                show_tasks(&bot, &dialogue, &*rzd_db, lang, q.chat_id().unwrap()).await?;
             }
             "rzd_return" => {
-                bot.send_message(q.chat_id().unwrap(), "Выберите сервис").reply_markup(make_start_keyboard()).await?;
+                bot.send_message(q.chat_id().unwrap(), lang.t(Key::ChooseService))
+                    .reply_markup(make_start_keyboard(lang))
+                    .await?;
                 dialogue.update(State::ChooseService).await?;
             }
             &_ => {
-                bot.send_message(q.chat_id().unwrap(), "Неизвестный сервис").await?;
+                bot.send_message(q.chat_id().unwrap(), lang.t(Key::UnknownService))
+                    .await?;
             }
         }
     }
     Ok(())
 }
 
-async fn cancel(bot: Bot, dialogue: RZDDialogue, msg: Message) -> HandlerResult {
-    bot.send_message(msg.chat.id, "You canceled it").await?;
-    bot.send_message(msg.chat.id, "Выберите сервис").reply_markup(make_start_keyboard()).await?;
+async fn cancel(bot: Bot, dialogue: RZDDialogue, lang: Lang, msg: Message) -> HandlerResult {
+    bot.send_message(msg.chat.id, lang.t(Key::Canceled)).await?;
+    bot.send_message(msg.chat.id, lang.t(Key::ChooseService))
+        .reply_markup(make_start_keyboard(lang))
+        .await?;
     dialogue.update(State::ChooseService).await?;
     Ok(())
 }
@@ -196,59 +538,330 @@ async fn cancel(bot: Bot, dialogue: RZDDialogue, msg: Message) -> HandlerResult
 async fn tasks(
     bot: Bot,
     dialogue: RZDDialogue,
-    rzd_db: Arc<RZDDb>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
     msg: Message,
 ) -> HandlerResult {
-    let tasks = rzd_db.list_tasks().await;
-    match tasks {
+    show_tasks(&bot, &dialogue, &*rzd_db, lang, msg.chat.id).await
+}
+
+async fn show_tasks(
+    bot: &Bot,
+    dialogue: &RZDDialogue,
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    chat_id: ChatId,
+) -> HandlerResult {
+    match rzd_db.list_chat_tasks(chat_id.0).await {
         Ok(tasks) => {
-            if tasks.len() == 0 {
-                bot.send_message(msg.chat.id, "Нет задач".to_string())
-                    .await?;
+            if tasks.is_empty() {
+                bot.send_message(chat_id, lang.t(Key::NoTasks)).await?;
             } else {
-                for task in tasks.iter() {
-                    let text: String;
-                    match task.1.get("type").unwrap_or(&"".to_string()).as_str() {
-                        "day" => {
-                            text = format!("Проверка конкретного дня:\nId: {}\nКод пункта отправления: {}\nКод пункта прибытия: {}\nДата: {}", task.0, task.1.get("from_point_code").unwrap_or(&"UNKNOWN".to_string()), task.1.get("to_point_code").unwrap_or(&"UNKNOWN".to_string()), task.1.get("date").unwrap_or(&"UNKNOWN".to_string()));
-                        }
-                        "train" => {
-                            text = String::from("Проверка конкретного поезда:\nКод пункта отправления: {}\nКод пункта прибытия: {}\nДата отправления: {}\nВремя отправления: {}\n Номер поезда отправления: {}");
-                            // Переделать под получение самого населенного пункта
-                        }
-                        _ => {
-                            text = format!("Неизвестный тип задачи:\nId: {}", task.0);
-                        }
-                    }
-                    bot.send_message(msg.chat.id, text).reply_markup(InlineKeyboardMarkup::default().append_row([InlineKeyboardButton::callback(
-                        "Удалить задачу",
-                        task.0,
-                    )])).await?;
-                }
+                bot.send_message(chat_id, format_tasks_page(&tasks, 0, lang))
+                    .reply_markup(make_tasks_keyboard(&tasks, 0, lang))
+                    .await?;
                 dialogue.update(State::DeleteTask).await?;
             }
         }
         Err(err) => {
-            bot.send_message(msg.chat.id, format!("Error on getting tasks {:?}", err))
+            bot.send_message(chat_id, lang.tf(Key::TasksError, &[("err", &err)]))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn tasks_page(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(page) = q.data.as_deref().and_then(parse_page) {
+        let tasks = match rzd_db.list_chat_tasks(q.chat_id().unwrap().0).await {
+            Ok(tasks) => tasks,
+            Err(err) => {
+                bot.send_message(
+                    q.chat_id().unwrap(),
+                    lang.tf(Key::TasksError, &[("err", &err)]),
+                )
+                .await?;
+                return Ok(());
+            }
+        };
+        if tasks.is_empty() {
+            edit_page(
+                &bot,
+                &q,
+                Some(lang.t(Key::NoTasks).to_string()),
+                Default::default(),
+            )
+            .await?;
+        } else {
+            edit_page(
+                &bot,
+                &q,
+                Some(format_tasks_page(&tasks, page, lang)),
+                make_tasks_keyboard(&tasks, page, lang),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the page shown in the message the pressed button belongs to.
+/// Without a text only the keyboard is replaced.
+async fn edit_page(
+    bot: &Bot,
+    q: &CallbackQuery,
+    text: Option<String>,
+    reply_markup: InlineKeyboardMarkup,
+) -> HandlerResult {
+    if let Some(message) = &q.message {
+        match text {
+            Some(text) => {
+                bot.edit_message_text(message.chat.id, message.id, text)
+                    .reply_markup(reply_markup)
+                    .await?;
+            }
+            None => {
+                bot.edit_message_reply_markup(message.chat.id, message.id)
+                    .reply_markup(reply_markup)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_history_callback(q: CallbackQuery) -> bool {
+    q.data.as_deref().and_then(parse_history_callback).is_some()
+}
+
+/// Notification history of a task from the task list, works after the dialogue moved on.
+async fn task_history(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    let (task_id, page) = match q.data.as_deref().and_then(parse_history_callback) {
+        Some(callback) => callback,
+        None => return Ok(()),
+    };
+    let chat_id = q.chat_id().unwrap();
+    // Only the chat the task notifies may read its history
+    let history = match rzd_db.get_task(task_id).await {
+        Ok(Some(task)) if task.get("chat_id") == Some(&chat_id.to_string()) => {
+            rzd_db.list_notification_history(task_id).await
+        }
+        Ok(_) => Ok(vec![]),
+        Err(err) => Err(err),
+    };
+    let history = match history {
+        Ok(history) => history,
+        Err(err) => {
+            bot.send_message(chat_id, lang.tf(Key::TasksError, &[("err", &err)]))
+                .await?;
+            return Ok(());
+        }
+    };
+    if history.is_empty() {
+        bot.send_message(chat_id, lang.t(Key::NoNotificationHistory))
+            .await?;
+        return Ok(());
+    }
+    let text = truncate_message(&format_history_page(
+        &history,
+        page.unwrap_or_default(),
+        lang,
+    ));
+    let keyboard = make_history_keyboard(task_id, &history, page.unwrap_or_default());
+    if page.is_some() {
+        edit_page(&bot, &q, Some(text), keyboard).await?;
+    } else {
+        bot.send_message(chat_id, text)
+            .reply_markup(keyboard)
+            .await?;
+    }
+    Ok(())
+}
+
+fn is_task_action_callback(q: CallbackQuery) -> bool {
+    q.data.as_deref().and_then(parse_task_action).is_some()
+}
+
+/// The task if the chat is the one it notifies, only that chat may change it.
+async fn chat_task(
+    bot: &Bot,
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    chat_id: ChatId,
+    task_id: &str,
+) -> Result<Option<HashMap<String, String>>, teloxide::RequestError> {
+    match rzd_db.get_task(task_id).await {
+        Ok(Some(task)) if task.get("chat_id") == Some(&chat_id.to_string()) => Ok(Some(task)),
+        Ok(_) => {
+            bot.send_message(chat_id, lang.t(Key::TaskNotFound)).await?;
+            Ok(None)
+        }
+        Err(err) => {
+            bot.send_message(chat_id, lang.tf(Key::TasksError, &[("err", &err)]))
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Task menu from the task list, works after the dialogue moved on.
+async fn task_action(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    let (action, task_id) = match q.data.as_deref().and_then(parse_task_action) {
+        Some(callback) => callback,
+        None => return Ok(()),
+    };
+    let chat_id = q.chat_id().unwrap();
+    let mut task = match chat_task(&bot, &*rzd_db, lang, chat_id, task_id).await? {
+        Some(task) => task,
+        None => return Ok(()),
+    };
+    match action {
+        TaskAction::Menu => {
+            bot.send_message(chat_id, format_task_menu(task_id, &task, lang))
+                .reply_markup(make_task_menu_keyboard(task_id, &task, lang))
+                .await?;
+        }
+        TaskAction::Pause | TaskAction::Resume => {
+            set_paused(&mut task, action == TaskAction::Pause);
+            match rzd_db.put_task(task_id, &task).await {
+                Ok(()) => {
+                    edit_page(
+                        &bot,
+                        &q,
+                        Some(format_task_menu(task_id, &task, lang)),
+                        make_task_menu_keyboard(task_id, &task, lang),
+                    )
+                    .await?;
+                }
+                Err(err) => {
+                    bot.send_message(chat_id, lang.tf(Key::TaskUpdateError, &[("err", &err)]))
+                        .await?;
+                }
+            }
+        }
+        TaskAction::Edit(edit) => {
+            bot.send_message(chat_id, lang.t(edit.prompt())).await?;
+            dialogue
+                .update(State::EditTask {
+                    task_id: task_id.to_string(),
+                    edit,
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// New value for the task field chosen in the task menu. A wrong value is asked again.
+async fn receive_task_edit(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    msg: Message,
+    (task_id, edit): (String, TaskEdit),
+) -> HandlerResult {
+    let text = match msg.text() {
+        Some(text) => text,
+        None => {
+            bot.send_message(msg.chat.id, lang.t(Key::SendPlainText))
+                .await?;
+            return Ok(());
+        }
+    };
+    let task = match chat_task(&bot, &*rzd_db, lang, msg.chat.id, &task_id).await? {
+        Some(task) => task,
+        None => {
+            dialogue.reset().await?;
+            return Ok(());
+        }
+    };
+    let edited = match apply_edit(&task, edit, text, Local::now().date_naive()) {
+        Ok(edited) => edited,
+        Err(err) => {
+            bot.send_message(msg.chat.id, err.to_text(lang)).await?;
+            return Ok(());
+        }
+    };
+    if edit == TaskEdit::Clone {
+        match rzd_db.create_task(edited.clone()).await {
+            Ok(id) => {
+                bot.send_message(
+                    msg.chat.id,
+                    lang.tf(
+                        Key::TaskCloned,
+                        &[("task", &format_task(&id, &edited, lang))],
+                    ),
+                )
+                .await?;
+            }
+            Err(err) => {
+                bot.send_message(msg.chat.id, lang.tf(Key::TaskCreateError, &[("err", &err)]))
+                    .await?;
+            }
+        }
+    } else {
+        match rzd_db.update_task(&task_id, edited.clone()).await {
+            Ok(()) => {
+                bot.send_message(
+                    msg.chat.id,
+                    lang.tf(
+                        Key::TaskUpdated,
+                        &[("task", &format_task_menu(&task_id, &edited, lang))],
+                    ),
+                )
                 .await?;
+            }
+            Err(err) => {
+                bot.send_message(msg.chat.id, lang.tf(Key::TaskUpdateError, &[("err", &err)]))
+                    .await?;
+            }
         }
     }
+    dialogue.reset().await?;
     Ok(())
 }
 
 async fn delete_task(
     bot: Bot,
     dialogue: RZDDialogue,
-    rzd_db: Arc<RZDDb>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
     q: CallbackQuery,
 ) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
     if let Some(id) = &q.data {
+        let chat_id = q.chat_id().unwrap();
+        if chat_task(&bot, &*rzd_db, lang, chat_id, id).await?.is_none() {
+            return Ok(());
+        }
         match rzd_db.delete_task_by_id(id.to_string()).await {
             Ok(id) => {
-                bot.send_message(q.chat_id().unwrap(), format!("Удалена задача с id: {id}")).await?;
+                bot.send_message(chat_id, lang.tf(Key::TaskDeleted, &[("task_id", &id)]))
+                    .await?;
             }
             Err(err) => {
-                bot.send_message(q.chat_id().unwrap(),format!("Ошибка при удалении задачи: {err}")).await?;
+                bot.send_message(chat_id, lang.tf(Key::TaskDeleteError, &[("err", &err)]))
+                    .await?;
             }
         }
     }
@@ -259,6 +872,8 @@ async fn receive_from_point(
     bot: Bot,
     dialogue: RZDDialogue,
     rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
     msg: Message,
 ) -> HandlerResult {
     match msg.text() {
@@ -266,32 +881,28 @@ async fn receive_from_point(
             let codes = rzd_api.get_rzd_point_codes(text.into(), 5).await;
             match codes {
                 Ok(codes) => {
-                    let mut reply_markup = InlineKeyboardMarkup::default();
-                    for code in codes.iter().clone() {
-                        reply_markup =
-                            reply_markup
-                                .clone()
-                                .append_row([InlineKeyboardButton::callback(
-                                    code.name.clone(),
-                                    code.code.clone(),
-                                )]);
+                    if let Err(err) = rzd_db.remember_stations(&codes).await {
+                        tracing::warn!(error = %err, "cant remember stations");
                     }
-                    bot.send_message(msg.chat.id, "Выбери точку отправления")
-                        .reply_markup(reply_markup)
+                    bot.send_message(msg.chat.id, lang.t(Key::ChooseFromPoint))
+                        .reply_markup(make_stations_keyboard(&codes, 0))
+                        .await?;
+                    dialogue
+                        .update(State::ChooseFromPointCode { codes })
                         .await?;
-                    dialogue.update(State::ChooseFromPointCode {}).await?;
                 }
                 Err(err) => {
                     bot.send_message(
                         msg.chat.id,
-                        format!("Ошибка во время получения кодов отправления {}", err),
+                        lang.tf(Key::FromCodesError, &[("err", &err)]),
                     )
                     .await?;
                 }
             }
         }
         None => {
-            bot.send_message(msg.chat.id, "Отправь мне обычный текст").await?;
+            bot.send_message(msg.chat.id, lang.t(Key::SendPlainText))
+                .await?;
         }
     }
 
@@ -301,11 +912,16 @@ async fn receive_from_point(
 async fn choose_from_point_code(
     bot: Bot,
     dialogue: RZDDialogue,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
     q: CallbackQuery,
 ) -> HandlerResult {
+    if let Some(code) = q.data.as_deref().and_then(|data| data.strip_prefix("fav_")) {
+        return toggle_favourite_station(bot, rzd_db, lang, &q, code).await;
+    }
     bot.answer_callback_query(q.id.clone()).await?;
     if let Some(code) = &q.data {
-        bot.send_message(q.chat_id().unwrap(), "Напишите точку прибытия").await?;
+        send_to_point_prompt(&bot, &*rzd_db, lang, &q).await?;
         dialogue
             .update(State::ReceiveToPoint {
                 from_point_code: code.into(),
@@ -315,10 +931,95 @@ async fn choose_from_point_code(
     Ok(())
 }
 
+async fn from_points_page(
+    bot: Bot,
+    codes: Vec<GetRZDPointCodes>,
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(page) = q.data.as_deref().and_then(parse_page) {
+        edit_page(&bot, &q, None, make_stations_keyboard(&codes, page)).await?;
+    }
+    Ok(())
+}
+
+async fn choose_from_quick_pick(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(data) = &q.data {
+        if let Some(code) = data.strip_prefix("station_") {
+            send_to_point_prompt(&bot, &*rzd_db, lang, &q).await?;
+            dialogue
+                .update(State::ReceiveToPoint {
+                    from_point_code: code.into(),
+                })
+                .await?;
+        } else if let Some(route) = data.strip_prefix("route_") {
+            let splitted_data = route.split('_').collect::<Vec<&str>>();
+            if splitted_data.len() != 2 {
+                bot.send_message(q.chat_id().unwrap(), lang.t(Key::InvalidCallback))
+                    .await?;
+                return Ok(());
+            }
+            bot.send_message(q.chat_id().unwrap(), lang.t(Key::EnterDate))
+                .await?;
+            dialogue
+                .update(State::ReceiveDate {
+                    from_point_code: splitted_data[0].into(),
+                    to_point_code: splitted_data[1].into(),
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn send_to_point_prompt(
+    bot: &Bot,
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    q: &CallbackQuery,
+) -> HandlerResult {
+    let favourites = rzd_db
+        .list_favourite_stations(q.from.id.0)
+        .await
+        .unwrap_or_else(|err| {
+            tracing::warn!(user_id = q.from.id.0, error = %err, "cant get favourite stations");
+            Vec::new()
+        });
+    bot.send_message(q.chat_id().unwrap(), lang.t(Key::EnterToPoint))
+        .reply_markup(make_quick_pick_keyboard(&favourites, &[]))
+        .await?;
+    Ok(())
+}
+
+async fn toggle_favourite_station(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    q: &CallbackQuery,
+    code: &str,
+) -> HandlerResult {
+    let text = match rzd_db.toggle_favourite_station(q.from.id.0, code).await {
+        Ok(true) => lang.t(Key::FavouriteAdded).to_string(),
+        Ok(false) => lang.t(Key::FavouriteRemoved).to_string(),
+        Err(err) => lang.tf(Key::FavouriteError, &[("err", &err)]),
+    };
+    bot.answer_callback_query(q.id.clone()).text(text).await?;
+    Ok(())
+}
+
 async fn receive_to_point(
     bot: Bot,
     dialogue: RZDDialogue,
     rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
     from_point_code: String,
     msg: Message,
 ) -> HandlerResult {
@@ -327,34 +1028,31 @@ async fn receive_to_point(
             let codes = rzd_api.get_rzd_point_codes(text.into(), 5).await;
             match codes {
                 Ok(codes) => {
-                    let mut reply_markup = InlineKeyboardMarkup::default();
-                    for code in codes.iter().clone() {
-                        reply_markup =
-                            reply_markup
-                                .clone()
-                                .append_row([InlineKeyboardButton::callback(
-                                    code.name.clone(),
-                                    code.code.clone(),
-                                )]);
+                    if let Err(err) = rzd_db.remember_stations(&codes).await {
+                        tracing::warn!(error = %err, "cant remember stations");
                     }
-                    bot.send_message(msg.chat.id, "Выбери точку прибытия")
-                        .reply_markup(reply_markup)
+                    bot.send_message(msg.chat.id, lang.t(Key::ChooseToPoint))
+                        .reply_markup(make_stations_keyboard(&codes, 0))
                         .await?;
                     dialogue
-                        .update(State::ChooseToPointCode { from_point_code })
+                        .update(State::ChooseToPointCode {
+                            from_point_code,
+                            codes,
+                        })
                         .await?;
                 }
                 Err(err) => {
                     bot.send_message(
                         msg.chat.id,
-                        format!("Ошибка во время получения кодов прибытия {}", err),
+                        lang.tf(Key::ToCodesError, &[("err", &err)]),
                     )
                     .await?;
                 }
             }
         }
         None => {
-            bot.send_message(msg.chat.id, "Отправь мне обычный текст").await?;
+            bot.send_message(msg.chat.id, lang.t(Key::SendPlainText))
+                .await?;
         }
     }
 
@@ -364,12 +1062,17 @@ async fn receive_to_point(
 async fn choose_to_point_code(
     bot: Bot,
     dialogue: RZDDialogue,
-    from_point_code: String,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    (from_point_code, _): (String, Vec<GetRZDPointCodes>),
     q: CallbackQuery,
 ) -> HandlerResult {
+    if let Some(code) = q.data.as_deref().and_then(|data| data.strip_prefix("fav_")) {
+        return toggle_favourite_station(bot, rzd_db, lang, &q, code).await;
+    }
     bot.answer_callback_query(q.clone().id).await?;
     if let Some(code) = &q.data {
-        bot.send_message(q.chat_id().unwrap(), "Напиши мне дату в формате (день.месяц.год)")
+        bot.send_message(q.chat_id().unwrap(), lang.t(Key::EnterDate))
             .await?;
         dialogue
             .update(State::ReceiveDate {
@@ -381,93 +1084,73 @@ async fn choose_to_point_code(
     Ok(())
 }
 
-async fn receive_date(
+async fn to_points_page(
+    bot: Bot,
+    (_, codes): (String, Vec<GetRZDPointCodes>),
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(page) = q.data.as_deref().and_then(parse_page) {
+        edit_page(&bot, &q, None, make_stations_keyboard(&codes, page)).await?;
+    }
+    Ok(())
+}
+
+async fn choose_to_quick_pick(
     bot: Bot,
     dialogue: RZDDialogue,
-    rzd_api: Arc<RZDApi>,
-    (from_point_code, to_point_code): (String, String),
-    msg: Message,
+    lang: Lang,
+    from_point_code: String,
+    q: CallbackQuery,
 ) -> HandlerResult {
-    match msg.text() {
-        Some(date) => {
-            let date = NaiveDate::parse_from_str(date, "%d.%m.%Y");
-            match date {
-                Ok(date) => {
-                    // TODO check if date not less than now
-                    let trains = rzd_api
-                        .get_trains_from_rzd(
-                            from_point_code.clone(),
-                            to_point_code.clone(),
-                            date.format("%d.%m.%Y").to_string(),
-                            5,
-                        )
-                        .await;
-                    match trains {
-                        Ok(trains) => {
-                            let mut trains_state: Vec<Train> = Vec::new();
-                            let mut message_text: String = String::new();
-                            let mut idx_counter = 1;
-                            for train in trains.tp[0].list.iter() {
-                                let mut cupe_count_type = 0;
-                                for car in train.cars.iter() {
-                                    if car._type.to_lowercase() == CUPE_TYPE && !car.disabled_person
-                                    {
-                                        cupe_count_type += car.free_seats
-                                    }
-                                }
-                                if cupe_count_type == 0 {
-                                    continue;
-                                }
-                                trains_state.push(Train {
-                                    code0: from_point_code.clone(),
-                                    code1: to_point_code.clone(),
-                                    dt0: train.date0.clone(),
-                                    time0: train.time0.clone(),
-                                    tnum0: train.number.clone(),
-                                });
-                                message_text.push_str(format!("{0}. Поезд: {1}\nДата отбытия: {2} \nВремя отбытия: {3}\nКоличество свободных мест в купе: {cupe_count_type}\n", idx_counter, train.number, train.date0, train.time0).as_str());
-                                idx_counter += 1;
-                            }
-                            if message_text.is_empty() {
-                                bot.send_message(msg.chat.id, "Не найдено. Пожалуйста, напиши /start чтобы заново начать. Текущий диалог сброшен").await?;
-                                dialogue.reset().await?;
-                            } else {
-                                let mut reply_markup = InlineKeyboardMarkup::default();
-                                reply_markup = reply_markup.clone().append_row([
-                                    InlineKeyboardButton::callback(
-                                        "Проверять этот день",
-                                        format!(
-                                            "{from_point_code}_{to_point_code}_{}",
-                                            date.format("%d.%m.%Y")
-                                        ),
-                                    ),
-                                ]);
-                                bot.send_message(msg.chat.id, message_text)
-                                    .reply_markup(reply_markup)
-                                    .await?;
-                                dialogue
-                                    .update(State::ChooseTrain {
-                                        trains: trains_state,
-                                    })
-                                    .await?;
-                            }
-                        }
-                        Err(err) => {
-                            bot.send_message(
-                                msg.chat.id,
-                                format!(
-                                    "Error on getting rzd trains {err}. Current dialogue canceled"
-                                ),
-                            )
-                            .await?;
-                            dialogue.reset().await?;
-                        }
-                    }
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(code) = q
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix("station_"))
+    {
+        bot.send_message(q.chat_id().unwrap(), lang.t(Key::EnterDate))
+            .await?;
+        dialogue
+            .update(State::ReceiveDate {
+                from_point_code,
+                to_point_code: code.into(),
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+async fn receive_date(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    (from_point_code, to_point_code): (String, String),
+    msg: Message,
+) -> HandlerResult {
+    match msg.text() {
+        Some(date) => {
+            let date = NaiveDate::parse_from_str(date, "%d.%m.%Y");
+            match date {
+                Ok(date) => {
+                    // TODO check if date not less than now
+                    search_trains(
+                        &bot,
+                        &dialogue,
+                        &rzd_api,
+                        &*rzd_db,
+                        lang,
+                        msg.from().map(|user| user.id),
+                        (from_point_code, to_point_code, date),
+                    )
+                    .await?;
                 }
                 Err(err) => {
                     bot.send_message(
                         msg.chat.id,
-                        format!("Ошибка во время парсинга даты {}. Текущий диалог сброшен", err),
+                        lang.tf(Key::DateParseError, &[("err", &err)]),
                     )
                     .await?;
                     dialogue.reset().await?;
@@ -475,32 +1158,114 @@ async fn receive_date(
             }
         }
         None => {
-            bot.send_message(msg.chat.id, "Отправь мне обычный текст").await?;
+            bot.send_message(msg.chat.id, lang.t(Key::SendPlainText))
+                .await?;
         }
     }
 
     Ok(())
 }
 
+async fn search_trains(
+    bot: &Bot,
+    dialogue: &RZDDialogue,
+    rzd_api: &RZDApi,
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    user_id: Option<UserId>,
+    (from_point_code, to_point_code, date): (String, String, NaiveDate),
+) -> HandlerResult {
+    let chat_id = dialogue.chat_id();
+    if let Some(user_id) = user_id {
+        if let Some(text) = search_limit_exceeded(rzd_db, lang, user_id).await {
+            bot.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+    }
+    let trains = rzd_api
+        .get_trains_from_rzd(
+            from_point_code.clone(),
+            to_point_code.clone(),
+            date.format("%d.%m.%Y").to_string(),
+            5,
+        )
+        .await;
+    match trains {
+        Ok(trains) => {
+            if let Some(user_id) = user_id {
+                if let Err(err) = rzd_db
+                    .push_recent_route(user_id.0, &from_point_code, &to_point_code)
+                    .await
+                {
+                    tracing::warn!(user_id = user_id.0, error = %err, "cant save recent route");
+                }
+            }
+            let mut trains_state: Vec<Train> = Vec::new();
+            for train in trains.tp[0].list.iter() {
+                let mut cupe_count_type = 0;
+                for car in train.cars.iter() {
+                    if car._type.to_lowercase() == CUPE_TYPE && !car.disabled_person {
+                        cupe_count_type += car.free_seats
+                    }
+                }
+                if cupe_count_type == 0 {
+                    continue;
+                }
+                trains_state.push(Train {
+                    code0: from_point_code.clone(),
+                    code1: to_point_code.clone(),
+                    dt0: train.date0.clone(),
+                    time0: train.time0.clone(),
+                    tnum0: train.number.clone(),
+                    cupe_free_seats: cupe_count_type,
+                });
+            }
+            if trains_state.is_empty() {
+                bot.send_message(chat_id, lang.t(Key::TrainsNotFound))
+                    .await?;
+                dialogue.reset().await?;
+            } else {
+                let date = date.format("%d.%m.%Y").to_string();
+                bot.send_message(chat_id, format_trains_page(&trains_state, 0, lang))
+                    .reply_markup(make_trains_keyboard(&trains_state, &date, 0, lang))
+                    .await?;
+                dialogue
+                    .update(State::ChooseTrain {
+                        date,
+                        trains: trains_state,
+                    })
+                    .await?;
+            }
+        }
+        Err(err) => {
+            bot.send_message(chat_id, lang.tf(Key::TrainsError, &[("err", &err)]))
+                .await?;
+            dialogue.reset().await?;
+        }
+    }
+    Ok(())
+}
+
 async fn receive_train_idx(
     bot: Bot,
     dialogue: RZDDialogue,
     rzd_api: Arc<RZDApi>,
-    trains: Vec<Train>,
+    lang: Lang,
+    (_, trains): (String, Vec<Train>),
     msg: Message,
 ) -> HandlerResult {
     match msg.text() {
         Some(idx) => {
             let idx: usize = idx.parse().unwrap_or(0);
             if idx == 0 {
-                bot.send_message(msg.chat.id, "Отрицательный индекс. Текущий диалог сброшен.")
+                bot.send_message(msg.chat.id, lang.t(Key::NegativeIndex))
                     .await?;
                 dialogue.reset().await?;
                 return Ok(());
             }
             let train = trains.get(idx - 1);
             if train.is_none() {
-                bot.send_message(msg.chat.id, "Неправильный индекс. Текущий диалог сброшен")
+                bot.send_message(msg.chat.id, lang.t(Key::WrongIndex))
                     .await?;
                 dialogue.reset().await?;
                 return Ok(());
@@ -518,132 +1283,183 @@ async fn receive_train_idx(
                 .await;
             match carriages {
                 Ok(v) => {
-                    let mut message_text: String = String::new();
-                    for car in v.lst[0].cars.iter() {
-                        if car._type.to_lowercase() != CUPE_TYPE {
-                            continue;
-                        }
-                        for place in car.places.iter() {
-                            let places_ref = place.split('-').collect::<Vec<&str>>();
-                            if places_ref.len() != 2 {
-                                continue;
-                            }
-                            let (start_place, end_place) = (
-                                places_ref[0]
-                                    .trim_end_matches(|c: char| c.is_alphabetic())
-                                    .parse::<isize>()
-                                    .unwrap(),
-                                places_ref[1]
-                                    .trim_end_matches(|c: char| c.is_alphabetic())
-                                    .parse::<isize>()
-                                    .unwrap(),
-                            );
-                            if start_place > end_place {
-                                log::warn!("start_place {} is greater than end_place {} with params code0 = {}, code1 = {}, dt0 = {}, time0 = {}, tnum0 = {}", start_place, end_place, train.code0, train.code1, train.dt0, train.time0, train.tnum0);
-                                continue;
-                            }
-                            for place_n in start_place..=end_place {
-                                //
-                                if place_n % 4 == 1 && end_place - place_n >= 3 {
-                                    // Blyat ya ne vspomnu cherez god logiku
-                                    message_text.push_str(
-                                        format!(
-                                            "Номер вагона: {}\nНомер мест: {} - {}\n",
-                                            car.cnumber,
-                                            place_n,
-                                            place_n + 3
-                                        )
-                                        .as_str(),
-                                    )
-                                }
-                            }
-                        }
-                    }
-                    let mut reply_markup = InlineKeyboardMarkup::default();
-                    reply_markup = reply_markup.clone().append_row([
-                        InlineKeyboardButton::callback(
-                            "Проверять этот поезд",
-                            format!(
-                                "{}_{}_{}_{}_{}",
-                                train.code0.clone(),
-                                train.code1.clone(),
-                                train.dt0.clone(),
-                                train.time0.clone(),
-                                train.tnum0.clone()
-                            ),
-                        ),
-                    ]);
-                    reply_markup = reply_markup.clone().append_row([
-                        InlineKeyboardButton::callback(
-                            "Не проверять этот поезд",
-                            "cancel"
-                        )
-                    ]);
-                    if message_text.is_empty() {
-                        bot.send_message(
-                            msg.chat.id,
-                            "Не найдено. Пожалуйста напишите /start , чтобы заново попробовать. Текущий диалог сброшен",
-                        ).reply_markup(reply_markup)
-                        .await?;
-                        dialogue.reset().await?;
+                    let places = find_free_compartments(&v)
+                        .into_iter()
+                        .map(|(car, place)| {
+                            lang.tf(
+                                Key::CarriagePlaces,
+                                &[("car", &car), ("from", &place), ("to", &(place + 3))],
+                            )
+                        })
+                        .collect::<Vec<String>>();
+                    let reply_markup = make_carriages_keyboard(train, &places, 0, lang);
+                    if places.is_empty() {
+                        bot.send_message(msg.chat.id, lang.t(Key::NoFreeCupe))
+                            .reply_markup(reply_markup)
+                            .await?;
                     } else {
-                        bot.send_message(msg.chat.id, message_text + "Текущий диалог сброшен").reply_markup(
-                            reply_markup
-                        )
+                        bot.send_message(msg.chat.id, format_carriages_page(&places, 0))
+                            .reply_markup(reply_markup)
                             .await?;
-                        dialogue.reset().await?;
                     }
+                    dialogue
+                        .update(State::ChooseCarriage {
+                            train: train.clone(),
+                            places,
+                        })
+                        .await?;
                 }
                 Err(err) => {
-                    bot.send_message(
-                        msg.chat.id,
-                        format!(
-                            "Error on getting rzd train carriages {err}. Current dialogue canceled"
-                        ),
-                    )
-                    .await?;
+                    bot.send_message(msg.chat.id, lang.tf(Key::CarriagesError, &[("err", &err)]))
+                        .await?;
                     dialogue.reset().await?;
                 }
             }
         }
         None => {
-            bot.send_message(msg.chat.id, "Отправь мне обычный текст").await?;
+            bot.send_message(msg.chat.id, lang.t(Key::SendPlainText))
+                .await?;
         }
     }
     Ok(())
 }
 
+async fn trains_page(
+    bot: Bot,
+    lang: Lang,
+    (date, trains): (String, Vec<Train>),
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(page) = q.data.as_deref().and_then(parse_page) {
+        edit_page(
+            &bot,
+            &q,
+            Some(format_trains_page(&trains, page, lang)),
+            make_trains_keyboard(&trains, &date, page, lang),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn carriages_page(
+    bot: Bot,
+    lang: Lang,
+    (train, places): (Train, Vec<String>),
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(page) = q.data.as_deref().and_then(parse_page) {
+        edit_page(
+            &bot,
+            &q,
+            Some(format_carriages_page(&places, page)),
+            make_carriages_keyboard(&train, &places, page, lang),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Who gets the poller notifications for the task and in which language.
+fn set_task_owner(
+    task: &mut HashMap<String, String>,
+    chat_id: ChatId,
+    user_id: Option<UserId>,
+    lang: Lang,
+) {
+    task.insert("chat_id".to_string(), chat_id.to_string());
+    if let Some(user_id) = user_id {
+        task.insert("user_id".to_string(), user_id.to_string());
+    }
+    task.insert("lang".to_string(), lang.code().to_string());
+}
+
+/// Message for the user if they already have as many tasks as their quota allows.
+/// `TaskStore::create_task` enforces the quota anyway, so failed checks only get logged.
+async fn task_quota_exceeded(
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    user_id: UserId,
+) -> Option<String> {
+    let max_tasks = match rzd_db.user_limits(user_id.0).await {
+        Ok(limits) if limits.max_tasks > 0 => limits.max_tasks,
+        Ok(_) => return None,
+        Err(err) => {
+            tracing::warn!(user_id = user_id.0, error = %err, "cant get user limits");
+            return None;
+        }
+    };
+    match rzd_db.count_user_tasks(user_id.0).await {
+        Ok(count) if count >= max_tasks => {
+            Some(lang.tf(Key::TaskLimitReached, &[("limit", &max_tasks)]))
+        }
+        Ok(_) => None,
+        Err(err) => {
+            tracing::warn!(user_id = user_id.0, error = %err, "cant count user tasks");
+            None
+        }
+    }
+}
+
+/// Message for the user if they searched trains too often during the last minute.
+async fn search_limit_exceeded(
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    user_id: UserId,
+) -> Option<String> {
+    match rzd_db.register_search(user_id.0).await {
+        Ok(Some(wait)) => Some(lang.tf(Key::SearchLimitReached, &[("secs", &wait)])),
+        Ok(None) => None,
+        Err(err) => {
+            tracing::warn!(user_id = user_id.0, error = %err, "cant register search");
+            None
+        }
+    }
+}
+
+fn day_task(from_point_code: &str, to_point_code: &str, date: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("from_point_code".to_string(), from_point_code.to_string()),
+        ("to_point_code".to_string(), to_point_code.to_string()),
+        ("date".to_string(), date.to_string()),
+        ("type".to_string(), "day".to_string()),
+    ])
+}
+
 async fn poll_day(
     bot: Bot,
     dialogue: RZDDialogue,
-    rzd_db: Arc<RZDDb>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
     q: CallbackQuery,
 ) -> HandlerResult {
     bot.answer_callback_query(q.id.clone()).await?;
     if let Some(data) = &q.data {
         let splitted_data = data.split('_').collect::<Vec<&str>>();
         if splitted_data.len() != 3 {
-            bot.send_message(q.chat_id().unwrap(), "Invalid length of callback")
+            bot.send_message(q.chat_id().unwrap(), lang.t(Key::InvalidCallback))
                 .await?;
+            return Ok(());
+        }
+        if let Some(text) = task_quota_exceeded(&*rzd_db, lang, q.from.id).await {
+            bot.send_message(q.chat_id().unwrap(), text).await?;
+            return Ok(());
         }
-        let created_task = rzd_db
-            .create_task(HashMap::from([
-                ("from_point_code".to_string(), splitted_data[0].to_string()),
-                ("to_point_code".to_string(), splitted_data[1].to_string()),
-                ("date".to_string(), splitted_data[2].to_string()),
-                ("type".to_string(), "day".to_string()),
-            ]))
-            .await;
+        let mut task = day_task(splitted_data[0], splitted_data[1], splitted_data[2]);
+        set_task_owner(&mut task, q.chat_id().unwrap(), Some(q.from.id), lang);
+        let created_task = rzd_db.create_task(task).await;
         match created_task {
             Ok(task_id) => {
                 bot.send_message(
                     q.chat_id().unwrap(),
-                    format!("Создана задача с уникальный номером {task_id}"),
+                    lang.tf(Key::TaskCreated, &[("task_id", &task_id)]),
                 )
                 .await?;
             }
             Err(err) => {
-                bot.send_message(q.chat_id().unwrap(), format!("Cant create task {err}"))
+                bot.send_message(q.chat_id().unwrap(), lang.tf(Key::TaskCreateError, &[("err", &err)]))
                     .await?;
             }
         }
@@ -654,42 +1470,613 @@ async fn poll_day(
 async fn poll_train(
     bot: Bot,
     dialogue: RZDDialogue,
-    rzd_db: Arc<RZDDb>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
     q: CallbackQuery,
 ) -> HandlerResult {
     bot.answer_callback_query(q.id.clone()).await?;
     if let Some(data) = &q.data {
         if data == "cancel" {
             dialogue.reset().await?;
-            bot.send_message(q.chat_id().unwrap(), "Текущий диалог сброшен").await?;
+            bot.send_message(q.chat_id().unwrap(), lang.t(Key::DialogueReset))
+                .await?;
+            return Ok(());
         }
         let splitted_data = data.split('_').collect::<Vec<&str>>();
         if splitted_data.len() != 5 {
-            bot.send_message(q.chat_id().unwrap(), "Invalid length of callback data")
+            bot.send_message(q.chat_id().unwrap(), lang.t(Key::InvalidCallback))
                 .await?;
+            return Ok(());
         }
-        let created_task = rzd_db
-            .create_task(HashMap::from([
-                ("from_point_code".to_string(), splitted_data[0].to_string()),
-                ("to_point_code".to_string(), splitted_data[1].to_string()),
-                ("date".to_string(), splitted_data[2].to_string()),
-                ("time".to_string(), splitted_data[3].to_string()),
-                ("tnum".to_string(), splitted_data[4].to_string()),
-                ("type".to_string(), "train".to_string()),
-            ]))
-            .await;
+        if let Some(text) = task_quota_exceeded(&*rzd_db, lang, q.from.id).await {
+            bot.send_message(q.chat_id().unwrap(), text).await?;
+            return Ok(());
+        }
+        let mut task = HashMap::from([
+            ("from_point_code".to_string(), splitted_data[0].to_string()),
+            ("to_point_code".to_string(), splitted_data[1].to_string()),
+            ("date".to_string(), splitted_data[2].to_string()),
+            ("time".to_string(), splitted_data[3].to_string()),
+            ("tnum".to_string(), splitted_data[4].to_string()),
+            ("type".to_string(), "train".to_string()),
+        ]);
+        set_task_owner(&mut task, q.chat_id().unwrap(), Some(q.from.id), lang);
+        let created_task = rzd_db.create_task(task).await;
         match created_task {
             Ok(task_id) => {
                 bot.send_message(
                     q.chat_id().unwrap(),
-                    format!("Создана задача с уникальный номером {task_id}"),
+                    lang.tf(Key::TaskCreated, &[("task_id", &task_id)]),
                 )
                     .await?;
+                dialogue.reset().await?;
+            }
+            Err(err) => {
+                bot.send_message(
+                    q.chat_id().unwrap(),
+                    lang.tf(Key::TaskCreateError, &[("err", &err)]),
+                )
+                .await?;
             }
+        }
+    }
+    Ok(())
+}
+
+async fn quick_search(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let query = parse_quick_query(QuickCommand::Search, &text, Local::now().date_naive());
+    start_quick_query(bot, dialogue, rzd_api, rzd_db, lang, query, msg).await
+}
+
+async fn quick_watch(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let query = parse_quick_query(QuickCommand::Watch, &text, Local::now().date_naive());
+    start_quick_query(bot, dialogue, rzd_api, rzd_db, lang, query, msg).await
+}
+
+async fn start_quick_query(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    query: Result<QuickQuery, QuickQueryError>,
+    msg: Message,
+) -> HandlerResult {
+    match query {
+        Ok(query) => {
+            continue_quick_query(
+                &bot,
+                &dialogue,
+                &rzd_api,
+                &*rzd_db,
+                lang,
+                msg.from().map(|user| user.id),
+                query,
+            )
+            .await
+        }
+        Err(err) => {
+            bot.send_message(msg.chat.id, err.to_text(lang)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Resolves the remaining station names and runs the command.
+/// Stops and asks the user to pick a station when a name is ambiguous.
+async fn continue_quick_query(
+    bot: &Bot,
+    dialogue: &RZDDialogue,
+    rzd_api: &RZDApi,
+    rzd_db: &dyn TaskStore,
+    lang: Lang,
+    user_id: Option<UserId>,
+    mut query: QuickQuery,
+) -> HandlerResult {
+    let chat_id = dialogue.chat_id();
+    while query.from_code.is_none() || query.to_code.is_none() {
+        let name = if query.from_code.is_none() {
+            query.from.clone()
+        } else {
+            query.to.clone()
+        };
+        let codes = match rzd_api.get_rzd_point_codes(name.clone(), 5).await {
+            Ok(codes) => codes,
             Err(err) => {
-                bot.send_message(q.chat_id().unwrap(), format!("Невозможно создать задачу {err}"))
+                bot.send_message(chat_id, lang.tf(Key::StationCodesError, &[("err", &err)]))
+                    .await?;
+                return Ok(());
+            }
+        };
+        if let Err(err) = rzd_db.remember_stations(&codes).await {
+            tracing::warn!(error = %err, "cant remember stations");
+        }
+        match resolve_station(&name, codes) {
+            ResolvedStation::Found(station) => {
+                if query.from_code.is_none() {
+                    query.from_code = Some(station.code);
+                } else {
+                    query.to_code = Some(station.code);
+                }
+            }
+            ResolvedStation::Ambiguous(codes) => {
+                bot.send_message(chat_id, lang.tf(Key::ClarifyStation, &[("name", &name)]))
+                    .reply_markup(make_stations_keyboard(&codes, 0))
+                    .await?;
+                dialogue
+                    .update(State::ChooseQuickStation { query, codes })
+                    .await?;
+                return Ok(());
+            }
+            ResolvedStation::NotFound => {
+                bot.send_message(chat_id, lang.tf(Key::StationNotFound, &[("name", &name)]))
+                    .await?;
+                return Ok(());
+            }
+        }
+    }
+
+    let route = (query.from_code.unwrap(), query.to_code.unwrap());
+    match query.command {
+        QuickCommand::Search => {
+            if query.dates.len() != 1 {
+                bot.send_message(chat_id, lang.t(Key::SingleDateRequired))
                     .await?;
+                return Ok(());
+            }
+            search_trains(
+                bot,
+                dialogue,
+                rzd_api,
+                rzd_db,
+                lang,
+                user_id,
+                (route.0, route.1, query.dates[0]),
+            )
+            .await
+        }
+        QuickCommand::Watch => {
+            let car_types = if query.car_types.is_empty() {
+                CUPE_TYPE.to_string()
+            } else {
+                query.car_types.join(",")
+            };
+            let mut message_text = String::new();
+            for date in query.dates.iter() {
+                if let Some(user_id) = user_id {
+                    if let Some(text) = task_quota_exceeded(rzd_db, lang, user_id).await {
+                        message_text.push_str(&text);
+                        break;
+                    }
+                }
+                let date = date.format("%d.%m.%Y").to_string();
+                let mut task = day_task(&route.0, &route.1, &date);
+                set_task_owner(&mut task, chat_id, user_id, lang);
+                task.insert("car_types".to_string(), car_types.clone());
+                if let Some(seats) = query.seats {
+                    task.insert("seats".to_string(), seats.as_str().to_string());
+                }
+                match rzd_db.create_task(task).await {
+                    Ok(task_id) => message_text.push_str(&lang.tf(
+                        Key::DatedTaskCreated,
+                        &[("date", &date), ("task_id", &task_id)],
+                    )),
+                    Err(err) => message_text.push_str(
+                        &lang.tf(Key::DatedTaskCreateError, &[("date", &date), ("err", &err)]),
+                    ),
+                }
             }
+            bot.send_message(chat_id, message_text).await?;
+            dialogue.reset().await?;
+            Ok(())
+        }
+    }
+}
+
+async fn quick_stations_page(
+    bot: Bot,
+    (_, codes): (QuickQuery, Vec<GetRZDPointCodes>),
+    q: CallbackQuery,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(page) = q.data.as_deref().and_then(parse_page) {
+        edit_page(&bot, &q, None, make_stations_keyboard(&codes, page)).await?;
+    }
+    Ok(())
+}
+
+async fn choose_quick_station(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    (mut query, _): (QuickQuery, Vec<GetRZDPointCodes>),
+    q: CallbackQuery,
+) -> HandlerResult {
+    if let Some(code) = q.data.as_deref().and_then(|data| data.strip_prefix("fav_")) {
+        return toggle_favourite_station(bot, rzd_db, lang, &q, code).await;
+    }
+    bot.answer_callback_query(q.id.clone()).await?;
+    if let Some(code) = &q.data {
+        if query.from_code.is_none() {
+            query.from_code = Some(code.clone());
+        } else {
+            query.to_code = Some(code.clone());
+        }
+        continue_quick_query(
+            &bot,
+            &dialogue,
+            &rzd_api,
+            &*rzd_db,
+            lang,
+            Some(q.from.id),
+            query,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn inline_query(
+    bot: Bot,
+    rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    q: InlineQuery,
+) -> HandlerResult {
+    let words = q.query.split_whitespace().collect::<Vec<&str>>();
+    if words.is_empty() {
+        bot.answer_inline_query(q.id, []).await?;
+        return Ok(());
+    }
+    let today = Local::now().date_naive();
+    let results = match (
+        words.len(),
+        words.last().and_then(|w| parse_short_date(w, today)),
+    ) {
+        (3, Some(date)) => {
+            if let Some(text) = search_limit_exceeded(&*rzd_db, lang, q.from.id).await {
+                vec![inline_article("error", lang.t(Key::Error), text)]
+            } else {
+                inline_trains(&rzd_api, lang, (words[0], words[1]), date).await
+            }
+        }
+        _ => inline_stations(&rzd_api, lang, &q.query).await,
+    };
+    // Results depend on the user's search quota, so they are not shared through telegram's cache
+    bot.answer_inline_query(q.id, results)
+        .cache_time(60)
+        .is_personal(true)
+        .await?;
+    Ok(())
+}
+
+fn inline_article(
+    id: impl Into<String>,
+    title: impl Into<String>,
+    text: String,
+) -> InlineQueryResult {
+    InlineQueryResult::Article(InlineQueryResultArticle::new(
+        id,
+        title,
+        InputMessageContent::Text(InputMessageContentText::new(truncate_message(&text))),
+    ))
+}
+
+async fn inline_stations(rzd_api: &RZDApi, lang: Lang, query: &str) -> Vec<InlineQueryResult> {
+    match rzd_api.get_rzd_point_codes(query.into(), 5).await {
+        Ok(codes) => codes
+            .into_iter()
+            .map(|code| {
+                let text = lang.tf(
+                    Key::StationCodeText,
+                    &[("name", &code.name), ("code", &code.code)],
+                );
+                InlineQueryResult::Article(
+                    InlineQueryResultArticle::new(
+                        code.code.clone(),
+                        code.name.clone(),
+                        InputMessageContent::Text(InputMessageContentText::new(text)),
+                    )
+                    .description(lang.tf(Key::StationCodeDescription, &[("code", &code.code)])),
+                )
+            })
+            .collect(),
+        Err(err) => vec![inline_article(
+            "error",
+            lang.t(Key::Error),
+            lang.tf(Key::StationCodesError, &[("err", &err)]),
+        )],
+    }
+}
+
+async fn inline_trains(
+    rzd_api: &RZDApi,
+    lang: Lang,
+    (from, to): (&str, &str),
+    date: NaiveDate,
+) -> Vec<InlineQueryResult> {
+    let mut stations = Vec::new();
+    for name in [from, to] {
+        match rzd_api.get_rzd_point_codes(name.into(), 5).await {
+            Ok(codes) if !codes.is_empty() => stations.push(codes[0].clone()),
+            Ok(_) => {
+                return vec![inline_article(
+                    "error",
+                    lang.t(Key::StationNotFoundTitle),
+                    lang.tf(Key::StationNotFound, &[("name", &name)]),
+                )]
+            }
+            Err(err) => {
+                return vec![inline_article(
+                    "error",
+                    lang.t(Key::Error),
+                    lang.tf(Key::StationCodesError, &[("err", &err)]),
+                )]
+            }
+        }
+    }
+    let date = date.format("%d.%m.%Y").to_string();
+    let title = format!("{} → {} {date}", stations[0].name, stations[1].name);
+    match rzd_api
+        .get_trains_from_rzd(
+            stations[0].code.clone(),
+            stations[1].code.clone(),
+            date.clone(),
+            5,
+        )
+        .await
+    {
+        Ok(trains) => {
+            let summary = format_trains_summary(&trains, lang);
+            let text = if summary.is_empty() {
+                format!("{title}\n{}", lang.t(Key::NoTrainsFound))
+            } else {
+                format!("{title}\n\n{summary}")
+            };
+            vec![inline_article(
+                format!("{}_{}_{date}", stations[0].code, stations[1].code),
+                title,
+                text,
+            )]
+        }
+        Err(err) => vec![inline_article(
+            "error",
+            lang.t(Key::Error),
+            lang.tf(Key::TrainsError, &[("err", &err)]),
+        )],
+    }
+}
+
+async fn set_lang(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let (new_lang, user) = match (Lang::parse(&text), msg.from()) {
+        (Some(new_lang), Some(user)) => (new_lang, user),
+        _ => {
+            bot.send_message(msg.chat.id, lang.t(Key::LanguageUsage))
+                .await?;
+            return Ok(());
+        }
+    };
+    match rzd_db.set_user_lang(user.id.0, new_lang).await {
+        Ok(()) => {
+            bot.send_message(msg.chat.id, new_lang.t(Key::LanguageChanged))
+                .await?;
+        }
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("{}: {err}", lang.t(Key::Error)))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Tasks the user created as JSON and CSV documents, both can be imported back.
+async fn export_tasks(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    msg: Message,
+) -> HandlerResult {
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let tasks: Vec<ExportedTask> = match rzd_db.list_user_tasks(user.id.0).await {
+        Ok(tasks) => tasks
+            .iter()
+            .map(|(_, task)| ExportedTask::from_task(task))
+            .collect(),
+        Err(err) => {
+            bot.send_message(msg.chat.id, lang.tf(Key::TasksError, &[("err", &err)]))
+                .await?;
+            return Ok(());
+        }
+    };
+    if tasks.is_empty() {
+        bot.send_message(msg.chat.id, lang.t(Key::NoTasks)).await?;
+        return Ok(());
+    }
+    let json = match export_json(&tasks, &Local::now().to_rfc3339()) {
+        Ok(json) => json,
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("{}: {err}", lang.t(Key::Error)))
+                .await?;
+            return Ok(());
+        }
+    };
+    bot.send_document(
+        msg.chat.id,
+        InputFile::memory(json).file_name("rzd-tasks.json"),
+    )
+    .await?;
+    bot.send_document(
+        msg.chat.id,
+        InputFile::memory(export_csv(&tasks)).file_name("rzd-tasks.csv"),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn import_tasks(bot: Bot, dialogue: RZDDialogue, lang: Lang, msg: Message) -> HandlerResult {
+    bot.send_message(msg.chat.id, lang.t(Key::ImportPrompt))
+        .await?;
+    dialogue.update(State::ImportTasks).await?;
+    Ok(())
+}
+
+/// Checks the sent export and shows what would be created, a file without valid tasks can be sent again.
+async fn receive_import(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    lang: Lang,
+    msg: Message,
+) -> HandlerResult {
+    let document = match msg.document() {
+        Some(document) => document,
+        None => {
+            bot.send_message(msg.chat.id, lang.t(Key::ImportNotADocument))
+                .await?;
+            return Ok(());
+        }
+    };
+    if document.file.size > MAX_IMPORT_BYTES {
+        bot.send_message(
+            msg.chat.id,
+            lang.tf(Key::ImportTooLarge, &[("kb", &(MAX_IMPORT_BYTES / 1024))]),
+        )
+        .await?;
+        return Ok(());
+    }
+    let file = bot.get_file(&document.file.id).await?;
+    let mut data = Vec::new();
+    bot.download_file(&file.path, &mut data).await?;
+    let import = match parse_import(&data, Local::now().date_naive()) {
+        Ok(import) => import,
+        Err(err) => {
+            bot.send_message(msg.chat.id, err.to_text(lang)).await?;
+            return Ok(());
+        }
+    };
+    let preview = truncate_message(&format_import_preview(&import, lang));
+    if import.tasks.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            truncate_message(&format!("{}\n\n{preview}", lang.t(Key::ImportNothing))),
+        )
+        .await?;
+        return Ok(());
+    }
+    bot.send_message(msg.chat.id, preview)
+        .reply_markup(make_import_keyboard(lang))
+        .await?;
+    dialogue
+        .update(State::ConfirmImport {
+            tasks: import.tasks,
+        })
+        .await?;
+    Ok(())
+}
+
+/// Imported tasks belong to whoever confirmed them, the quota applies as to any other task.
+async fn confirm_import(
+    bot: Bot,
+    dialogue: RZDDialogue,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    q: CallbackQuery,
+    tasks: Vec<ExportedTask>,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id.clone()).await?;
+    let chat_id = q.chat_id().unwrap();
+    edit_page(&bot, &q, None, Default::default()).await?;
+    if q.data.as_deref() != Some("import_confirm") {
+        bot.send_message(chat_id, lang.t(Key::Canceled)).await?;
+        dialogue.reset().await?;
+        return Ok(());
+    }
+    let total = tasks.len();
+    let mut created = 0;
+    let mut last_error = None;
+    for exported in tasks {
+        let mut task = exported.into_task();
+        set_task_owner(&mut task, chat_id, Some(q.from.id), lang);
+        match rzd_db.create_task(task).await {
+            Ok(_) => created += 1,
+            Err(err) => last_error = Some(err),
+        }
+    }
+    let mut text = lang.tf(Key::ImportDone, &[("created", &created), ("total", &total)]);
+    if let Some(err) = last_error {
+        text.push('\n');
+        text.push_str(&lang.tf(Key::TaskCreateError, &[("err", &err)]));
+    }
+    bot.send_message(chat_id, text).await?;
+    dialogue.reset().await?;
+    Ok(())
+}
+
+async fn set_ttl(
+    bot: Bot,
+    rzd_db: Arc<dyn TaskStore>,
+    lang: Lang,
+    text: String,
+    msg: Message,
+) -> HandlerResult {
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let text = text.trim();
+    let result = match text {
+        "" => rzd_db.get_task_ttl(user.id.0).await.map(|days| match days {
+            Some(days) => lang.tf(Key::TtlCurrent, &[("days", &days)]),
+            None => lang.t(Key::TtlOff).to_string(),
+        }),
+        "off" => rzd_db
+            .set_task_ttl(user.id.0, None)
+            .await
+            .map(|()| lang.t(Key::TtlOff).to_string()),
+        days => match days.parse::<u32>() {
+            Ok(days) if days > MAX_TASK_TTL_DAYS => {
+                Ok(lang.tf(Key::TtlTooLong, &[("max", &MAX_TASK_TTL_DAYS)]))
+            }
+            Ok(days) if days > 0 => rzd_db
+                .set_task_ttl(user.id.0, Some(days))
+                .await
+                .map(|()| lang.tf(Key::TtlSet, &[("days", &days)])),
+            _ => Ok(lang.t(Key::TtlUsage).to_string()),
+        },
+    };
+    match result {
+        Ok(reply) => {
+            bot.send_message(msg.chat.id, reply).await?;
+        }
+        Err(err) => {
+            bot.send_message(msg.chat.id, format!("{}: {err}", lang.t(Key::Error)))
+                .await?;
         }
     }
     Ok(())
@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+pub const RZD_REQUESTS: &str = "rzd_requests_total";
+pub const RZD_RETRIES: &str = "rzd_retries_total";
+pub const RZD_RID_POLLS: &str = "rzd_rid_poll_iterations_total";
+pub const RZD_LATENCY: &str = "rzd_request_duration_seconds";
+pub const RZD_LIMITER_WAIT: &str = "rzd_limiter_wait_seconds";
+pub const RZD_LIMITER_QUEUE: &str = "rzd_limiter_queued_requests";
+pub const HANDLER_INVOCATIONS: &str = "handler_invocations_total";
+pub const ACTIVE_TASKS: &str = "active_tasks";
+pub const POLL_CYCLES: &str = "poll_cycles_total";
+pub const POLL_SHARED_FETCHES: &str = "poll_shared_fetches_total";
+pub const NOTIFICATIONS_SENT: &str = "notifications_sent_total";
+pub const TASKS_EXPIRED: &str = "tasks_expired_total";
+pub const DB_SIZE: &str = "speedb_size_bytes";
+
+const LATENCY_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 40.0, 80.0];
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        }
+    }
+}
+
+const DESCRIPTIONS: [(&str, Kind, &str); 13] = [
+    (
+        RZD_REQUESTS,
+        Kind::Counter,
+        "HTTP requests to RZD by endpoint and outcome",
+    ),
+    (
+        RZD_RETRIES,
+        Kind::Counter,
+        "Retries spent by RZDApi methods",
+    ),
+    (
+        RZD_RID_POLLS,
+        Kind::Counter,
+        "Iterations of the RID polling loop",
+    ),
+    (
+        RZD_LATENCY,
+        Kind::Histogram,
+        "Duration of RZDApi method calls including retries",
+    ),
+    (
+        RZD_LIMITER_WAIT,
+        Kind::Histogram,
+        "Time RZD requests waited for the rate limiter by priority",
+    ),
+    (
+        RZD_LIMITER_QUEUE,
+        Kind::Gauge,
+        "RZD requests waiting for the rate limiter by priority",
+    ),
+    (
+        HANDLER_INVOCATIONS,
+        Kind::Counter,
+        "Handled telegram updates by dialogue state",
+    ),
+    (ACTIVE_TASKS, Kind::Gauge, "Active tasks by type, paused ones are left out"),
+    (POLL_CYCLES, Kind::Counter, "Finished poller cycles"),
+    (
+        POLL_SHARED_FETCHES,
+        Kind::Counter,
+        "Task polls answered by an RZD response fetched for another task with the same query",
+    ),
+    (
+        NOTIFICATIONS_SENT,
+        Kind::Counter,
+        "Notifications sent to task owners",
+    ),
+    (
+        TASKS_EXPIRED,
+        Kind::Counter,
+        "Tasks removed after their travel date or TTL by type",
+    ),
+    (DB_SIZE, Kind::Gauge, "Size of the speedb database"),
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<&'static str, BTreeMap<String, u64>>,
+    gauges: BTreeMap<&'static str, BTreeMap<String, f64>>,
+    histograms: BTreeMap<&'static str, BTreeMap<String, Histogram>>,
+}
+
+/// Process wide metrics rendered in the prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn inc(&self, name: &'static str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    pub fn add(&self, name: &'static str, labels: &[(&str, &str)], value: u64) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .counters
+            .entry(name)
+            .or_default()
+            .entry(format_labels(labels))
+            .or_default() += value;
+    }
+
+    pub fn set(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        let mut registry = self.registry.lock().unwrap();
+        registry
+            .gauges
+            .entry(name)
+            .or_default()
+            .insert(format_labels(labels), value);
+    }
+
+    /// Replaces every series of the gauge, so label values that disappeared are not reported anymore.
+    pub fn set_all(&self, name: &'static str, label: &str, values: &BTreeMap<String, f64>) {
+        let series = values
+            .iter()
+            .map(|(label_value, value)| (format_labels(&[(label, label_value)]), *value))
+            .collect();
+        self.registry.lock().unwrap().gauges.insert(name, series);
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        let mut registry = self.registry.lock().unwrap();
+        let histogram = registry
+            .histograms
+            .entry(name)
+            .or_default()
+            .entry(format_labels(labels))
+            .or_default();
+        for (idx, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if value <= *bound {
+                histogram.buckets[idx] += 1;
+            }
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut text = String::new();
+        for (name, kind, help) in DESCRIPTIONS.iter() {
+            let _ = writeln!(text, "# HELP {name} {help}");
+            let _ = writeln!(text, "# TYPE {name} {}", kind.as_str());
+            match kind {
+                Kind::Counter => {
+                    for (labels, value) in registry.counters.get(name).into_iter().flatten() {
+                        let _ = writeln!(text, "{name}{} {value}", wrap_labels(labels));
+                    }
+                }
+                Kind::Gauge => {
+                    for (labels, value) in registry.gauges.get(name).into_iter().flatten() {
+                        let _ = writeln!(text, "{name}{} {value}", wrap_labels(labels));
+                    }
+                }
+                Kind::Histogram => {
+                    for (labels, histogram) in registry.histograms.get(name).into_iter().flatten() {
+                        let separator = if labels.is_empty() { "" } else { "," };
+                        for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                            let _ = writeln!(
+                                text,
+                                "{name}_bucket{{{labels}{separator}le=\"{bound}\"}} {count}"
+                            );
+                        }
+                        let _ = writeln!(
+                            text,
+                            "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {}",
+                            histogram.count
+                        );
+                        let _ =
+                            writeln!(text, "{name}_sum{} {}", wrap_labels(labels), histogram.sum);
+                        let _ = writeln!(
+                            text,
+                            "{name}_count{} {}",
+                            wrap_labels(labels),
+                            histogram.count
+                        );
+                    }
+                }
+            }
+        }
+        text
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{name}=\"{}\"",
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn wrap_labels(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{labels}}}")
+    }
+}
@@ -0,0 +1,192 @@
+use teloxide::types::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup};
+
+pub const PAGE_SIZE: usize = 5;
+const PAGE_PREFIX: &str = "page_";
+
+/// One page of a long list together with the buttons to move between pages.
+/// The page number travels in the callback data, so handlers only need the full list.
+pub struct Paginated<'a, T> {
+    items: &'a [T],
+    page: usize,
+    page_size: usize,
+    prefix: String,
+}
+
+impl<'a, T> Paginated<'a, T> {
+    pub fn new(items: &'a [T], page: usize) -> Self {
+        Self::with_page_size(items, page, PAGE_SIZE)
+    }
+
+    pub fn with_page_size(items: &'a [T], page: usize, page_size: usize) -> Self {
+        let page_size = page_size.max(1);
+        let pages = items.len().div_ceil(page_size).max(1);
+        Self {
+            items,
+            page: page.min(pages - 1),
+            page_size,
+            prefix: PAGE_PREFIX.to_string(),
+        }
+    }
+
+    /// Callback data of the navigation buttons is the prefix followed by the page number,
+    /// for lists paged outside of a dialogue state.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn pages(&self) -> usize {
+        self.items.len().div_ceil(self.page_size).max(1)
+    }
+
+    /// Items of the current page with their index in the whole list.
+    pub fn items(&self) -> impl Iterator<Item = (usize, &'a T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .skip(self.page * self.page_size)
+            .take(self.page_size)
+    }
+
+    pub fn navigation_row(&self) -> Vec<InlineKeyboardButton> {
+        let mut row = Vec::new();
+        if self.page > 0 {
+            row.push(InlineKeyboardButton::callback(
+                "◀",
+                format!("{}{}", self.prefix, self.page - 1),
+            ));
+        }
+        row.push(InlineKeyboardButton::callback(
+            format!("{}/{}", self.page + 1, self.pages()),
+            format!("{}{}", self.prefix, self.page),
+        ));
+        if self.page + 1 < self.pages() {
+            row.push(InlineKeyboardButton::callback(
+                "▶",
+                format!("{}{}", self.prefix, self.page + 1),
+            ));
+        }
+        row
+    }
+
+    /// Appends the navigation row to the keyboard if there is more than one page.
+    pub fn keyboard(&self, reply_markup: InlineKeyboardMarkup) -> InlineKeyboardMarkup {
+        if self.pages() > 1 {
+            reply_markup.append_row(self.navigation_row())
+        } else {
+            reply_markup
+        }
+    }
+}
+
+pub fn parse_page(data: &str) -> Option<usize> {
+    data.strip_prefix(PAGE_PREFIX)?.parse().ok()
+}
+
+pub fn is_page_callback(q: CallbackQuery) -> bool {
+    q.data.as_deref().and_then(parse_page).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    use super::*;
+
+    fn callbacks(paginated: &Paginated<'_, usize>) -> Vec<(String, String)> {
+        paginated
+            .navigation_row()
+            .into_iter()
+            .map(|button| match button.kind {
+                InlineKeyboardButtonKind::CallbackData(data) => (button.text, data),
+                kind => panic!("unexpected button {kind:?}"),
+            })
+            .collect()
+    }
+
+    fn page_items(paginated: &Paginated<'_, usize>) -> Vec<usize> {
+        paginated.items().map(|(_, item)| *item).collect()
+    }
+
+    #[test]
+    fn splits_items_into_pages() {
+        let items: Vec<usize> = (0..10).collect();
+        let first = Paginated::new(&items, 0);
+        assert_eq!(first.pages(), 2);
+        assert_eq!(page_items(&first), vec![0, 1, 2, 3, 4]);
+        assert_eq!(
+            callbacks(&first),
+            vec![
+                ("1/2".to_string(), "page_0".to_string()),
+                ("▶".to_string(), "page_1".to_string()),
+            ]
+        );
+
+        let second = Paginated::new(&items, 1);
+        assert_eq!(
+            second.items().collect::<Vec<_>>(),
+            vec![(5, &5), (6, &6), (7, &7), (8, &8), (9, &9)]
+        );
+        assert_eq!(
+            callbacks(&second),
+            vec![
+                ("◀".to_string(), "page_0".to_string()),
+                ("2/2".to_string(), "page_1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_last_partial_page() {
+        let items: Vec<usize> = (0..7).collect();
+        let last = Paginated::with_page_size(&items, 2, 3);
+        assert_eq!(last.pages(), 3);
+        assert_eq!(page_items(&last), vec![6]);
+        assert_eq!(callbacks(&last).len(), 2);
+    }
+
+    #[test]
+    fn shows_an_empty_list_as_one_page() {
+        let items: Vec<usize> = Vec::new();
+        let empty = Paginated::new(&items, 0);
+        assert_eq!(empty.pages(), 1);
+        assert!(page_items(&empty).is_empty());
+        assert_eq!(
+            callbacks(&empty),
+            vec![("1/1".to_string(), "page_0".to_string())]
+        );
+        let keyboard = empty.keyboard(InlineKeyboardMarkup::default());
+        assert!(keyboard.inline_keyboard.is_empty());
+    }
+
+    #[test]
+    fn shows_the_last_page_for_a_stale_page_number() {
+        let items: Vec<usize> = (0..6).collect();
+        let stale = Paginated::new(&items, parse_page("page_9").unwrap());
+        assert_eq!(page_items(&stale), vec![5]);
+        assert_eq!(callbacks(&stale)[0].1, "page_0");
+
+        let items: Vec<usize> = Vec::new();
+        assert!(page_items(&Paginated::new(&items, 3)).is_empty());
+    }
+
+    #[test]
+    fn uses_the_prefix_in_callbacks() {
+        let items: Vec<usize> = (0..12).collect();
+        let paginated = Paginated::new(&items, 1).with_prefix("history_42_");
+        let data: Vec<String> = callbacks(&paginated)
+            .into_iter()
+            .map(|(_, data)| data)
+            .collect();
+        assert_eq!(data, vec!["history_42_0", "history_42_1", "history_42_2"]);
+        assert!(data.iter().all(|data| parse_page(data).is_none()));
+        assert_eq!(
+            crate::utils::parse_history_callback(&data[2]),
+            Some(("42", Some(2)))
+        );
+
+        assert_eq!(parse_page("page_2"), Some(2));
+        assert_eq!(parse_page("page_"), None);
+        assert_eq!(parse_page("page_-1"), None);
+    }
+}
@@ -0,0 +1,800 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use teloxide::prelude::*;
+use tokio::time::MissedTickBehavior;
+use tracing::Instrument;
+
+use crate::access::AccessStatus;
+use crate::db::{FoundSeats, PendingNotification, SentNotification};
+use crate::expiry::{is_expired, moscow_now};
+use crate::health::Health;
+use crate::i18n::{Key, Lang};
+use crate::metrics::{Metrics, NOTIFICATIONS_SENT, POLL_CYCLES, POLL_SHARED_FETCHES};
+use crate::quick_search::{parse_car_type, parse_seat_filter, SeatFilter};
+use crate::rate_limiter::background;
+use crate::rzd::{GetRZDTrains, GetRZDTrainsCarriagesResponse, GetRZDTrainsResponse, RZDApi};
+use crate::shutdown::Shutdown;
+use crate::store::TaskStore;
+use crate::task_edit::is_paused;
+use crate::utils::{find_free_compartments, truncate_message};
+use crate::CUPE_TYPE;
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 300;
+const POLL_STATS_WINDOW: Duration = Duration::from_secs(3600);
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+/// `POLL_INTERVAL_SECS`, never below the minimum poll interval.
+pub fn poll_interval_from_env(min_interval: Duration) -> Duration {
+    let interval = env::var("POLL_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+    let interval = Duration::from_secs(interval);
+    if interval < min_interval {
+        tracing::warn!(
+            ?min_interval,
+            "POLL_INTERVAL_SECS is below the minimum poll interval, using the minimum"
+        );
+        return min_interval;
+    }
+    interval
+}
+
+/// Pause switch and recent task polls of the poller, shared with the admin commands.
+#[derive(Default)]
+pub struct PollerControl {
+    paused: AtomicBool,
+    recent_polls: Mutex<VecDeque<(Instant, bool)>>,
+}
+
+impl PollerControl {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Returns `false` if the poller was already paused.
+    pub fn pause(&self) -> bool {
+        !self.paused.swap(true, Ordering::SeqCst)
+    }
+
+    /// Returns `false` if the poller was not paused.
+    pub fn resume(&self) -> bool {
+        self.paused.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Task polls and failed task polls during the last hour.
+    pub fn polls_last_hour(&self) -> (usize, usize) {
+        let mut recent_polls = self.recent_polls.lock().unwrap();
+        prune_polls(&mut recent_polls);
+        let failed = recent_polls.iter().filter(|(_, ok)| !ok).count();
+        (recent_polls.len(), failed)
+    }
+
+    fn record_poll(&self, ok: bool) {
+        let mut recent_polls = self.recent_polls.lock().unwrap();
+        prune_polls(&mut recent_polls);
+        recent_polls.push_back((Instant::now(), ok));
+    }
+}
+
+fn prune_polls(recent_polls: &mut VecDeque<(Instant, bool)>) {
+    while let Some((at, _)) = recent_polls.front() {
+        if at.elapsed() <= POLL_STATS_WINDOW {
+            break;
+        }
+        recent_polls.pop_front();
+    }
+}
+
+/// Result of a task poll, `lines` are what the owner is told and what the snapshot keeps.
+#[derive(Default)]
+struct Found {
+    lines: Vec<String>,
+    seats: Vec<FoundSeats>,
+}
+
+/// RZD request a task is polled with, tasks with the same query share one fetch per cycle.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum RzdQuery {
+    Day {
+        from: String,
+        to: String,
+        date: String,
+    },
+    Train {
+        from: String,
+        to: String,
+        date: String,
+        time: String,
+        tnum: String,
+    },
+}
+
+impl RzdQuery {
+    /// `None` for task types the poller does not know.
+    fn of(task: &HashMap<String, String>) -> Option<Self> {
+        let field = |name: &str| task.get(name).cloned().unwrap_or_default();
+        match task.get("type").map(String::as_str) {
+            Some("day") => Some(RzdQuery::Day {
+                from: field("from_point_code"),
+                to: field("to_point_code"),
+                date: field("date"),
+            }),
+            Some("train") => Some(RzdQuery::Train {
+                from: field("from_point_code"),
+                to: field("to_point_code"),
+                date: field("date"),
+                time: field("time"),
+                tnum: field("tnum"),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// What RZD answered to a query, each task applies its own filters to it.
+enum Fetched {
+    /// The train list only has the total of free seats, the carriages of the trains a seat filter needs
+    /// come along by train number.
+    Trains(
+        GetRZDTrainsResponse,
+        BTreeMap<String, GetRZDTrainsCarriagesResponse>,
+    ),
+    Carriages(GetRZDTrainsCarriagesResponse),
+}
+
+/// Due task waiting for the response to its query.
+type Polled<'a> = (&'a String, &'a HashMap<String, String>, ChatId);
+
+/// Background loop that checks every stored task and notifies the owner when free places change.
+pub struct Poller {
+    bot: Bot,
+    rzd_api: Arc<RZDApi>,
+    rzd_db: Arc<dyn TaskStore>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    control: Arc<PollerControl>,
+    interval: Duration,
+}
+
+impl Poller {
+    pub fn new(
+        bot: Bot,
+        rzd_api: Arc<RZDApi>,
+        rzd_db: Arc<dyn TaskStore>,
+        metrics: Arc<Metrics>,
+        health: Arc<Health>,
+        control: Arc<PollerControl>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            bot,
+            rzd_api,
+            rzd_db,
+            metrics,
+            health,
+            control,
+            interval,
+        }
+    }
+
+    /// Polls until the shutdown is triggered. The cycle in progress stops before the next task,
+    /// notifications it already queued are still delivered.
+    pub async fn run(self, shutdown: Arc<Shutdown>) {
+        // Left undelivered by the previous run
+        self.deliver_notifications().await;
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            if self.control.is_paused() {
+                // A paused poller is still alive, it should not fail the health checks
+                self.health.mark_poll();
+                continue;
+            }
+            let cycle = tracing::info_span!("poll_cycle", tasks = tracing::field::Empty);
+            self.poll_cycle(&shutdown).instrument(cycle).await;
+            self.deliver_notifications().await;
+        }
+        tracing::info!("poller is stopped");
+    }
+
+    /// Polls the tasks that are due and schedules their next poll, a failed poll is retried on schedule too.
+    /// Tasks asking RZD the same are polled together with a single request.
+    async fn poll_cycle(&self, shutdown: &Shutdown) {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let tasks = match self.rzd_db.list_due_tasks(started_at).await {
+            Ok(tasks) => tasks,
+            Err(err) => {
+                tracing::error!(error = %err, "cant list tasks for polling");
+                return;
+            }
+        };
+        tracing::Span::current().record("tasks", tasks.len());
+
+        let now = moscow_now();
+        let mut groups: BTreeMap<RzdQuery, Vec<Polled>> = BTreeMap::new();
+        for (task_id, task) in tasks.iter() {
+            // Left for the expiry sweeper, which also tells the owner.
+            if is_expired(task, now) {
+                continue;
+            }
+            // Paused tasks leave the due index, this one was paused while the index was read
+            if is_paused(task) {
+                continue;
+            }
+            let query = match RzdQuery::of(task) {
+                Some(query) => query,
+                None => {
+                    self.schedule_next_poll(task_id, task, started_at).await;
+                    continue;
+                }
+            };
+            match self.task_chat(task).await {
+                Ok(Some(chat_id)) => groups
+                    .entry(query)
+                    .or_default()
+                    .push((task_id, task, chat_id)),
+                // Checked again an interval later instead of on every cycle
+                Ok(None) => self.schedule_next_poll(task_id, task, started_at).await,
+                Err(err) => {
+                    tracing::warn!(task_id = %task_id, error = %err, "cant poll task");
+                    self.control.record_poll(false);
+                }
+            }
+        }
+
+        for (query, group) in groups.iter() {
+            if shutdown.is_triggered() {
+                return;
+            }
+            for (task_id, task, _) in group.iter() {
+                self.schedule_next_poll(task_id, task, started_at).await;
+            }
+            let span = tracing::info_span!("rzd_fetch", tasks = group.len());
+            let fetched = background(self.fetch(query, group)).instrument(span).await;
+            if group.len() > 1 {
+                self.metrics
+                    .add(POLL_SHARED_FETCHES, &[], (group.len() - 1) as u64);
+            }
+            for (task_id, task, chat_id) in group.iter() {
+                let span = tracing::info_span!(
+                    "poll_task",
+                    task_id = %task_id,
+                    task_type = task.get("type").map(String::as_str),
+                    user_id = task.get("user_id").map(String::as_str),
+                );
+                let result = match &fetched {
+                    Ok(fetched) => {
+                        self.poll_task(task_id, task, *chat_id, fetched)
+                            .instrument(span)
+                            .await
+                    }
+                    Err(err) => Err(err.clone()),
+                };
+                if let Err(err) = &result {
+                    tracing::warn!(task_id = %task_id, error = %err, "cant poll task");
+                }
+                self.control.record_poll(result.is_ok());
+            }
+        }
+        self.metrics.inc(POLL_CYCLES, &[]);
+        self.health.mark_poll();
+    }
+
+    /// Tasks of users with a minimum poll interval longer than the poller's are polled that much less often.
+    async fn poll_interval(&self, task: &HashMap<String, String>) -> Duration {
+        let user_id = match task.get("user_id").and_then(|id| id.parse::<u64>().ok()) {
+            Some(user_id) => user_id,
+            None => return self.interval,
+        };
+        match self.rzd_db.user_limits(user_id).await {
+            Ok(limits) => limits.min_poll_interval.max(self.interval),
+            Err(err) => {
+                tracing::warn!(user_id, error = %err, "cant get user limits");
+                self.interval
+            }
+        }
+    }
+
+    async fn schedule_next_poll(
+        &self,
+        task_id: &str,
+        task: &HashMap<String, String>,
+        started_at: u64,
+    ) {
+        let next_poll_at = started_at + self.poll_interval(task).await.as_secs();
+        if let Err(err) = self.rzd_db.schedule_poll(task_id, next_poll_at).await {
+            tracing::error!(task_id = %task_id, error = %err, "cant schedule the next poll");
+        }
+    }
+
+    /// Chat the task notifies, `None` if the task is not worth an RZD request.
+    async fn task_chat(&self, task: &HashMap<String, String>) -> Result<Option<ChatId>, String> {
+        // Tasks created before owners were stored have nobody to notify
+        let chat_id = match task.get("chat_id").and_then(|id| id.parse::<i64>().ok()) {
+            Some(chat_id) => ChatId(chat_id),
+            None => return Ok(None),
+        };
+        // Tasks of banned users are kept, but do not cost RZD requests anymore
+        if let Some(user_id) = task.get("user_id").and_then(|id| id.parse::<u64>().ok()) {
+            if self.rzd_db.get_access(user_id).await? == Some(AccessStatus::Banned) {
+                return Ok(None);
+            }
+        }
+        Ok(Some(chat_id))
+    }
+
+    async fn fetch(&self, query: &RzdQuery, group: &[Polled<'_>]) -> Result<Fetched, String> {
+        match query {
+            RzdQuery::Day { from, to, date } => {
+                let trains = self
+                    .rzd_api
+                    .get_trains_from_rzd(from.clone(), to.clone(), date.clone(), 5)
+                    .await?;
+                let mut carriages = BTreeMap::new();
+                for train in berth_trains(group.iter().map(|(_, task, _)| *task), &trains) {
+                    let train_carriages = self
+                        .rzd_api
+                        .get_trains_carriages_from_rzd(
+                            from.clone(),
+                            to.clone(),
+                            date.clone(),
+                            train.time0.clone(),
+                            train.number.clone(),
+                            5,
+                        )
+                        .await?;
+                    carriages.insert(train.number.clone(), train_carriages);
+                }
+                Ok(Fetched::Trains(trains, carriages))
+            }
+            RzdQuery::Train {
+                from,
+                to,
+                date,
+                time,
+                tnum,
+            } => self
+                .rzd_api
+                .get_trains_carriages_from_rzd(
+                    from.clone(),
+                    to.clone(),
+                    date.clone(),
+                    time.clone(),
+                    tnum.clone(),
+                    5,
+                )
+                .await
+                .map(Fetched::Carriages),
+        }
+    }
+
+    async fn poll_task(
+        &self,
+        task_id: &str,
+        task: &HashMap<String, String>,
+        chat_id: ChatId,
+        fetched: &Fetched,
+    ) -> Result<(), String> {
+        let lang = task
+            .get("lang")
+            .and_then(|code| Lang::parse(code))
+            .unwrap_or_default();
+        let task_type = task.get("type").cloned().unwrap_or_default();
+        let found = match fetched {
+            Fetched::Trains(trains, carriages) => find_day_places(task, lang, trains, carriages),
+            Fetched::Carriages(carriages) => find_train_places(task, lang, carriages),
+        };
+
+        let snapshot = self.rzd_db.get_snapshot(task_id).await?.unwrap_or_default();
+        if snapshot == found.lines {
+            return Ok(());
+        }
+        if found.lines.is_empty() {
+            return self.rzd_db.put_snapshot(task_id, &found.lines).await;
+        }
+        let notification = PendingNotification {
+            chat_id: chat_id.0,
+            text: truncate_message(&self.format_notification(task, lang, &found.lines).await),
+            task_type,
+            attempts: 0,
+            added: found
+                .lines
+                .iter()
+                .filter(|line| !snapshot.contains(line))
+                .count(),
+            removed: snapshot
+                .iter()
+                .filter(|line| !found.lines.contains(line))
+                .count(),
+            seats: found.seats,
+        };
+        self.rzd_db
+            .queue_notification(task_id, &found.lines, &notification)
+            .await
+    }
+
+    /// Sends the queued notifications, failed ones are retried after the next cycle.
+    async fn deliver_notifications(&self) {
+        let notifications = match self.rzd_db.list_pending_notifications().await {
+            Ok(notifications) => notifications,
+            Err(err) => {
+                tracing::error!(error = %err, "cant list pending notifications");
+                return;
+            }
+        };
+        for (task_id, mut notification) in notifications {
+            let result = match self
+                .bot
+                .send_message(ChatId(notification.chat_id), &notification.text)
+                .await
+            {
+                Ok(message) => {
+                    self.metrics.inc(
+                        NOTIFICATIONS_SENT,
+                        &[("type", notification.task_type.as_str())],
+                    );
+                    let sent = SentNotification {
+                        sent_at: moscow_now().to_rfc3339(),
+                        seats: notification.seats,
+                        added: notification.added,
+                        removed: notification.removed,
+                        message_id: message.id.0,
+                    };
+                    self.rzd_db.record_notification(&task_id, &sent).await
+                }
+                Err(err) => {
+                    notification.attempts += 1;
+                    if notification.attempts >= MAX_DELIVERY_ATTEMPTS {
+                        tracing::error!(task_id = %task_id, error = %err, "dropping notification");
+                        self.rzd_db.delete_pending_notification(&task_id).await
+                    } else {
+                        tracing::warn!(task_id = %task_id, error = %err, "cant send notification");
+                        self.rzd_db
+                            .put_pending_notification(&task_id, &notification)
+                            .await
+                    }
+                }
+            };
+            if let Err(err) = result {
+                tracing::error!(task_id = %task_id, error = %err, "cant update pending notification");
+            }
+        }
+    }
+
+    async fn format_notification(
+        &self,
+        task: &HashMap<String, String>,
+        lang: Lang,
+        places: &[String],
+    ) -> String {
+        let field = |name: &str| task.get(name).cloned().unwrap_or_default();
+        let places = places.join("\n");
+        match field("type").as_str() {
+            "train" => lang.tf(
+                Key::SeatsFoundTrain,
+                &[
+                    ("tnum", &field("tnum")),
+                    ("date", &field("date")),
+                    ("time", &field("time")),
+                    ("places", &places),
+                ],
+            ),
+            _ => {
+                let from = self.station_name(&field("from_point_code")).await;
+                let to = self.station_name(&field("to_point_code")).await;
+                lang.tf(
+                    Key::SeatsFoundDay,
+                    &[
+                        ("date", &field("date")),
+                        ("from", &from),
+                        ("to", &to),
+                        ("places", &places),
+                    ],
+                )
+            }
+        }
+    }
+
+    async fn station_name(&self, code: &str) -> String {
+        match self.rzd_db.get_station(code).await {
+            Ok(Some(station)) => station.name,
+            _ => code.to_string(),
+        }
+    }
+}
+
+/// Car types of the train the task looks for that have free seats within its price.
+fn matching_car_types(
+    task: &HashMap<String, String>,
+    train: &GetRZDTrains,
+) -> Vec<(&'static str, usize)> {
+    let car_types = task
+        .get("car_types")
+        .map(|car_types| car_types.split(',').collect())
+        .unwrap_or_else(|| vec![CUPE_TYPE]);
+    let max_price = task
+        .get("max_price")
+        .and_then(|max_price| max_price.parse::<u32>().ok());
+    train
+        .cars
+        .iter()
+        .filter(|car| !car.disabled_person)
+        // Carriages without a price are kept
+        .filter(|car| match (max_price, car.tariff) {
+            (Some(max_price), Some(tariff)) => tariff <= max_price,
+            _ => true,
+        })
+        .filter_map(|car| {
+            parse_car_type(&car._type.to_lowercase())
+                .filter(|car_type| car_types.contains(car_type))
+                .map(|car_type| (car_type, car.free_seats))
+        })
+        .collect()
+}
+
+/// Trains of the day tasks with a seat filter could have places in, their carriages tell which berths are free.
+fn berth_trains<'a, 'b>(
+    tasks: impl Iterator<Item = &'b HashMap<String, String>>,
+    trains: &'a GetRZDTrainsResponse,
+) -> Vec<&'a GetRZDTrains> {
+    let tasks: Vec<_> = tasks
+        .filter(|task| {
+            task.get("seats")
+                .and_then(|seats| parse_seat_filter(seats))
+                .is_some()
+        })
+        .collect();
+    trains
+        .tp
+        .iter()
+        .flat_map(|tp| tp.list.iter())
+        .filter(|train| {
+            tasks
+                .iter()
+                .any(|task| !matching_car_types(task, train).is_empty())
+        })
+        .collect()
+}
+
+/// Free berths of the kind in the carriages of the given types, side berths are not counted.
+fn count_berths(
+    carriages: &GetRZDTrainsCarriagesResponse,
+    car_types: &[&str],
+    filter: SeatFilter,
+) -> usize {
+    let berth = match filter {
+        SeatFilter::Lower => "dn",
+        SeatFilter::Upper => "up",
+    };
+    carriages
+        .lst
+        .iter()
+        .take(1)
+        .flat_map(|lst| lst.cars.iter())
+        .filter(|car| {
+            parse_car_type(&car._type.to_lowercase())
+                .is_some_and(|car_type| car_types.contains(&car_type))
+        })
+        .flat_map(|car| car.seats.iter())
+        .filter(|seats| seats._type == berth)
+        .map(|seats| seats.free)
+        .sum()
+}
+
+/// Trains of the day with free seats in the task's car types. With a seat filter only the free berths of
+/// that kind count, taken from the carriages of the train.
+fn find_day_places(
+    task: &HashMap<String, String>,
+    lang: Lang,
+    trains: &GetRZDTrainsResponse,
+    carriages: &BTreeMap<String, GetRZDTrainsCarriagesResponse>,
+) -> Found {
+    let seat_filter = task.get("seats").and_then(|seats| parse_seat_filter(seats));
+    let mut found = Found::default();
+    for train in trains.tp.iter().flat_map(|tp| tp.list.iter()) {
+        let car_types = matching_car_types(task, train);
+        let seats = match seat_filter {
+            None => car_types.iter().map(|(_, free_seats)| free_seats).sum(),
+            Some(_) if car_types.is_empty() => 0,
+            Some(filter) => {
+                let car_types: Vec<&str> =
+                    car_types.iter().map(|(car_type, _)| *car_type).collect();
+                carriages
+                    .get(&train.number)
+                    .map(|carriages| count_berths(carriages, &car_types, filter))
+                    .unwrap_or_default()
+            }
+        };
+        if seats > 0 {
+            found.lines.push(lang.tf(
+                Key::TrainSeatsLine,
+                &[
+                    ("number", &train.number),
+                    ("time", &train.time0),
+                    ("seats", &seats),
+                ],
+            ));
+            found.seats.push(FoundSeats {
+                train: train.number.clone(),
+                car: None,
+                seats,
+            });
+        }
+    }
+    found
+}
+
+/// Whole free compartments, four seats each.
+fn find_train_places(
+    task: &HashMap<String, String>,
+    lang: Lang,
+    carriages: &GetRZDTrainsCarriagesResponse,
+) -> Found {
+    let mut found = Found::default();
+    let mut seats_by_car: BTreeMap<String, usize> = BTreeMap::new();
+    for (car, place) in find_free_compartments(carriages) {
+        found.lines.push(lang.tf(
+            Key::CarriagePlaces,
+            &[("car", &car), ("from", &place), ("to", &(place + 3))],
+        ));
+        *seats_by_car.entry(car).or_default() += 4;
+    }
+    found.seats = seats_by_car
+        .into_iter()
+        .map(|(car, seats)| FoundSeats {
+            train: task.get("tnum").cloned().unwrap_or_default(),
+            car: Some(car),
+            seats,
+        })
+        .collect();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn day_task(extra: &[(&str, &str)]) -> HashMap<String, String> {
+        let mut day = task(&[
+            ("type", "day"),
+            ("from_point_code", "2000000"),
+            ("to_point_code", "2004000"),
+            ("date", "21.10.2026"),
+        ]);
+        day.extend(task(extra));
+        day
+    }
+
+    #[test]
+    fn tasks_share_queries_regardless_of_their_filters() {
+        let coupe = day_task(&[("car_types", "купе"), ("chat_id", "1")]);
+        let cheap_berth = day_task(&[
+            ("car_types", "плацкартный"),
+            ("max_price", "2000"),
+            ("chat_id", "2"),
+        ]);
+        let other_day = day_task(&[("date", "22.10.2026")]);
+        assert_eq!(RzdQuery::of(&coupe), RzdQuery::of(&cheap_berth));
+        assert_ne!(RzdQuery::of(&coupe), RzdQuery::of(&other_day));
+        assert_eq!(RzdQuery::of(&task(&[("type", "bus")])), None);
+    }
+
+    /// Car types as the train list sends them, the short `type` with the full name in `typeLoc`.
+    fn trains() -> GetRZDTrainsResponse {
+        serde_json::from_str(
+            r#"{"tp":[{"list":[{"number":"020У","date0":"21.10.2026","time0":"00:20","cars":[
+                {"carDataType":1,"itype":4,"type":"Купе","typeLoc":"Купе","freeSeats":4,"tariff":4500},
+                {"carDataType":1,"itype":3,"type":"Плац","typeLoc":"Плацкартный","freeSeats":10,"tariff":2500},
+                {"carDataType":1,"itype":3,"type":"Плац","typeLoc":"Плацкартный","freeSeats":2,"tariff":1800},
+                {"carDataType":1,"itype":1,"type":"Сид","typeLoc":"Сидячий","freeSeats":40,"tariff":1500},
+                {"carDataType":1,"itype":6,"type":"Люкс","typeLoc":"СВ","freeSeats":2,"tariff":9000}
+            ]},{"number":"752А","date0":"21.10.2026","time0":"06:40","cars":[
+                {"carDataType":1,"itype":1,"type":"Сид","typeLoc":"Сидячий","freeSeats":120,"tariff":1900}
+            ]}]}]}"#,
+        )
+        .unwrap()
+    }
+
+    /// Carriages of 020У, free places by kind in `seats`.
+    fn carriages() -> BTreeMap<String, GetRZDTrainsCarriagesResponse> {
+        let carriages = serde_json::from_str(
+            r#"{"lst":[{"cars":[
+                {"cnumber":"05","type":"Купе","typeLoc":"Купе","places":"001-004","seats":[
+                    {"type":"dn","label":"Нижнее","free":1},{"type":"up","label":"Верхнее","free":3}
+                ]},
+                {"cnumber":"07","type":"Плац","typeLoc":"Плацкартный","places":"001-012","seats":[
+                    {"type":"dn","label":"Нижнее","free":4},{"type":"up","label":"Верхнее","free":6},
+                    {"type":"ldn","label":"Нижнее боковое","free":2}
+                ]}
+            ]}]}"#,
+        )
+        .unwrap();
+        BTreeMap::from([("020У".to_string(), carriages)])
+    }
+
+    #[test]
+    fn applies_each_task_filters_to_a_shared_response() {
+        let trains = trains();
+        let carriages = carriages();
+        let seats = |task: &HashMap<String, String>| {
+            find_day_places(task, Lang::default(), &trains, &carriages)
+                .seats
+                .iter()
+                .map(|found| found.seats)
+                .sum::<usize>()
+        };
+        assert_eq!(seats(&day_task(&[])), 4);
+        assert_eq!(seats(&day_task(&[("car_types", "плацкартный")])), 12);
+        assert_eq!(seats(&day_task(&[("car_types", "сидячий,св")])), 162);
+        assert_eq!(
+            seats(&day_task(&[
+                ("car_types", "плацкартный"),
+                ("max_price", "2000")
+            ])),
+            2
+        );
+        assert_eq!(seats(&day_task(&[("max_price", "2000")])), 0);
+        assert_eq!(seats(&day_task(&[("seats", "lower")])), 1);
+        assert_eq!(seats(&day_task(&[("seats", "upper")])), 3);
+        assert_eq!(
+            seats(&day_task(&[
+                ("car_types", "плацкартный,сидячий"),
+                ("seats", "lower")
+            ])),
+            4
+        );
+        assert_eq!(
+            find_day_places(
+                &day_task(&[("seats", "lower")]),
+                Lang::default(),
+                &trains,
+                &BTreeMap::new()
+            )
+            .seats,
+            vec![]
+        );
+    }
+
+    #[test]
+    fn fetches_carriages_only_for_seat_filters() {
+        let trains = trains();
+        let numbers = |tasks: &[HashMap<String, String>]| {
+            berth_trains(tasks.iter(), &trains)
+                .iter()
+                .map(|train| train.number.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(numbers(&[day_task(&[("car_types", "плацкартный")])]).is_empty());
+        assert_eq!(numbers(&[day_task(&[("seats", "lower")])]), vec!["020У"]);
+        assert_eq!(
+            numbers(&[
+                day_task(&[("seats", "upper")]),
+                day_task(&[("car_types", "сидячий"), ("seats", "lower")])
+            ]),
+            vec!["020У", "752А"]
+        );
+    }
+}
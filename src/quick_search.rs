@@ -0,0 +1,438 @@
+use chrono::{Datelike, Days, Duration, NaiveDate, Weekday};
+
+use crate::i18n::{Key, Lang};
+use crate::rzd::GetRZDPointCodes;
+use crate::utils::parse_short_date;
+
+const MAX_DATE_RANGE_DAYS: i64 = 14;
+const STATION_SEPARATORS: [&str; 4] = ["-", "—", "→", ">"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuickCommand {
+    Search,
+    Watch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeatFilter {
+    Lower,
+    Upper,
+}
+
+impl SeatFilter {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SeatFilter::Lower => "lower",
+            SeatFilter::Upper => "upper",
+        }
+    }
+}
+
+/// Parsed `/search` or `/watch` arguments, station codes are filled while resolving names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickQuery {
+    pub command: QuickCommand,
+    pub from: String,
+    pub to: String,
+    pub from_code: Option<String>,
+    pub to_code: Option<String>,
+    pub dates: Vec<NaiveDate>,
+    pub car_types: Vec<String>,
+    pub seats: Option<SeatFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuickQueryError {
+    DateMissing,
+    StationMissing,
+    StationsNotSeparated,
+    DateInPast(NaiveDate),
+    RangeReversed,
+    RangeTooLong,
+    UnknownDate(String),
+    UnknownFilter(String),
+    /// `/search` only counts compartments, filters are for `/watch`.
+    SearchFilters,
+}
+
+impl QuickQueryError {
+    pub fn to_text(&self, lang: Lang) -> String {
+        match self {
+            QuickQueryError::DateMissing => lang.t(Key::DateMissing).to_string(),
+            QuickQueryError::StationMissing => lang.t(Key::StationMissing).to_string(),
+            QuickQueryError::StationsNotSeparated => lang.t(Key::StationsNotSeparated).to_string(),
+            QuickQueryError::DateInPast(date) => {
+                lang.tf(Key::DateInPast, &[("date", &date.format("%d.%m.%Y"))])
+            }
+            QuickQueryError::RangeReversed => lang.t(Key::RangeReversed).to_string(),
+            QuickQueryError::RangeTooLong => {
+                lang.tf(Key::RangeTooLong, &[("days", &MAX_DATE_RANGE_DAYS)])
+            }
+            QuickQueryError::UnknownDate(text) => lang.tf(Key::UnknownDate, &[("text", text)]),
+            QuickQueryError::UnknownFilter(text) => lang.tf(Key::UnknownFilter, &[("text", text)]),
+            QuickQueryError::SearchFilters => lang.t(Key::SearchFilters).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ResolvedStation {
+    Found(GetRZDPointCodes),
+    Ambiguous(Vec<GetRZDPointCodes>),
+    NotFound,
+}
+
+/// Parses `<откуда> <куда> <дата> [фильтры]`.
+/// Multi-word stations are separated with ` - `, e.g. `Нижний Новгород - Москва завтра`.
+pub fn parse_quick_query(
+    command: QuickCommand,
+    text: &str,
+    today: NaiveDate,
+) -> Result<QuickQuery, QuickQueryError> {
+    let words = text.split_whitespace().collect::<Vec<&str>>();
+    let date_idx = words
+        .iter()
+        .position(|w| parse_date_bounds(w, today).is_ok())
+        .ok_or(QuickQueryError::DateMissing)?;
+    let (from, to) = split_stations(&words[..date_idx])?;
+    let dates = parse_dates(words[date_idx], today)?;
+
+    let mut car_types = Vec::new();
+    let mut seats = None;
+    for word in words[date_idx + 1..].iter() {
+        let word = word.to_lowercase();
+        if let Some(car_type) = parse_car_type(&word) {
+            if !car_types.iter().any(|t| t == car_type) {
+                car_types.push(car_type.to_string());
+            }
+        } else if let Some(seat_filter) = parse_seat_filter(&word) {
+            seats = Some(seat_filter);
+        } else {
+            return Err(QuickQueryError::UnknownFilter(word));
+        }
+    }
+    if command == QuickCommand::Search && (!car_types.is_empty() || seats.is_some()) {
+        return Err(QuickQueryError::SearchFilters);
+    }
+
+    Ok(QuickQuery {
+        command,
+        from: expand_station_alias(&from),
+        to: expand_station_alias(&to),
+        from_code: None,
+        to_code: None,
+        dates,
+        car_types,
+        seats,
+    })
+}
+
+/// Picks the station the user most likely meant, an exact name match wins over the other suggestions.
+pub fn resolve_station(name: &str, mut codes: Vec<GetRZDPointCodes>) -> ResolvedStation {
+    if codes.is_empty() {
+        return ResolvedStation::NotFound;
+    }
+    if codes.len() == 1 {
+        return ResolvedStation::Found(codes.remove(0));
+    }
+    let name = name.to_lowercase();
+    match codes.iter().position(|c| c.name.to_lowercase() == name) {
+        Some(idx) => ResolvedStation::Found(codes.remove(idx)),
+        None => ResolvedStation::Ambiguous(codes),
+    }
+}
+
+fn split_stations(words: &[&str]) -> Result<(String, String), QuickQueryError> {
+    if let Some(idx) = words.iter().position(|w| STATION_SEPARATORS.contains(w)) {
+        let (from, to) = (words[..idx].join(" "), words[idx + 1..].join(" "));
+        if from.is_empty() || to.is_empty() {
+            return Err(QuickQueryError::StationMissing);
+        }
+        return Ok((from, to));
+    }
+    match words {
+        [from, to] => Ok((from.to_string(), to.to_string())),
+        [] | [_] => Err(QuickQueryError::StationMissing),
+        _ => Err(QuickQueryError::StationsNotSeparated),
+    }
+}
+
+fn expand_station_alias(name: &str) -> String {
+    match name.to_lowercase().as_str() {
+        "мск" => "Москва".to_string(),
+        "спб" | "питер" => "Санкт-Петербург".to_string(),
+        "нн" => "Нижний Новгород".to_string(),
+        "екб" => "Екатеринбург".to_string(),
+        _ => name.to_string(),
+    }
+}
+
+fn parse_date_bounds(
+    text: &str,
+    today: NaiveDate,
+) -> Result<(NaiveDate, NaiveDate), QuickQueryError> {
+    match text.split_once('-') {
+        Some((start, end)) if !start.is_empty() => Ok((
+            parse_relative_date(start, today)?,
+            parse_relative_date(end, today)?,
+        )),
+        _ => {
+            let date = parse_relative_date(text, today)?;
+            Ok((date, date))
+        }
+    }
+}
+
+fn parse_dates(text: &str, today: NaiveDate) -> Result<Vec<NaiveDate>, QuickQueryError> {
+    let (start, end) = parse_date_bounds(text, today)?;
+    if start < today {
+        return Err(QuickQueryError::DateInPast(start));
+    }
+    if end < start {
+        return Err(QuickQueryError::RangeReversed);
+    }
+    if (end - start).num_days() >= MAX_DATE_RANGE_DAYS {
+        return Err(QuickQueryError::RangeTooLong);
+    }
+    Ok(start.iter_days().take_while(|date| *date <= end).collect())
+}
+
+fn parse_relative_date(text: &str, today: NaiveDate) -> Result<NaiveDate, QuickQueryError> {
+    let text = text.to_lowercase();
+    let date = match text.as_str() {
+        "сегодня" | "today" => Some(today),
+        "завтра" | "tomorrow" => Some(today + Duration::days(1)),
+        "послезавтра" => Some(today + Duration::days(2)),
+        _ => {
+            if let Some(days) = text.strip_prefix('+') {
+                days.parse::<u64>()
+                    .ok()
+                    .and_then(|d| today.checked_add_days(Days::new(d)))
+            } else if let Some(weekday) = parse_weekday(&text) {
+                let days_ahead = (7 + weekday.num_days_from_monday() as i64
+                    - today.weekday().num_days_from_monday() as i64)
+                    % 7;
+                Some(today + Duration::days(days_ahead))
+            } else {
+                parse_short_date(&text, today)
+            }
+        }
+    };
+    date.ok_or(QuickQueryError::UnknownDate(text))
+}
+
+fn parse_weekday(text: &str) -> Option<Weekday> {
+    match text {
+        "пн" | "понедельник" => Some(Weekday::Mon),
+        "вт" | "вторник" => Some(Weekday::Tue),
+        "ср" | "среда" => Some(Weekday::Wed),
+        "чт" | "четверг" => Some(Weekday::Thu),
+        "пт" | "пятница" => Some(Weekday::Fri),
+        "сб" | "суббота" => Some(Weekday::Sat),
+        "вс" | "воскресенье" => Some(Weekday::Sun),
+        _ => text.parse::<Weekday>().ok(),
+    }
+}
+
+/// Also maps the lower-cased `cars[].type` of the RZD train list, which sends the short `Плац`, `Сид` and
+/// `Люкс` and keeps the full names in `typeLoc`.
+pub fn parse_car_type(word: &str) -> Option<&'static str> {
+    match word {
+        "купе" | "coupe" | "compartment" => Some("купе"),
+        "плац" | "плацкарт" | "плацкартный" | "berth" => Some("плацкартный"),
+        "св" | "люкс" | "sv" | "lux" => Some("св"),
+        "сид" | "сидячий" | "seated" => Some("сидячий"),
+        _ => None,
+    }
+}
+
+pub fn parse_seat_filter(word: &str) -> Option<SeatFilter> {
+    match word {
+        "нижние" | "нижнее" | "низ" | "lower" => Some(SeatFilter::Lower),
+        "верхние" | "верхнее" | "верх" | "upper" => Some(SeatFilter::Upper),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        // Friday
+        NaiveDate::from_ymd_opt(2026, 10, 16).unwrap()
+    }
+
+    fn date(day: u32, month: u32, year: i32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn station(code: &str, name: &str) -> GetRZDPointCodes {
+        GetRZDPointCodes {
+            code: code.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_simple_search() {
+        let query = parse_quick_query(
+            QuickCommand::Search,
+            "Москва Санкт-Петербург 21.10",
+            today(),
+        )
+        .unwrap();
+        assert_eq!(query.from, "Москва");
+        assert_eq!(query.to, "Санкт-Петербург");
+        assert_eq!(query.dates, vec![date(21, 10, 2026)]);
+        assert!(query.car_types.is_empty());
+        assert_eq!(query.seats, None);
+    }
+
+    #[test]
+    fn parses_watch_with_range_and_filters() {
+        let query = parse_quick_query(
+            QuickCommand::Watch,
+            "МСК СПБ 21.10-23.10 купе нижние",
+            today(),
+        )
+        .unwrap();
+        assert_eq!(query.from, "Москва");
+        assert_eq!(query.to, "Санкт-Петербург");
+        assert_eq!(
+            query.dates,
+            vec![date(21, 10, 2026), date(22, 10, 2026), date(23, 10, 2026)]
+        );
+        assert_eq!(query.car_types, vec!["купе".to_string()]);
+        assert_eq!(query.seats, Some(SeatFilter::Lower));
+    }
+
+    #[test]
+    fn parses_multi_word_stations() {
+        let query = parse_quick_query(
+            QuickCommand::Search,
+            "Нижний Новгород - Москва завтра",
+            today(),
+        )
+        .unwrap();
+        assert_eq!(query.from, "Нижний Новгород");
+        assert_eq!(query.to, "Москва");
+        assert_eq!(query.dates, vec![date(17, 10, 2026)]);
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        let parse = |text: &str| parse_relative_date(text, today()).unwrap();
+        assert_eq!(parse("сегодня"), date(16, 10, 2026));
+        assert_eq!(parse("послезавтра"), date(18, 10, 2026));
+        assert_eq!(parse("+10"), date(26, 10, 2026));
+        assert_eq!(parse("пт"), date(16, 10, 2026));
+        assert_eq!(parse("понедельник"), date(19, 10, 2026));
+        assert_eq!(parse("21.10.2026"), date(21, 10, 2026));
+        assert_eq!(parse("01.02"), date(1, 2, 2027));
+        for text in ["+999999999", "+-3", "+"] {
+            assert_eq!(
+                parse_relative_date(text, today()),
+                Err(QuickQueryError::UnknownDate(text.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn parses_english_keywords() {
+        let query = parse_quick_query(
+            QuickCommand::Watch,
+            "Москва Казань monday coupe lower",
+            today(),
+        )
+        .unwrap();
+        assert_eq!(query.command, QuickCommand::Watch);
+        assert_eq!(query.dates, vec![date(19, 10, 2026)]);
+        assert_eq!(query.car_types, vec!["купе".to_string()]);
+        assert_eq!(query.seats, Some(SeatFilter::Lower));
+        assert_eq!(
+            parse_relative_date("tomorrow", today()).unwrap(),
+            date(17, 10, 2026)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_queries() {
+        assert!(
+            parse_quick_query(QuickCommand::Search, "Москва Санкт-Петербург", today()).is_err()
+        );
+        assert!(parse_quick_query(QuickCommand::Search, "Москва 21.10", today()).is_err());
+        assert!(parse_quick_query(
+            QuickCommand::Search,
+            "Нижний Новгород Москва 21.10",
+            today()
+        )
+        .is_err());
+        assert_eq!(
+            parse_quick_query(QuickCommand::Search, "Москва Казань 21.10 бизнес", today()),
+            Err(QuickQueryError::UnknownFilter("бизнес".to_string()))
+        );
+        for filters in ["плац", "нижние", "купе верх"] {
+            assert_eq!(
+                parse_quick_query(
+                    QuickCommand::Search,
+                    &format!("МСК СПБ 21.10 {filters}"),
+                    today()
+                ),
+                Err(QuickQueryError::SearchFilters)
+            );
+        }
+        assert!(
+            parse_quick_query(QuickCommand::Search, "Москва Казань 21.10.2025", today()).is_err()
+        );
+        assert!(
+            parse_quick_query(QuickCommand::Search, "Москва Казань 23.10-21.10", today()).is_err()
+        );
+        assert!(
+            parse_quick_query(QuickCommand::Search, "Москва Казань 01.11-30.11", today()).is_err()
+        );
+    }
+
+    #[test]
+    fn maps_rzd_car_types() {
+        for (rzd_type, car_type) in [
+            ("Купе", Some("купе")),
+            ("Плац", Some("плацкартный")),
+            ("Сид", Some("сидячий")),
+            ("Люкс", Some("св")),
+            ("Мягкий", None),
+            ("Общ", None),
+        ] {
+            assert_eq!(parse_car_type(&rzd_type.to_lowercase()), car_type);
+        }
+    }
+
+    #[test]
+    fn resolves_exact_station_match() {
+        let codes = vec![
+            station("2000000", "МОСКВА"),
+            station("2000001", "МОСКВА КУРСКИЙ ВОКЗАЛ"),
+        ];
+        assert_eq!(
+            resolve_station("Москва", codes),
+            ResolvedStation::Found(station("2000000", "МОСКВА"))
+        );
+    }
+
+    #[test]
+    fn resolves_ambiguous_and_missing_stations() {
+        let codes = vec![
+            station("2000001", "МОСКВА КУРСКИЙ ВОКЗАЛ"),
+            station("2000002", "МОСКВА ЯРОСЛАВСКИЙ ВОКЗАЛ"),
+        ];
+        assert_eq!(
+            resolve_station("Москва", codes.clone()),
+            ResolvedStation::Ambiguous(codes)
+        );
+        assert_eq!(resolve_station("Москва", vec![]), ResolvedStation::NotFound);
+        assert_eq!(
+            resolve_station("Мос", vec![station("2000000", "МОСКВА")]),
+            ResolvedStation::Found(station("2000000", "МОСКВА"))
+        );
+    }
+}
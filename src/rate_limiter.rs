@@ -0,0 +1,233 @@
+use std::env;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::metrics::{Metrics, RZD_LIMITER_QUEUE, RZD_LIMITER_WAIT};
+
+const DEFAULT_RATE_PER_SEC: f64 = 1.0;
+const DEFAULT_BURST: f64 = 5.0;
+
+tokio::task_local! {
+    static PRIORITY: Priority;
+}
+
+/// Interactive requests take free tokens first, background ones wait while any of them is queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Interactive,
+    Background,
+}
+
+impl Priority {
+    /// Priority of the RZD requests made by the current task, interactive unless set with [`background`].
+    pub fn current() -> Self {
+        PRIORITY
+            .try_with(|priority| *priority)
+            .unwrap_or(Priority::Interactive)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::Interactive => "interactive",
+            Priority::Background => "background",
+        }
+    }
+}
+
+/// Runs the future with its RZD requests queued behind the interactive ones.
+pub async fn background<F: Future>(future: F) -> F::Output {
+    PRIORITY.scope(Priority::Background, future).await
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    interactive_waiting: usize,
+    background_waiting: usize,
+}
+
+/// Token bucket shared by every outbound RZD request.
+pub struct RateLimiter {
+    rate_per_sec: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+    metrics: Arc<Metrics>,
+}
+
+impl RateLimiter {
+    /// `RZD_RATE_PER_SEC` tokens are added every second, up to `RZD_BURST` of them are kept.
+    pub fn from_env(metrics: Arc<Metrics>) -> Self {
+        let var = |name: &str, default: f64| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|value| *value > 0.0)
+                .unwrap_or(default)
+        };
+        Self::new(
+            var("RZD_RATE_PER_SEC", DEFAULT_RATE_PER_SEC),
+            var("RZD_BURST", DEFAULT_BURST),
+            metrics,
+        )
+    }
+
+    /// Starts with a full bucket, it holds at least one token.
+    pub fn new(rate_per_sec: f64, burst: f64, metrics: Arc<Metrics>) -> Self {
+        let burst = burst.max(1.0);
+        Self {
+            rate_per_sec,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+                interactive_waiting: 0,
+                background_waiting: 0,
+            }),
+            metrics,
+        }
+    }
+
+    /// Waits for a token, the wait is reported per priority.
+    pub async fn acquire(&self, priority: Priority) {
+        let started = Instant::now();
+        let _queued = Queued::enter(self, priority);
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let refilled =
+                    now.duration_since(bucket.refilled_at).as_secs_f64() * self.rate_per_sec;
+                bucket.tokens = (bucket.tokens + refilled).min(self.burst);
+                bucket.refilled_at = now;
+                let yields = priority == Priority::Background && bucket.interactive_waiting > 0;
+                if bucket.tokens >= 1.0 && !yields {
+                    bucket.tokens -= 1.0;
+                    break;
+                }
+                Duration::from_secs_f64(((1.0 - bucket.tokens) / self.rate_per_sec).max(0.0))
+                    .max(Duration::from_millis(10))
+            };
+            tokio::time::sleep(wait).await;
+        }
+        self.metrics.observe(
+            RZD_LIMITER_WAIT,
+            &[("priority", priority.as_str())],
+            started.elapsed().as_secs_f64(),
+        );
+    }
+
+    fn update_queue(&self, priority: Priority, enter: bool) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let waiting = match priority {
+            Priority::Interactive => &mut bucket.interactive_waiting,
+            Priority::Background => &mut bucket.background_waiting,
+        };
+        if enter {
+            *waiting += 1;
+        } else {
+            *waiting -= 1;
+        }
+        self.metrics.set(
+            RZD_LIMITER_QUEUE,
+            &[("priority", priority.as_str())],
+            *waiting as f64,
+        );
+    }
+}
+
+/// Keeps the request counted in the queue until it gets a token or is cancelled.
+struct Queued<'a> {
+    limiter: &'a RateLimiter,
+    priority: Priority,
+}
+
+impl<'a> Queued<'a> {
+    fn enter(limiter: &'a RateLimiter, priority: Priority) -> Self {
+        limiter.update_queue(priority, true);
+        Self { limiter, priority }
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.limiter.update_queue(self.priority, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn timed_acquire(limiter: &RateLimiter, priority: Priority) -> Duration {
+        let started = Instant::now();
+        limiter.acquire(priority).await;
+        started.elapsed()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refills_at_the_rate_up_to_the_burst() {
+        let limiter = RateLimiter::new(2.0, 3.0, Metrics::new());
+        for _ in 0..3 {
+            assert_eq!(
+                timed_acquire(&limiter, Priority::Interactive).await,
+                Duration::ZERO
+            );
+        }
+        assert_eq!(
+            timed_acquire(&limiter, Priority::Interactive).await,
+            Duration::from_millis(500)
+        );
+
+        // A long idle time still leaves only the burst
+        tokio::time::advance(Duration::from_secs(60)).await;
+        for _ in 0..3 {
+            assert_eq!(
+                timed_acquire(&limiter, Priority::Background).await,
+                Duration::ZERO
+            );
+        }
+        assert_eq!(
+            timed_acquire(&limiter, Priority::Background).await,
+            Duration::from_millis(500)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn interactive_requests_go_before_queued_background_ones() {
+        let limiter = Arc::new(RateLimiter::new(1.0, 1.0, Metrics::new()));
+        limiter.acquire(Priority::Interactive).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let spawn = |priority: Priority| {
+            let limiter = limiter.clone();
+            let order = order.clone();
+            tokio::spawn(async move {
+                limiter.acquire(priority).await;
+                order.lock().unwrap().push(priority);
+            })
+        };
+        let background = spawn(Priority::Background);
+        tokio::task::yield_now().await;
+        let interactive = spawn(Priority::Interactive);
+        interactive.await.unwrap();
+        background.await.unwrap();
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec![Priority::Interactive, Priority::Background]
+        );
+    }
+
+    #[tokio::test]
+    async fn priority_is_scoped_to_the_background_future() {
+        assert_eq!(Priority::current(), Priority::Interactive);
+        assert_eq!(
+            background(async { Priority::current() }).await,
+            Priority::Background
+        );
+        assert_eq!(Priority::current(), Priority::Interactive);
+    }
+}
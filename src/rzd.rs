@@ -1,20 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::default::Default;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_recursion::async_recursion;
+use chrono::{DateTime, Local};
 use fake_useragent::{Browsers, UserAgents, UserAgentsBuilder};
 use reqwest::header::{ACCEPT, CONTENT_TYPE};
 use reqwest::StatusCode;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::json;
 use tokio::sync::Mutex;
+use tracing::Instrument;
+
+use crate::health::Health;
+use crate::metrics::{Metrics, RZD_LATENCY, RZD_REQUESTS, RZD_RETRIES, RZD_RID_POLLS};
+use crate::rate_limiter::{Priority, RateLimiter};
 
 const BASE_API_URL: &str = "https://ticket.rzd.ru/api/v1";
 const BASE_PASS_URL: &str = "https://pass.rzd.ru";
 const ROUTES_LAYER: usize = 5827;
 const CARRIEAGES_LAYER: usize = 5764;
+const RECENT_ERRORS_LIMIT: usize = 50;
 
 fn places_deserialize<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
@@ -26,7 +33,7 @@ where
         .map(|item| item.to_owned())
         .collect())
 }
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct GetRZDPointCodes {
     #[serde(rename = "expressCode")]
     pub(crate) code: String,
@@ -43,6 +50,10 @@ pub struct GetRZDTrainsCars {
 
     #[serde(rename = "freeSeats")]
     pub(crate) free_seats: usize,
+
+    /// Lowest price in the carriages of this type, in roubles.
+    #[serde(default)]
+    pub(crate) tariff: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -70,6 +81,17 @@ pub struct GetRZDTrainsCarriagesCars {
     pub(crate) cnumber: String,
     #[serde(rename = "type")]
     pub(crate) _type: String,
+    /// Free places of the carriage by kind, the train list only has the totals.
+    #[serde(default)]
+    pub(crate) seats: Vec<GetRZDTrainsCarriagesSeats>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GetRZDTrainsCarriagesSeats {
+    /// `up` and `dn` for the upper and lower berths, `lup` and `ldn` for the side ones.
+    #[serde(rename = "type")]
+    pub(crate) _type: String,
+    pub(crate) free: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -82,25 +104,185 @@ pub struct GetRZDTrainsCarriagesResponse {
     pub(crate) lst: Vec<GetRZDTrainsCarriagesListResponse>,
 }
 
+#[derive(Debug, Clone)]
+pub struct RZDError {
+    pub(crate) at: DateTime<Local>,
+    pub(crate) method: &'static str,
+    pub(crate) error: String,
+}
+
+/// Outcomes of the public RZDApi methods since the start, shown by the admin commands.
+#[derive(Default)]
+struct CallLog {
+    calls: u64,
+    failures: u64,
+    recent_errors: VecDeque<RZDError>,
+}
+
 pub struct RZDApi {
     ua: Mutex<UserAgents>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    limiter: RateLimiter,
+    call_log: std::sync::Mutex<CallLog>,
 }
 impl RZDApi {
     #[must_use]
-    pub fn new() -> Arc<Self> {
+    pub fn new(metrics: Arc<Metrics>, health: Arc<Health>) -> Arc<Self> {
         let user_agents = UserAgentsBuilder::new()
             .set_browsers(Browsers::new().set_chrome().set_edge().set_firefox())
             .cache(false)
             .build();
         Arc::new(Self {
             ua: Mutex::from(user_agents),
+            limiter: RateLimiter::from_env(metrics.clone()),
+            metrics,
+            health,
+            call_log: Default::default(),
         })
     }
-    #[async_recursion]
+
     pub async fn get_rzd_point_codes(
         &self,
         part_or_full_name: String,
         retry_counter: isize,
+    ) -> Result<Vec<GetRZDPointCodes>, String> {
+        let started = Instant::now();
+        let span = tracing::info_span!(
+            "rzd_call",
+            method = "get_rzd_point_codes",
+            part_or_full_name = %part_or_full_name,
+            retries = retry_counter,
+        );
+        let result = self
+            .fetch_rzd_point_codes(part_or_full_name, retry_counter)
+            .instrument(span)
+            .await;
+        self.record_call("get_rzd_point_codes", started, &result);
+        result
+    }
+
+    pub async fn get_trains_from_rzd(
+        &self,
+        point_from: String,
+        point_to: String,
+        date: String,
+        retry_counter: isize,
+    ) -> Result<GetRZDTrainsResponse, String> {
+        let started = Instant::now();
+        let span = tracing::info_span!(
+            "rzd_call",
+            method = "get_trains_from_rzd",
+            point_from = %point_from,
+            point_to = %point_to,
+            date = %date,
+            retries = retry_counter,
+        );
+        let result = self
+            .fetch_trains_from_rzd(point_from, point_to, date, retry_counter)
+            .instrument(span)
+            .await;
+        self.record_call("get_trains_from_rzd", started, &result);
+        result
+    }
+
+    pub async fn get_trains_carriages_from_rzd(
+        &self,
+        point_from: String,
+        point_to: String,
+        dt0: String,
+        time0: String,
+        tnum0: String,
+        retry_counter: isize,
+    ) -> Result<GetRZDTrainsCarriagesResponse, String> {
+        let started = Instant::now();
+        let span = tracing::info_span!(
+            "rzd_call",
+            method = "get_trains_carriages_from_rzd",
+            point_from = %point_from,
+            point_to = %point_to,
+            dt0 = %dt0,
+            time0 = %time0,
+            tnum0 = %tnum0,
+            retries = retry_counter,
+        );
+        let result = self
+            .fetch_trains_carriages_from_rzd(point_from, point_to, dt0, time0, tnum0, retry_counter)
+            .instrument(span)
+            .await;
+        self.record_call("get_trains_carriages_from_rzd", started, &result);
+        result
+    }
+
+    /// Every HTTP request to RZD goes through the shared rate limiter first.
+    async fn wait_for_slot(&self) {
+        self.limiter.acquire(Priority::current()).await;
+    }
+
+    fn record_request(&self, endpoint: &str, outcome: &str) {
+        tracing::debug!(endpoint, outcome, "rzd request finished");
+        if outcome == "200" {
+            self.health.mark_rzd_success();
+        }
+        self.metrics.inc(
+            RZD_REQUESTS,
+            &[("endpoint", endpoint), ("outcome", outcome)],
+        );
+    }
+
+    fn record_retry(&self, method: &str) {
+        self.metrics.inc(RZD_RETRIES, &[("method", method)]);
+    }
+
+    fn record_call<T>(&self, method: &'static str, started: Instant, result: &Result<T, String>) {
+        self.metrics.observe(
+            RZD_LATENCY,
+            &[("method", method)],
+            started.elapsed().as_secs_f64(),
+        );
+        let mut call_log = self.call_log.lock().unwrap();
+        call_log.calls += 1;
+        if let Err(err) = result {
+            call_log.failures += 1;
+            if call_log.recent_errors.len() == RECENT_ERRORS_LIMIT {
+                call_log.recent_errors.pop_back();
+            }
+            call_log.recent_errors.push_front(RZDError {
+                at: Local::now(),
+                method,
+                error: err.clone(),
+            });
+        }
+    }
+
+    /// Calls and failed calls of the public methods since the start.
+    pub fn call_stats(&self) -> (u64, u64) {
+        let call_log = self.call_log.lock().unwrap();
+        (call_log.calls, call_log.failures)
+    }
+
+    /// Newest errors first, at most the last 50 are kept.
+    pub fn recent_errors(&self, limit: usize) -> Vec<RZDError> {
+        let call_log = self.call_log.lock().unwrap();
+        call_log.recent_errors.iter().take(limit).cloned().collect()
+    }
+
+    /// Every attempt gets its own span, retries recurse into it with a smaller `retry_counter`.
+    #[async_recursion]
+    async fn fetch_rzd_point_codes(
+        &self,
+        part_or_full_name: String,
+        retry_counter: isize,
+    ) -> Result<Vec<GetRZDPointCodes>, String> {
+        self.attempt_rzd_point_codes(part_or_full_name, retry_counter)
+            .instrument(tracing::info_span!("attempt", retries_left = retry_counter))
+            .await
+    }
+
+    async fn attempt_rzd_point_codes(
+        &self,
+        part_or_full_name: String,
+        retry_counter: isize,
     ) -> Result<Vec<GetRZDPointCodes>, String> {
         if retry_counter == -1 {
             return Err("Error on fetching info from rzd".to_string());
@@ -125,6 +307,7 @@ impl RZDApi {
             &query_params,
         )
         .unwrap();
+        self.wait_for_slot().await;
         let result = client
             .get(url)
             .header(ACCEPT, "application/json")
@@ -132,17 +315,21 @@ impl RZDApi {
             .await;
 
         if result.is_err() {
+            self.record_request("suggests", "network_error");
+            self.record_retry("get_rzd_point_codes");
             return self
-                .get_rzd_point_codes(part_or_full_name.clone(), retry_counter - 1)
+                .fetch_rzd_point_codes(part_or_full_name.clone(), retry_counter - 1)
                 .await;
         }
 
         let r = result.unwrap();
         if r.status() != 200 {
-            log::warn!("got {} from rzd in function get_rzd_point_codes with params: part_or_full_name = {}, retry_counter = {}", r.status(), part_or_full_name, retry_counter);
+            self.record_request("suggests", r.status().as_str());
+            tracing::warn!(status = %r.status(), endpoint = "suggests", "unexpected response from rzd");
             if r.status().as_u16() == StatusCode::FORBIDDEN.as_u16() {
+                self.record_retry("get_rzd_point_codes");
                 return self
-                    .get_rzd_point_codes(part_or_full_name.clone(), retry_counter - 1)
+                    .fetch_rzd_point_codes(part_or_full_name.clone(), retry_counter - 1)
                     .await;
             }
             return Err(format!("Invalid response code from rzd {}", r.status()));
@@ -150,19 +337,36 @@ impl RZDApi {
 
         let json_response = r.json::<HashMap<String, serde_json::Value>>().await;
         if let Err(err) = json_response {
+            self.record_request("suggests", "decode_error");
             return Err(format!("Error on deserialize json {}", err));
         }
 
-        return match json_response.unwrap().get("city") {
-            None => Err("cant get cities for given request from rzd".to_string()),
+        match json_response.unwrap().get("city") {
+            None => {
+                self.record_request("suggests", "decode_error");
+                Err("cant get cities for given request from rzd".to_string())
+            }
             Some(v) => {
+                self.record_request("suggests", "200");
                 let cities_serialized = serde_json::from_value(v.clone()).unwrap();
                 Ok(cities_serialized)
             }
-        };
+        }
     }
     #[async_recursion]
-    pub async fn get_trains_from_rzd(
+    async fn fetch_trains_from_rzd(
+        &self,
+        point_from: String,
+        point_to: String,
+        date: String,
+        retry_counter: isize,
+    ) -> Result<GetRZDTrainsResponse, String> {
+        self.attempt_trains_from_rzd(point_from, point_to, date, retry_counter)
+            .instrument(tracing::info_span!("attempt", retries_left = retry_counter))
+            .await
+    }
+
+    async fn attempt_trains_from_rzd(
         &self,
         point_from: String,
         point_to: String,
@@ -192,14 +396,17 @@ impl RZDApi {
             &query_params,
         )
         .unwrap();
+        self.wait_for_slot().await;
         let result = client
             .get(url)
             .header(ACCEPT, "application/json")
             .send()
             .await;
         if result.is_err() {
+            self.record_request("timetable", "network_error");
+            self.record_retry("get_trains_from_rzd");
             return self
-                .get_trains_from_rzd(
+                .fetch_trains_from_rzd(
                     point_from.clone(),
                     point_to.clone(),
                     date.clone(),
@@ -210,10 +417,12 @@ impl RZDApi {
 
         let r = result.unwrap();
         if r.status() != 200 {
-            log::warn!("got {} from rzd in function get_trains_from_rzd with params: point_from = {}, point_to = {}, date = {}, retry_counter = {}", r.status(), point_from, point_to, date, retry_counter);
+            self.record_request("timetable", r.status().as_str());
+            tracing::warn!(status = %r.status(), endpoint = "timetable", "unexpected response from rzd");
             if r.status().as_u16() == StatusCode::FORBIDDEN.as_u16() {
+                self.record_retry("get_trains_from_rzd");
                 return self
-                    .get_trains_from_rzd(
+                    .fetch_trains_from_rzd(
                         point_from.clone(),
                         point_to.clone(),
                         date.clone(),
@@ -225,6 +434,7 @@ impl RZDApi {
         }
         let response_string_result = r.text().await;
         if let Err(err) = response_string_result {
+            self.record_request("timetable", "decode_error");
             return Err(format!("Error on getting response bytes {}", err));
         }
         let response_string = response_string_result.unwrap();
@@ -235,6 +445,7 @@ impl RZDApi {
         );
 
         if let Err(err) = rid_response_result {
+            self.record_request("timetable", "decode_error");
             return Err(format!("Error on deserialize json {}", err));
         }
 
@@ -245,8 +456,10 @@ impl RZDApi {
                 .unwrap_or(&json!(""))
                 .eq(&json!("FAIL"))
             {
+                self.record_request("timetable", "FAIL");
+                self.record_retry("get_trains_from_rzd");
                 return self
-                    .get_trains_from_rzd(
+                    .fetch_trains_from_rzd(
                         point_from.clone(),
                         point_to.clone(),
                         date.clone(),
@@ -259,11 +472,18 @@ impl RZDApi {
                     .as_str()
                     .trim_matches(|c: char| c.is_whitespace() || c == '\"'),
             ) {
-                Ok(v) => return Ok(v),
-                Err(err) => Err(format!("Error on deserialize json {}", err)),
+                Ok(v) => {
+                    self.record_request("timetable", "200");
+                    Ok(v)
+                }
+                Err(err) => {
+                    self.record_request("timetable", "decode_error");
+                    Err(format!("Error on deserialize json {}", err))
+                }
             };
         }
 
+        self.record_request("timetable", "200");
         let mut c = 0;
         let rid = rid_response.get("RID").unwrap();
 
@@ -275,17 +495,21 @@ impl RZDApi {
             )
             .unwrap();
 
+            self.wait_for_slot().await;
             let result = client
                 .post(url)
                 .header(ACCEPT, "application/json")
                 .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
                 .body(format!("rid={rid}"))
                 .send()
+                .instrument(tracing::info_span!("rid_poll", rid_iteration = c))
                 .await;
 
             if result.is_err() {
+                self.record_request("timetable_rid", "network_error");
+                self.record_retry("get_trains_from_rzd");
                 return self
-                    .get_trains_from_rzd(
+                    .fetch_trains_from_rzd(
                         point_from.clone(),
                         point_to.clone(),
                         date.clone(),
@@ -296,10 +520,12 @@ impl RZDApi {
 
             let r = result.unwrap();
             if r.status() != 200 {
-                log::warn!("got {} from rzd in function get_trains_from_rzd with params: point_from = {}, point_to = {}, date = {}, retry_counter = {}", r.status(), point_from, point_to, date, retry_counter);
+                self.record_request("timetable_rid", r.status().as_str());
+                tracing::warn!(status = %r.status(), endpoint = "timetable_rid", rid_iteration = c, "unexpected response from rzd");
                 if r.status().as_u16() == StatusCode::FORBIDDEN.as_u16() {
+                    self.record_retry("get_trains_from_rzd");
                     return self
-                        .get_trains_from_rzd(
+                        .fetch_trains_from_rzd(
                             point_from.clone(),
                             point_to.clone(),
                             date.clone(),
@@ -312,6 +538,7 @@ impl RZDApi {
 
             let response_string_result = r.text().await;
             if let Err(err) = response_string_result {
+                self.record_request("timetable_rid", "decode_error");
                 return Err(format!("Error on getting response bytes {}", err));
             }
             let response_string = response_string_result.unwrap();
@@ -322,6 +549,7 @@ impl RZDApi {
             );
 
             if let Err(err) = rid_response_result {
+                self.record_request("timetable_rid", "decode_error");
                 return Err(format!("Error on deserialize json {}", err));
             }
 
@@ -332,8 +560,10 @@ impl RZDApi {
                     .unwrap_or(&json!("".to_string()))
                     .eq(&json!("FAIL"))
                 {
+                    self.record_request("timetable_rid", "FAIL");
+                    self.record_retry("get_trains_from_rzd");
                     return self
-                        .get_trains_from_rzd(
+                        .fetch_trains_from_rzd(
                             point_from.clone(),
                             point_to.clone(),
                             date.clone(),
@@ -346,10 +576,19 @@ impl RZDApi {
                         .as_str()
                         .trim_matches(|c: char| c.is_whitespace() || c == '\"'),
                 ) {
-                    Ok(v) => return Ok(v),
-                    Err(err) => Err(format!("Error on deserialize json {}", err)),
+                    Ok(v) => {
+                        self.record_request("timetable_rid", "200");
+                        Ok(v)
+                    }
+                    Err(err) => {
+                        self.record_request("timetable_rid", "decode_error");
+                        Err(format!("Error on deserialize json {}", err))
+                    }
                 };
             }
+            self.record_request("timetable_rid", "200");
+            self.metrics
+                .inc(RZD_RID_POLLS, &[("method", "get_trains_from_rzd")]);
             c += 1;
 
             if c > 5 {
@@ -360,7 +599,28 @@ impl RZDApi {
     }
 
     #[async_recursion]
-    pub async fn get_trains_carriages_from_rzd(
+    async fn fetch_trains_carriages_from_rzd(
+        &self,
+        point_from: String,
+        point_to: String,
+        dt0: String,
+        time0: String,
+        tnum0: String,
+        retry_counter: isize,
+    ) -> Result<GetRZDTrainsCarriagesResponse, String> {
+        self.attempt_trains_carriages_from_rzd(
+            point_from,
+            point_to,
+            dt0,
+            time0,
+            tnum0,
+            retry_counter,
+        )
+        .instrument(tracing::info_span!("attempt", retries_left = retry_counter))
+        .await
+    }
+
+    async fn attempt_trains_carriages_from_rzd(
         &self,
         point_from: String,
         point_to: String,
@@ -391,14 +651,17 @@ impl RZDApi {
             &query_params,
         )
         .unwrap();
+        self.wait_for_slot().await;
         let result = client
             .get(url)
             .header(ACCEPT, "application/json")
             .send()
             .await;
         if result.is_err() {
+            self.record_request("carriages", "network_error");
+            self.record_retry("get_trains_carriages_from_rzd");
             return self
-                .get_trains_carriages_from_rzd(
+                .fetch_trains_carriages_from_rzd(
                     point_from.clone(),
                     point_to.clone(),
                     dt0.clone(),
@@ -411,10 +674,12 @@ impl RZDApi {
 
         let r = result.unwrap();
         if r.status() != 200 {
-            log::warn!("got {} from rzd in function get_trains_carriages_from_rzd with params: point_from = {}, point_to = {}, dt0 = {}, time0 = {}, tnum0 = {}, retry_counter = {}", r.status(), point_from, point_to, dt0, time0, tnum0, retry_counter);
+            self.record_request("carriages", r.status().as_str());
+            tracing::warn!(status = %r.status(), endpoint = "carriages", "unexpected response from rzd");
             if r.status().as_u16() == StatusCode::FORBIDDEN.as_u16() {
+                self.record_retry("get_trains_carriages_from_rzd");
                 return self
-                    .get_trains_carriages_from_rzd(
+                    .fetch_trains_carriages_from_rzd(
                         point_from.clone(),
                         point_to.clone(),
                         dt0.clone(),
@@ -428,6 +693,7 @@ impl RZDApi {
         }
         let response_string_result = r.text().await;
         if let Err(err) = response_string_result {
+            self.record_request("carriages", "decode_error");
             return Err(format!("Error on getting response bytes {}", err));
         }
         let response_string = response_string_result.unwrap();
@@ -438,6 +704,7 @@ impl RZDApi {
         );
 
         if let Err(err) = rid_response_result {
+            self.record_request("carriages", "decode_error");
             return Err(format!("Error on deserialize json {}", err));
         }
 
@@ -448,8 +715,10 @@ impl RZDApi {
                 .unwrap_or(&json!(""))
                 .eq(&json!("FAIL"))
             {
+                self.record_request("carriages", "FAIL");
+                self.record_retry("get_trains_carriages_from_rzd");
                 return self
-                    .get_trains_carriages_from_rzd(
+                    .fetch_trains_carriages_from_rzd(
                         point_from.clone(),
                         point_to.clone(),
                         dt0.clone(),
@@ -464,26 +733,37 @@ impl RZDApi {
                     .as_str()
                     .trim_matches(|c: char| c.is_whitespace() || c == '\"'),
             ) {
-                Ok(v) => return Ok(v),
-                Err(err) => Err(format!("Error on deserialize json {}", err)),
+                Ok(v) => {
+                    self.record_request("carriages", "200");
+                    Ok(v)
+                }
+                Err(err) => {
+                    self.record_request("carriages", "decode_error");
+                    Err(format!("Error on deserialize json {}", err))
+                }
             };
         }
 
+        self.record_request("carriages", "200");
         let mut c = 0;
         let rid = rid_response.get("RID").unwrap();
 
         loop {
+            self.wait_for_slot().await;
             let result = client
                 .post(&(BASE_PASS_URL.to_owned() + "/timetable/public/ru"))
                 .header(ACCEPT, "application/json")
                 .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
                 .body(format!("rid={rid}&layer_id={CARRIEAGES_LAYER}&dir=0&code0={point_from}&code1={point_to}&dt0={dt0}&time0={time0}&tnum0={tnum0}"))
                 .send()
+                .instrument(tracing::info_span!("rid_poll", rid_iteration = c))
                 .await;
 
             if result.is_err() {
+                self.record_request("carriages_rid", "network_error");
+                self.record_retry("get_trains_carriages_from_rzd");
                 return self
-                    .get_trains_carriages_from_rzd(
+                    .fetch_trains_carriages_from_rzd(
                         point_from.clone(),
                         point_to.clone(),
                         dt0.clone(),
@@ -496,10 +776,12 @@ impl RZDApi {
 
             let r = result.unwrap();
             if r.status() != 200 {
-                log::warn!("got {} from rzd in function get_trains_carriages_from_rzd with params: point_from = {}, point_to = {}, dt0 = {}, time0 = {}, tnum0 = {}, retry_counter = {}", r.status(), point_from, point_to, dt0, time0, tnum0, retry_counter);
+                self.record_request("carriages_rid", r.status().as_str());
+                tracing::warn!(status = %r.status(), endpoint = "carriages_rid", rid_iteration = c, "unexpected response from rzd");
                 if r.status().as_u16() == StatusCode::FORBIDDEN.as_u16() {
+                    self.record_retry("get_trains_carriages_from_rzd");
                     return self
-                        .get_trains_carriages_from_rzd(
+                        .fetch_trains_carriages_from_rzd(
                             point_from.clone(),
                             point_to.clone(),
                             dt0.clone(),
@@ -514,6 +796,7 @@ impl RZDApi {
 
             let response_string_result = r.text().await;
             if let Err(err) = response_string_result {
+                self.record_request("carriages_rid", "decode_error");
                 return Err(format!("Error on getting response bytes {}", err));
             }
             let response_string = response_string_result.unwrap();
@@ -524,6 +807,7 @@ impl RZDApi {
             );
 
             if let Err(err) = rid_response_result {
+                self.record_request("carriages_rid", "decode_error");
                 return Err(format!("Error on deserialize json {}", err));
             }
 
@@ -534,8 +818,10 @@ impl RZDApi {
                     .unwrap_or(&json!("".to_string()))
                     .eq(&json!("FAIL"))
                 {
+                    self.record_request("carriages_rid", "FAIL");
+                    self.record_retry("get_trains_carriages_from_rzd");
                     return self
-                        .get_trains_carriages_from_rzd(
+                        .fetch_trains_carriages_from_rzd(
                             point_from.clone(),
                             point_to.clone(),
                             dt0.clone(),
@@ -550,10 +836,21 @@ impl RZDApi {
                         .as_str()
                         .trim_matches(|c: char| c.is_whitespace() || c == '\"'),
                 ) {
-                    Ok(v) => return Ok(v),
-                    Err(err) => Err(format!("Error on deserialize json {}", err)),
+                    Ok(v) => {
+                        self.record_request("carriages_rid", "200");
+                        Ok(v)
+                    }
+                    Err(err) => {
+                        self.record_request("carriages_rid", "decode_error");
+                        Err(format!("Error on deserialize json {}", err))
+                    }
                 };
             }
+            self.record_request("carriages_rid", "200");
+            self.metrics.inc(
+                RZD_RID_POLLS,
+                &[("method", "get_trains_carriages_from_rzd")],
+            );
             c += 1;
 
             if c > 5 {
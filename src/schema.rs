@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Version of the task records written by this build.
+pub const TASK_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades the data of a version `n` record to version `n + 1`.
+type Migration = fn(Value) -> Result<Value, String>;
+
+const MIGRATIONS: [Migration; TASK_VERSION as usize] = [from_unversioned];
+
+/// Records written before the envelope are the bare task map, the data itself did not change.
+fn from_unversioned(data: Value) -> Result<Value, String> {
+    Ok(data)
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    version: u32,
+    data: &'a T,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DecodedTask {
+    pub data: HashMap<String, String>,
+    /// The record is stored in an older version and should be rewritten.
+    pub upgraded: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Written by a newer build, left as is so a rollback does not destroy it.
+    Newer(u32),
+    /// Can't be read by any version, such records are quarantined.
+    Invalid(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Newer(version) => write!(f, "stored in a newer version {version}"),
+            DecodeError::Invalid(err) => f.write_str(err),
+        }
+    }
+}
+
+pub fn encode_task(data: &HashMap<String, String>) -> Result<Vec<u8>, String> {
+    serde_json::to_vec(&Envelope {
+        version: TASK_VERSION,
+        data,
+    })
+    .map_err(|err| format!("cant serialize task {err}"))
+}
+
+/// Decodes a task of any known version, migrating it to the current one in memory.
+pub fn decode_task(value: &[u8]) -> Result<DecodedTask, DecodeError> {
+    let value: Value = serde_json::from_slice(value)
+        .map_err(|err| DecodeError::Invalid(format!("not a json {err}")))?;
+    let (version, mut data) = match value {
+        Value::Object(mut record)
+            if record.contains_key("version") && record.contains_key("data") =>
+        {
+            let version = record
+                .get("version")
+                .and_then(Value::as_u64)
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| DecodeError::Invalid("invalid version".to_string()))?;
+            (version, record.remove("data").unwrap_or_default())
+        }
+        value => (0, value),
+    };
+    if version > TASK_VERSION {
+        return Err(DecodeError::Newer(version));
+    }
+    for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        data = migrate(data).map_err(|err| {
+            DecodeError::Invalid(format!("cant migrate from version {from} {err}"))
+        })?;
+    }
+    match serde_json::from_value(data) {
+        Ok(data) => Ok(DecodedTask {
+            data,
+            upgraded: version < TASK_VERSION,
+        }),
+        Err(err) => Err(DecodeError::Invalid(format!("invalid task {err}"))),
+    }
+}
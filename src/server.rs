@@ -0,0 +1,100 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Map, Value};
+
+use crate::health::{is_ok, Health};
+use crate::metrics::{Metrics, ACTIVE_TASKS, DB_SIZE};
+use crate::store::TaskStore;
+
+#[derive(Clone)]
+struct AppState {
+    metrics: Arc<Metrics>,
+    rzd_db: Arc<dyn TaskStore>,
+    health: Arc<Health>,
+}
+
+/// Serves the operational endpoints next to the bot.
+pub async fn serve(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    rzd_db: Arc<dyn TaskStore>,
+    health: Arc<Health>,
+) {
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(AppState {
+            metrics,
+            rzd_db,
+            health,
+        });
+    tracing::info!(%addr, "http server is listening");
+    if let Err(err) = axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+    {
+        tracing::error!(error = %err, "http server stopped");
+    }
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    match state.rzd_db.size_bytes().await {
+        Ok(size) => state.metrics.set(DB_SIZE, &[], size as f64),
+        Err(err) => tracing::warn!(error = %err, "cant get db size"),
+    }
+    state
+        .metrics
+        .set_all(ACTIVE_TASKS, "type", &state.rzd_db.active_tasks());
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+/// Liveness, fails when the bot is stuck and needs a restart.
+async fn healthz(State(state): State<AppState>) -> impl IntoResponse {
+    report([
+        ("telegram", state.health.telegram_check()),
+        ("poller", state.health.poller_check()),
+    ])
+}
+
+/// Readiness, additionally checks the database and the RZD api.
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let database = match state.rzd_db.check_read_write().await {
+        Ok(()) => json!({ "status": "ok" }),
+        Err(err) => json!({ "status": "fail", "error": err }),
+    };
+    report([
+        ("database", database),
+        ("telegram", state.health.telegram_check()),
+        ("rzd", state.health.rzd_check()),
+        ("poller", state.health.poller_check()),
+    ])
+}
+
+fn report<const N: usize>(checks: [(&str, Value); N]) -> (StatusCode, Json<Value>) {
+    let healthy = checks.iter().all(|(_, check)| is_ok(check));
+    let checks = checks
+        .into_iter()
+        .map(|(name, check)| (name.to_string(), check))
+        .collect::<Map<String, Value>>();
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if healthy { "ok" } else { "fail" },
+        "checks": checks,
+    });
+    (status, Json(body))
+}
@@ -0,0 +1,72 @@
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 20;
+
+/// Tells the background work that the bot is stopping and how long it may take.
+pub struct Shutdown {
+    triggered_at: watch::Sender<Option<Instant>>,
+    timeout: Duration,
+}
+
+impl Shutdown {
+    /// `SHUTDOWN_TIMEOUT_SECS` limits how long in-flight work may take after a signal.
+    pub fn new() -> Arc<Self> {
+        let timeout = env::var("SHUTDOWN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS);
+        Arc::new(Self {
+            triggered_at: watch::channel(None).0,
+            timeout: Duration::from_secs(timeout),
+        })
+    }
+
+    pub fn trigger(&self) {
+        self.triggered_at.send_if_modified(|triggered_at| {
+            if triggered_at.is_some() {
+                return false;
+            }
+            *triggered_at = Some(Instant::now());
+            true
+        });
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.triggered_at.borrow().is_some()
+    }
+
+    /// When in-flight work gets cancelled, counted from the trigger or from now if there was none yet.
+    pub fn deadline(&self) -> Instant {
+        self.triggered_at.borrow().unwrap_or_else(Instant::now) + self.timeout
+    }
+
+    /// Resolves once the shutdown is triggered.
+    pub async fn triggered(&self) {
+        let mut triggered_at = self.triggered_at.subscribe();
+        // The sender lives in `self`, so the channel cant be closed here
+        let _ = triggered_at
+            .wait_for(|triggered_at| triggered_at.is_some())
+            .await;
+    }
+
+    /// Resolves once the time for in-flight work is over.
+    pub async fn expired(&self) {
+        self.triggered().await;
+        tokio::time::sleep_until(self.deadline()).await;
+    }
+}
+
+/// Waits for SIGINT or SIGTERM.
+pub async fn wait_for_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("cant listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => tracing::info!(signal = "SIGINT", "shutting down"),
+        _ = sigterm.recv() => tracing::info!(signal = "SIGTERM", "shutting down"),
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use speedb::{Direction, IteratorMode, WriteBatch, DB};
+
+/// Entries of a scan in key order.
+pub type Entries<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), String>> + 'a>;
+
+/// Ordered key-value store the tasks, snapshots, settings and the station cache of `RZDDb` are kept in.
+/// Keys are compared bytewise.
+pub trait Storage: Send + Sync {
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String>;
+
+    /// Applies all the changes of the batch or none of them.
+    fn write(&self, batch: Batch) -> Result<(), String>;
+
+    /// Entries from the `from` key on.
+    fn scan(&self, from: &[u8]) -> Entries<'_>;
+
+    /// Makes the written data durable.
+    fn flush(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn size_bytes(&self) -> Result<u64, String>;
+}
+
+impl dyn Storage + '_ {
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, String> {
+        self.read(key.as_ref())
+    }
+
+    pub fn put(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<(), String> {
+        let mut batch = Batch::default();
+        batch.put(key, value);
+        self.write(batch)
+    }
+
+    pub fn delete(&self, key: impl AsRef<[u8]>) -> Result<(), String> {
+        let mut batch = Batch::default();
+        batch.delete(key);
+        self.write(batch)
+    }
+}
+
+/// Changes written together, later ones win.
+#[derive(Debug, Default)]
+pub struct Batch {
+    changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl Batch {
+    pub fn put(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        self.changes
+            .push((key.as_ref().to_vec(), Some(value.as_ref().to_vec())));
+    }
+
+    pub fn delete(&mut self, key: impl AsRef<[u8]>) {
+        self.changes.push((key.as_ref().to_vec(), None));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Storage for DB {
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        DB::get(self, key).map_err(|err| err.to_string())
+    }
+
+    fn write(&self, batch: Batch) -> Result<(), String> {
+        let mut write_batch = WriteBatch::default();
+        for (key, value) in batch.changes {
+            match value {
+                Some(value) => write_batch.put(key, value),
+                None => write_batch.delete(key),
+            }
+        }
+        DB::write(self, write_batch).map_err(|err| err.to_string())
+    }
+
+    fn scan(&self, from: &[u8]) -> Entries<'_> {
+        Box::new(
+            self.iterator(IteratorMode::From(from, Direction::Forward))
+                .map(|r| {
+                    r.map(|(key, value)| (key.to_vec(), value.to_vec()))
+                        .map_err(|err| err.to_string())
+                }),
+        )
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        self.flush_wal(true).map_err(|err| err.to_string())?;
+        DB::flush(self).map_err(|err| err.to_string())
+    }
+
+    /// Approximate, sst files plus memtables.
+    fn size_bytes(&self) -> Result<u64, String> {
+        let mut size = 0;
+        for property in [
+            "rocksdb.total-sst-files-size",
+            "rocksdb.cur-size-all-mem-tables",
+        ] {
+            match self.property_int_value(property) {
+                Ok(value) => size += value.unwrap_or_default(),
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+        Ok(size)
+    }
+}
+
+/// Keeps everything in memory and loses it on exit, for tests and trying the bot out.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    data: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        Ok(self.data.read().unwrap().get(key).cloned())
+    }
+
+    fn write(&self, batch: Batch) -> Result<(), String> {
+        let mut data = self.data.write().unwrap();
+        for (key, value) in batch.changes {
+            match value {
+                Some(value) => data.insert(key, value),
+                None => data.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    /// Works on a copy, writes made during the scan are not seen.
+    fn scan(&self, from: &[u8]) -> Entries<'_> {
+        let entries: Vec<_> = self
+            .data
+            .read()
+            .unwrap()
+            .range(from.to_vec()..)
+            .map(|(key, value)| Ok((key.clone(), value.clone())))
+            .collect();
+        Box::new(entries.into_iter())
+    }
+
+    fn size_bytes(&self) -> Result<u64, String> {
+        Ok(self
+            .data
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, value)| (key.len() + value.len()) as u64)
+            .sum())
+    }
+}
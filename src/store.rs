@@ -0,0 +1,340 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::access::AccessStatus;
+use crate::db::{
+    PendingNotification, RZDDb, RecentRoute, SentNotification, TaskList, TaskPages, TaskScan,
+};
+use crate::i18n::Lang;
+use crate::limits::{LimitOverrides, Limits};
+use crate::rzd::GetRZDPointCodes;
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+/// What the handlers, the poller and the sweeper keep about tasks, their owners and the stations.
+/// `RZDDb` over speedb or the in-memory map is the implementation. Everything past startup gets it as
+/// `Arc<dyn TaskStore>`, so a handler can be run against a test double. Maintenance of the storage itself
+/// (flushes, migrations, index rebuilds) stays on `RZDDb` and is only done by `main` and the `db` subcommands.
+pub trait TaskStore: Send + Sync {
+    fn create_task(&self, data: HashMap<String, String>) -> StoreFuture<'_, String>;
+    fn delete_task_by_id(&self, task_id: String) -> StoreFuture<'_, String>;
+    fn expire_task<'a>(&'a self, task_id: &'a str, archive: bool) -> StoreFuture<'a, ()>;
+    fn get_task<'a>(&'a self, task_id: &'a str)
+        -> StoreFuture<'a, Option<HashMap<String, String>>>;
+    fn put_task<'a>(
+        &'a self,
+        task_id: &'a str,
+        data: &'a HashMap<String, String>,
+    ) -> StoreFuture<'a, ()>;
+    fn update_task<'a>(
+        &'a self,
+        task_id: &'a str,
+        data: HashMap<String, String>,
+    ) -> StoreFuture<'a, ()>;
+    /// Tasks in id order after the `after` key, `TaskScan::next` tells where the next page starts.
+    fn task_page(&self, after: Option<String>) -> StoreFuture<'_, TaskScan>;
+    fn list_chat_tasks(&self, chat_id: i64) -> StoreFuture<'_, TaskList>;
+    fn list_due_tasks(&self, now: u64) -> StoreFuture<'_, TaskList>;
+    fn schedule_poll<'a>(&'a self, task_id: &'a str, at: u64) -> StoreFuture<'a, ()>;
+    fn count_user_tasks(&self, user_id: u64) -> StoreFuture<'_, usize>;
+    fn list_user_tasks(&self, user_id: u64) -> StoreFuture<'_, TaskList>;
+
+    fn was_notified<'a>(&'a self, task_id: &'a str) -> StoreFuture<'a, bool>;
+    fn get_snapshot<'a>(&'a self, task_id: &'a str) -> StoreFuture<'a, Option<Vec<String>>>;
+    fn put_snapshot<'a>(&'a self, task_id: &'a str, snapshot: &'a [String]) -> StoreFuture<'a, ()>;
+    fn queue_notification<'a>(
+        &'a self,
+        task_id: &'a str,
+        snapshot: &'a [String],
+        notification: &'a PendingNotification,
+    ) -> StoreFuture<'a, ()>;
+    fn list_pending_notifications(&self) -> StoreFuture<'_, Vec<(String, PendingNotification)>>;
+    fn put_pending_notification<'a>(
+        &'a self,
+        task_id: &'a str,
+        notification: &'a PendingNotification,
+    ) -> StoreFuture<'a, ()>;
+    fn delete_pending_notification<'a>(&'a self, task_id: &'a str) -> StoreFuture<'a, ()>;
+    fn record_notification<'a>(
+        &'a self,
+        task_id: &'a str,
+        notification: &'a SentNotification,
+    ) -> StoreFuture<'a, ()>;
+    fn list_notification_history<'a>(
+        &'a self,
+        task_id: &'a str,
+    ) -> StoreFuture<'a, Vec<SentNotification>>;
+
+    fn user_limits(&self, user_id: u64) -> StoreFuture<'_, Limits>;
+    fn get_limit_overrides(&self, user_id: u64) -> StoreFuture<'_, LimitOverrides>;
+    fn set_limit_overrides<'a>(
+        &'a self,
+        user_id: u64,
+        overrides: &'a LimitOverrides,
+    ) -> StoreFuture<'a, ()>;
+    fn register_search(&self, user_id: u64) -> StoreFuture<'_, Option<i64>>;
+    fn list_user_ids(&self) -> StoreFuture<'_, BTreeSet<u64>>;
+    fn get_user_lang(&self, user_id: u64) -> StoreFuture<'_, Option<Lang>>;
+    fn set_user_lang(&self, user_id: u64, lang: Lang) -> StoreFuture<'_, ()>;
+    fn get_task_ttl(&self, user_id: u64) -> StoreFuture<'_, Option<u32>>;
+    fn set_task_ttl(&self, user_id: u64, days: Option<u32>) -> StoreFuture<'_, ()>;
+    fn get_access(&self, user_id: u64) -> StoreFuture<'_, Option<AccessStatus>>;
+    fn set_access(&self, user_id: u64, status: AccessStatus) -> StoreFuture<'_, ()>;
+    fn reset_access(&self, user_id: u64) -> StoreFuture<'_, ()>;
+    fn create_invite(&self, created_by: u64) -> StoreFuture<'_, String>;
+    fn redeem_invite<'a>(&'a self, code: &'a str) -> StoreFuture<'a, bool>;
+
+    fn remember_stations<'a>(&'a self, stations: &'a [GetRZDPointCodes]) -> StoreFuture<'a, ()>;
+    fn get_station<'a>(&'a self, code: &'a str) -> StoreFuture<'a, Option<GetRZDPointCodes>>;
+    fn list_favourite_stations(&self, user_id: u64) -> StoreFuture<'_, Vec<GetRZDPointCodes>>;
+    fn toggle_favourite_station<'a>(&'a self, user_id: u64, code: &'a str)
+        -> StoreFuture<'a, bool>;
+    fn list_recent_routes(&self, user_id: u64) -> StoreFuture<'_, Vec<RecentRoute>>;
+    fn push_recent_route<'a>(
+        &'a self,
+        user_id: u64,
+        from_point_code: &'a str,
+        to_point_code: &'a str,
+    ) -> StoreFuture<'a, ()>;
+
+    /// Health and size of the storage for the operational endpoints.
+    fn check_read_write(&self) -> StoreFuture<'_, ()>;
+    fn size_bytes(&self) -> StoreFuture<'_, u64>;
+    fn active_tasks(&self) -> BTreeMap<String, f64>;
+}
+
+impl dyn TaskStore + '_ {
+    /// All tasks in id order, a page at a time.
+    pub fn task_pages(&self) -> TaskPages<'_> {
+        TaskPages::new(self)
+    }
+}
+
+impl TaskStore for RZDDb {
+    fn create_task(&self, data: HashMap<String, String>) -> StoreFuture<'_, String> {
+        Box::pin(RZDDb::create_task(self, data))
+    }
+
+    fn delete_task_by_id(&self, task_id: String) -> StoreFuture<'_, String> {
+        Box::pin(RZDDb::delete_task_by_id(self, task_id))
+    }
+
+    fn expire_task<'a>(&'a self, task_id: &'a str, archive: bool) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::expire_task(self, task_id, archive))
+    }
+
+    fn get_task<'a>(
+        &'a self,
+        task_id: &'a str,
+    ) -> StoreFuture<'a, Option<HashMap<String, String>>> {
+        Box::pin(RZDDb::get_task(self, task_id))
+    }
+
+    fn put_task<'a>(
+        &'a self,
+        task_id: &'a str,
+        data: &'a HashMap<String, String>,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::put_task(self, task_id, data))
+    }
+
+    fn update_task<'a>(
+        &'a self,
+        task_id: &'a str,
+        data: HashMap<String, String>,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::update_task(self, task_id, data))
+    }
+
+    fn task_page(&self, after: Option<String>) -> StoreFuture<'_, TaskScan> {
+        Box::pin(RZDDb::task_page(self, after))
+    }
+
+    fn list_chat_tasks(&self, chat_id: i64) -> StoreFuture<'_, TaskList> {
+        Box::pin(RZDDb::list_chat_tasks(self, chat_id))
+    }
+
+    fn list_due_tasks(&self, now: u64) -> StoreFuture<'_, TaskList> {
+        Box::pin(RZDDb::list_due_tasks(self, now))
+    }
+
+    fn schedule_poll<'a>(&'a self, task_id: &'a str, at: u64) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::schedule_poll(self, task_id, at))
+    }
+
+    fn count_user_tasks(&self, user_id: u64) -> StoreFuture<'_, usize> {
+        Box::pin(RZDDb::count_user_tasks(self, user_id))
+    }
+
+    fn list_user_tasks(&self, user_id: u64) -> StoreFuture<'_, TaskList> {
+        Box::pin(RZDDb::list_user_tasks(self, user_id))
+    }
+
+    fn was_notified<'a>(&'a self, task_id: &'a str) -> StoreFuture<'a, bool> {
+        Box::pin(RZDDb::was_notified(self, task_id))
+    }
+
+    fn get_snapshot<'a>(&'a self, task_id: &'a str) -> StoreFuture<'a, Option<Vec<String>>> {
+        Box::pin(RZDDb::get_snapshot(self, task_id))
+    }
+
+    fn put_snapshot<'a>(&'a self, task_id: &'a str, snapshot: &'a [String]) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::put_snapshot(self, task_id, snapshot))
+    }
+
+    fn queue_notification<'a>(
+        &'a self,
+        task_id: &'a str,
+        snapshot: &'a [String],
+        notification: &'a PendingNotification,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::queue_notification(
+            self,
+            task_id,
+            snapshot,
+            notification,
+        ))
+    }
+
+    fn list_pending_notifications(&self) -> StoreFuture<'_, Vec<(String, PendingNotification)>> {
+        Box::pin(RZDDb::list_pending_notifications(self))
+    }
+
+    fn put_pending_notification<'a>(
+        &'a self,
+        task_id: &'a str,
+        notification: &'a PendingNotification,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::put_pending_notification(self, task_id, notification))
+    }
+
+    fn delete_pending_notification<'a>(&'a self, task_id: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::delete_pending_notification(self, task_id))
+    }
+
+    fn record_notification<'a>(
+        &'a self,
+        task_id: &'a str,
+        notification: &'a SentNotification,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::record_notification(self, task_id, notification))
+    }
+
+    fn list_notification_history<'a>(
+        &'a self,
+        task_id: &'a str,
+    ) -> StoreFuture<'a, Vec<SentNotification>> {
+        Box::pin(RZDDb::list_notification_history(self, task_id))
+    }
+
+    fn user_limits(&self, user_id: u64) -> StoreFuture<'_, Limits> {
+        Box::pin(RZDDb::user_limits(self, user_id))
+    }
+
+    fn get_limit_overrides(&self, user_id: u64) -> StoreFuture<'_, LimitOverrides> {
+        Box::pin(RZDDb::get_limit_overrides(self, user_id))
+    }
+
+    fn set_limit_overrides<'a>(
+        &'a self,
+        user_id: u64,
+        overrides: &'a LimitOverrides,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::set_limit_overrides(self, user_id, overrides))
+    }
+
+    fn register_search(&self, user_id: u64) -> StoreFuture<'_, Option<i64>> {
+        Box::pin(RZDDb::register_search(self, user_id))
+    }
+
+    fn list_user_ids(&self) -> StoreFuture<'_, BTreeSet<u64>> {
+        Box::pin(RZDDb::list_user_ids(self))
+    }
+
+    fn get_user_lang(&self, user_id: u64) -> StoreFuture<'_, Option<Lang>> {
+        Box::pin(RZDDb::get_user_lang(self, user_id))
+    }
+
+    fn set_user_lang(&self, user_id: u64, lang: Lang) -> StoreFuture<'_, ()> {
+        Box::pin(RZDDb::set_user_lang(self, user_id, lang))
+    }
+
+    fn get_task_ttl(&self, user_id: u64) -> StoreFuture<'_, Option<u32>> {
+        Box::pin(RZDDb::get_task_ttl(self, user_id))
+    }
+
+    fn set_task_ttl(&self, user_id: u64, days: Option<u32>) -> StoreFuture<'_, ()> {
+        Box::pin(RZDDb::set_task_ttl(self, user_id, days))
+    }
+
+    fn get_access(&self, user_id: u64) -> StoreFuture<'_, Option<AccessStatus>> {
+        Box::pin(RZDDb::get_access(self, user_id))
+    }
+
+    fn set_access(&self, user_id: u64, status: AccessStatus) -> StoreFuture<'_, ()> {
+        Box::pin(RZDDb::set_access(self, user_id, status))
+    }
+
+    fn reset_access(&self, user_id: u64) -> StoreFuture<'_, ()> {
+        Box::pin(RZDDb::reset_access(self, user_id))
+    }
+
+    fn create_invite(&self, created_by: u64) -> StoreFuture<'_, String> {
+        Box::pin(RZDDb::create_invite(self, created_by))
+    }
+
+    fn redeem_invite<'a>(&'a self, code: &'a str) -> StoreFuture<'a, bool> {
+        Box::pin(RZDDb::redeem_invite(self, code))
+    }
+
+    fn remember_stations<'a>(&'a self, stations: &'a [GetRZDPointCodes]) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::remember_stations(self, stations))
+    }
+
+    fn get_station<'a>(&'a self, code: &'a str) -> StoreFuture<'a, Option<GetRZDPointCodes>> {
+        Box::pin(RZDDb::get_station(self, code))
+    }
+
+    fn list_favourite_stations(&self, user_id: u64) -> StoreFuture<'_, Vec<GetRZDPointCodes>> {
+        Box::pin(RZDDb::list_favourite_stations(self, user_id))
+    }
+
+    fn toggle_favourite_station<'a>(
+        &'a self,
+        user_id: u64,
+        code: &'a str,
+    ) -> StoreFuture<'a, bool> {
+        Box::pin(RZDDb::toggle_favourite_station(self, user_id, code))
+    }
+
+    fn list_recent_routes(&self, user_id: u64) -> StoreFuture<'_, Vec<RecentRoute>> {
+        Box::pin(RZDDb::list_recent_routes(self, user_id))
+    }
+
+    fn push_recent_route<'a>(
+        &'a self,
+        user_id: u64,
+        from_point_code: &'a str,
+        to_point_code: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(RZDDb::push_recent_route(
+            self,
+            user_id,
+            from_point_code,
+            to_point_code,
+        ))
+    }
+
+    fn check_read_write(&self) -> StoreFuture<'_, ()> {
+        Box::pin(RZDDb::check_read_write(self))
+    }
+
+    fn size_bytes(&self) -> StoreFuture<'_, u64> {
+        Box::pin(RZDDb::size_bytes(self))
+    }
+
+    fn active_tasks(&self) -> BTreeMap<String, f64> {
+        RZDDb::active_tasks(self)
+    }
+}
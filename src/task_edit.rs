@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+
+use crate::i18n::{Key, Lang};
+use crate::quick_search::{parse_car_type, parse_seat_filter};
+use crate::utils::parse_short_date;
+
+const TASK_ACTION_PREFIX: &str = "task_";
+pub const STATUS_ACTIVE: &str = "active";
+pub const STATUS_PAUSED: &str = "paused";
+
+/// Paused tasks are kept but not polled, tasks without a status are active.
+pub fn is_paused(task: &HashMap<String, String>) -> bool {
+    task.get("status")
+        .is_some_and(|status| status == STATUS_PAUSED)
+}
+
+pub fn set_paused(task: &mut HashMap<String, String>, paused: bool) {
+    let status = if paused { STATUS_PAUSED } else { STATUS_ACTIVE };
+    task.insert("status".to_string(), status.to_string());
+}
+
+/// What the owner is asked to send a new value for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskEdit {
+    Date,
+    CarTypes,
+    Seats,
+    MaxPrice,
+    /// A copy of the task for another date, the task itself is left as is.
+    Clone,
+}
+
+impl TaskEdit {
+    pub fn prompt(&self) -> Key {
+        match self {
+            TaskEdit::Date | TaskEdit::Clone => Key::EnterDate,
+            TaskEdit::CarTypes => Key::EnterCarTypes,
+            TaskEdit::Seats => Key::EnterSeats,
+            TaskEdit::MaxPrice => Key::EnterMaxPrice,
+        }
+    }
+}
+
+/// Button of the task menu opened from the task list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskAction {
+    Menu,
+    Pause,
+    Resume,
+    Edit(TaskEdit),
+}
+
+impl TaskAction {
+    fn name(&self) -> &'static str {
+        match self {
+            TaskAction::Menu => "menu",
+            TaskAction::Pause => "pause",
+            TaskAction::Resume => "resume",
+            TaskAction::Edit(TaskEdit::Date) => "date",
+            TaskAction::Edit(TaskEdit::CarTypes) => "cars",
+            TaskAction::Edit(TaskEdit::Seats) => "seats",
+            TaskAction::Edit(TaskEdit::MaxPrice) => "price",
+            TaskAction::Edit(TaskEdit::Clone) => "clone",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "menu" => Some(TaskAction::Menu),
+            "pause" => Some(TaskAction::Pause),
+            "resume" => Some(TaskAction::Resume),
+            "date" => Some(TaskAction::Edit(TaskEdit::Date)),
+            "cars" => Some(TaskAction::Edit(TaskEdit::CarTypes)),
+            "seats" => Some(TaskAction::Edit(TaskEdit::Seats)),
+            "price" => Some(TaskAction::Edit(TaskEdit::MaxPrice)),
+            "clone" => Some(TaskAction::Edit(TaskEdit::Clone)),
+            _ => None,
+        }
+    }
+
+    pub fn callback(&self, task_id: &str) -> String {
+        format!("{TASK_ACTION_PREFIX}{}_{task_id}", self.name())
+    }
+}
+
+pub fn parse_task_action(data: &str) -> Option<(TaskAction, &str)> {
+    let (action, task_id) = data.strip_prefix(TASK_ACTION_PREFIX)?.split_once('_')?;
+    Some((TaskAction::parse(action)?, task_id))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskEditError {
+    UnknownDate(String),
+    DateInPast(NaiveDate),
+    UnknownFilter(String),
+    InvalidPrice(String),
+}
+
+impl TaskEditError {
+    pub fn to_text(&self, lang: Lang) -> String {
+        match self {
+            TaskEditError::UnknownDate(text) => lang.tf(Key::UnknownDate, &[("text", text)]),
+            TaskEditError::DateInPast(date) => {
+                lang.tf(Key::DateInPast, &[("date", &date.format("%d.%m.%Y"))])
+            }
+            TaskEditError::UnknownFilter(text) => lang.tf(Key::UnknownFilter, &[("text", text)]),
+            TaskEditError::InvalidPrice(text) => lang.tf(Key::InvalidPrice, &[("text", text)]),
+        }
+    }
+}
+
+/// The task with the owner's answer applied. For `Clone` it is the new task, which starts active.
+pub fn apply_edit(
+    task: &HashMap<String, String>,
+    edit: TaskEdit,
+    text: &str,
+    today: NaiveDate,
+) -> Result<HashMap<String, String>, TaskEditError> {
+    let text = text.trim().to_lowercase();
+    let mut task = task.clone();
+    match edit {
+        TaskEdit::Date | TaskEdit::Clone => {
+            let date = parse_short_date(&text, today)
+                .ok_or_else(|| TaskEditError::UnknownDate(text.clone()))?;
+            if date < today {
+                return Err(TaskEditError::DateInPast(date));
+            }
+            task.insert("date".to_string(), date.format("%d.%m.%Y").to_string());
+            // Both follow from the old date
+            task.remove("expires_at");
+            task.remove("next_poll_at");
+            if edit == TaskEdit::Clone {
+                task.remove("status");
+            }
+        }
+        TaskEdit::CarTypes => {
+            let mut car_types: Vec<&str> = Vec::new();
+            for word in text
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|word| !word.is_empty())
+            {
+                let car_type = parse_car_type(word)
+                    .ok_or_else(|| TaskEditError::UnknownFilter(word.to_string()))?;
+                if !car_types.contains(&car_type) {
+                    car_types.push(car_type);
+                }
+            }
+            if car_types.is_empty() {
+                return Err(TaskEditError::UnknownFilter(text));
+            }
+            task.insert("car_types".to_string(), car_types.join(","));
+        }
+        TaskEdit::Seats => match text.as_str() {
+            "любые" | "any" => {
+                task.remove("seats");
+            }
+            word => {
+                let seats = parse_seat_filter(word)
+                    .ok_or_else(|| TaskEditError::UnknownFilter(text.clone()))?;
+                task.insert("seats".to_string(), seats.as_str().to_string());
+            }
+        },
+        TaskEdit::MaxPrice => match text.parse::<u32>() {
+            Ok(0) => {
+                task.remove("max_price");
+            }
+            Ok(price) => {
+                task.insert("max_price".to_string(), price.to_string());
+            }
+            Err(_) => return Err(TaskEditError::InvalidPrice(text)),
+        },
+    }
+    Ok(task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 16).unwrap()
+    }
+
+    fn task() -> HashMap<String, String> {
+        [
+            ("type", "day"),
+            ("date", "21.10.2026"),
+            ("car_types", "купе"),
+            ("expires_at", "2026-10-21T23:59:59+03:00"),
+            ("next_poll_at", "1792500000"),
+            ("status", "paused"),
+        ]
+        .iter()
+        .map(|(field, value)| (field.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn round_trips_task_actions() {
+        let task_id = "5f0c6e1a-3b7e-4a53-9a8e-0f1f2d3c4b5a";
+        for action in [
+            TaskAction::Menu,
+            TaskAction::Pause,
+            TaskAction::Resume,
+            TaskAction::Edit(TaskEdit::Date),
+            TaskAction::Edit(TaskEdit::CarTypes),
+            TaskAction::Edit(TaskEdit::Seats),
+            TaskAction::Edit(TaskEdit::MaxPrice),
+            TaskAction::Edit(TaskEdit::Clone),
+        ] {
+            let callback = action.callback(task_id);
+            assert!(callback.len() <= 64, "{callback} is too long for a button");
+            assert_eq!(parse_task_action(&callback), Some((action, task_id)));
+        }
+        assert_eq!(parse_task_action("history_x"), None);
+        assert_eq!(parse_task_action("task_unknown_x"), None);
+    }
+
+    #[test]
+    fn moves_the_date_and_forgets_the_old_schedule() {
+        let edited = apply_edit(&task(), TaskEdit::Date, "25.10", today()).unwrap();
+        assert_eq!(edited.get("date").unwrap(), "25.10.2026");
+        assert!(!edited.contains_key("expires_at"));
+        assert!(!edited.contains_key("next_poll_at"));
+        assert!(is_paused(&edited));
+
+        let cloned = apply_edit(&task(), TaskEdit::Clone, "25.10.2026", today()).unwrap();
+        assert_eq!(cloned.get("date").unwrap(), "25.10.2026");
+        assert!(!is_paused(&cloned));
+
+        assert_eq!(
+            apply_edit(&task(), TaskEdit::Date, "01.10.2026", today()),
+            Err(TaskEditError::DateInPast(
+                NaiveDate::from_ymd_opt(2026, 10, 1).unwrap()
+            ))
+        );
+        assert_eq!(
+            apply_edit(&task(), TaskEdit::Date, "скоро", today()),
+            Err(TaskEditError::UnknownDate("скоро".to_string()))
+        );
+    }
+
+    #[test]
+    fn edits_filters() {
+        let edited =
+            apply_edit(&task(), TaskEdit::CarTypes, "Плацкарт, купе плац", today()).unwrap();
+        assert_eq!(edited.get("car_types").unwrap(), "плацкартный,купе");
+        assert_eq!(
+            apply_edit(&task(), TaskEdit::CarTypes, "купе вагон", today()),
+            Err(TaskEditError::UnknownFilter("вагон".to_string()))
+        );
+
+        let edited = apply_edit(&task(), TaskEdit::Seats, "нижние", today()).unwrap();
+        assert_eq!(edited.get("seats").unwrap(), "lower");
+        let edited = apply_edit(&edited, TaskEdit::Seats, "любые", today()).unwrap();
+        assert!(!edited.contains_key("seats"));
+
+        let edited = apply_edit(&task(), TaskEdit::MaxPrice, "3500", today()).unwrap();
+        assert_eq!(edited.get("max_price").unwrap(), "3500");
+        let edited = apply_edit(&edited, TaskEdit::MaxPrice, "0", today()).unwrap();
+        assert!(!edited.contains_key("max_price"));
+        assert_eq!(
+            apply_edit(&task(), TaskEdit::MaxPrice, "дёшево", today()),
+            Err(TaskEditError::InvalidPrice("дёшево".to_string()))
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use std::env;
+use std::sync::Arc;
+
+use dptree::di::DependencySupplier;
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::dispatching::UpdateHandler;
+use teloxide::prelude::*;
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+
+/// Sets up the subscriber, `LOG_FORMAT=json` switches from the human-readable output to JSON lines.
+///
+/// Levels are configured with `RUST_LOG`, `info` by default. Records of the `log` crate end up in the same output
+/// together with the fields of the spans they were emitted in. Everything goes to stderr, stdout is left to the
+/// `db` subcommands.
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match env::var("LOG_FORMAT").unwrap_or_default().as_str() {
+        "json" => builder.json().flatten_event(true).init(),
+        "" | "text" => builder.init(),
+        format => {
+            builder.init();
+            tracing::warn!(format, "unknown LOG_FORMAT, using text");
+        }
+    }
+}
+
+/// Runs the rest of the schema inside an `update` span, `state` is recorded once the dialogue is loaded.
+pub fn update_span() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    dptree::from_fn(|deps: DependencyMap, cont| async move {
+        let update: Arc<Update> = deps.get();
+        let span = tracing::info_span!(
+            "update",
+            update_id = update.id,
+            chat_id = update.chat_id().map(|chat_id| chat_id.0),
+            user_id = update.user().map(|user| user.id.0),
+            state = tracing::field::Empty,
+        );
+        cont(deps).instrument(span).await
+    })
+}
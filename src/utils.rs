@@ -1,25 +1,467 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Datelike, NaiveDate};
 use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
 
-pub fn make_start_keyboard() -> InlineKeyboardMarkup {
+use crate::db::{RecentRoute, SentNotification};
+use crate::i18n::{Key, Lang};
+use crate::pagination::Paginated;
+use crate::rzd::{GetRZDPointCodes, GetRZDTrainsCarriagesResponse, GetRZDTrainsResponse};
+use crate::task_edit::{is_paused, TaskAction, TaskEdit};
+use crate::{Train, CUPE_TYPE};
+
+const TELEGRAM_MESSAGE_LIMIT: usize = 4096;
+const HISTORY_PREFIX: &str = "history_";
+
+pub fn make_start_keyboard(lang: Lang) -> InlineKeyboardMarkup {
     InlineKeyboardMarkup::default().append_row([InlineKeyboardButton::callback(
-        "РЖД",
+        lang.t(Key::RZDButton),
             "rzd"
     )])
 }
 
-pub fn make_rzd_start_keyboard() -> InlineKeyboardMarkup {
+pub fn make_rzd_start_keyboard(lang: Lang) -> InlineKeyboardMarkup {
     InlineKeyboardMarkup::default().append_row([InlineKeyboardButton::callback(
-        "Поиск купе",
+        lang.t(Key::SearchCupeButton),
         "rzd_search"
     )]).append_row(
         [InlineKeyboardButton::callback(
-            "Задачи",
+            lang.t(Key::TasksButton),
             "rzd_tasks"
         )]
     ).append_row(
         [InlineKeyboardButton::callback(
-            "Назад",
+            lang.t(Key::BackButton),
             "rzd_return"
         )]
     )
-}
\ No newline at end of file
+}
+
+pub fn make_stations_keyboard(codes: &[GetRZDPointCodes], page: usize) -> InlineKeyboardMarkup {
+    let paginated = Paginated::new(codes, page);
+    let mut reply_markup = InlineKeyboardMarkup::default();
+    for (_, code) in paginated.items() {
+        reply_markup = reply_markup.append_row([
+            InlineKeyboardButton::callback(code.name.clone(), code.code.clone()),
+            InlineKeyboardButton::callback("⭐", format!("fav_{}", code.code)),
+        ]);
+    }
+    paginated.keyboard(reply_markup)
+}
+
+pub fn make_quick_pick_keyboard(
+    favourites: &[GetRZDPointCodes],
+    recent_routes: &[RecentRoute],
+) -> InlineKeyboardMarkup {
+    let mut reply_markup = InlineKeyboardMarkup::default();
+    for station in favourites.iter() {
+        reply_markup = reply_markup.append_row([InlineKeyboardButton::callback(
+            format!("⭐ {}", station.name),
+            format!("station_{}", station.code),
+        )]);
+    }
+    for route in recent_routes.iter() {
+        reply_markup = reply_markup.append_row([InlineKeyboardButton::callback(
+            format!("🔁 {} → {}", route.from.name, route.to.name),
+            format!("route_{}_{}", route.from.code, route.to.code),
+        )]);
+    }
+    reply_markup
+}
+
+/// Parses `день.месяц.год` or `день.месяц`, the latter is resolved to the nearest upcoming date.
+pub fn parse_short_date(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%d.%m.%Y") {
+        return Some(date);
+    }
+    let date = NaiveDate::parse_from_str(&format!("{text}.{}", today.year()), "%d.%m.%Y").ok()?;
+    if date < today {
+        return date.with_year(today.year() + 1);
+    }
+    Some(date)
+}
+
+pub fn format_trains_summary(trains: &GetRZDTrainsResponse, lang: Lang) -> String {
+    let mut message_text = String::new();
+    for train in trains.tp.iter().flat_map(|tp| tp.list.iter()) {
+        let mut free_seats: BTreeMap<String, usize> = BTreeMap::new();
+        for car in train.cars.iter() {
+            *free_seats.entry(car._type.to_lowercase()).or_default() += car.free_seats;
+        }
+        message_text.push_str(&lang.tf(
+            Key::TrainSummary,
+            &[
+                ("number", &train.number),
+                ("date", &train.date0),
+                ("time", &train.time0),
+            ],
+        ));
+        for (car_type, count) in free_seats.iter() {
+            message_text.push_str(&format!("{car_type}: {count}\n"));
+        }
+        message_text.push('\n');
+    }
+    message_text
+}
+
+/// Cuts the text on a line boundary so it fits into a single telegram message.
+pub fn truncate_message(text: &str) -> String {
+    if text.chars().count() <= TELEGRAM_MESSAGE_LIMIT {
+        return text.to_string();
+    }
+    let mut result = String::new();
+    for line in text.lines() {
+        if result.chars().count() + line.chars().count() + 2 > TELEGRAM_MESSAGE_LIMIT {
+            result.push('…');
+            break;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+pub fn format_trains_page(trains: &[Train], page: usize, lang: Lang) -> String {
+    let mut message_text = String::new();
+    for (idx, train) in Paginated::new(trains, page).items() {
+        message_text.push_str(&lang.tf(
+            Key::TrainsPageEntry,
+            &[
+                ("idx", &(idx + 1)),
+                ("number", &train.tnum0),
+                ("date", &train.dt0),
+                ("time", &train.time0),
+                ("seats", &train.cupe_free_seats),
+            ],
+        ));
+    }
+    message_text
+}
+
+pub fn make_trains_keyboard(
+    trains: &[Train],
+    date: &str,
+    page: usize,
+    lang: Lang,
+) -> InlineKeyboardMarkup {
+    let mut reply_markup = InlineKeyboardMarkup::default();
+    if let Some(train) = trains.first() {
+        reply_markup = reply_markup.append_row([InlineKeyboardButton::callback(
+            lang.t(Key::WatchDayButton),
+            format!("{}_{}_{date}", train.code0, train.code1),
+        )]);
+    }
+    Paginated::new(trains, page).keyboard(reply_markup)
+}
+
+pub fn format_carriages_page(places: &[String], page: usize) -> String {
+    let mut message_text = String::new();
+    for (_, place) in Paginated::new(places, page).items() {
+        message_text.push_str(place);
+    }
+    message_text
+}
+
+/// Whole free compartments as (carriage number, first place of the compartment).
+pub fn find_free_compartments(carriages: &GetRZDTrainsCarriagesResponse) -> Vec<(String, isize)> {
+    let mut compartments = Vec::new();
+    for car in carriages.lst.iter().take(1).flat_map(|lst| lst.cars.iter()) {
+        if car._type.to_lowercase() != CUPE_TYPE {
+            continue;
+        }
+        for place in car.places.iter() {
+            let places_ref = place.split('-').collect::<Vec<&str>>();
+            if places_ref.len() != 2 {
+                continue;
+            }
+            let (start_place, end_place) =
+                match (parse_place(places_ref[0]), parse_place(places_ref[1])) {
+                    (Some(start_place), Some(end_place)) => (start_place, end_place),
+                    _ => {
+                        tracing::warn!(place, carriage = car.cnumber, "cant parse places");
+                        continue;
+                    }
+                };
+            if start_place > end_place {
+                tracing::warn!(
+                    start_place,
+                    end_place,
+                    carriage = car.cnumber,
+                    "start_place is greater than end_place"
+                );
+                continue;
+            }
+            for place_n in start_place..=end_place {
+                //
+                if place_n % 4 == 1 && end_place - place_n >= 3 {
+                    // Blyat ya ne vspomnu cherez god logiku
+                    compartments.push((car.cnumber.clone(), place_n))
+                }
+            }
+        }
+    }
+    compartments
+}
+
+/// Place number without its letter suffix, e.g. `12М`.
+fn parse_place(place: &str) -> Option<isize> {
+    place
+        .trim_end_matches(|c: char| c.is_alphabetic())
+        .parse()
+        .ok()
+}
+
+pub fn make_carriages_keyboard(
+    train: &Train,
+    places: &[String],
+    page: usize,
+    lang: Lang,
+) -> InlineKeyboardMarkup {
+    let reply_markup = InlineKeyboardMarkup::default()
+        .append_row([InlineKeyboardButton::callback(
+            lang.t(Key::WatchTrainButton),
+            format!(
+                "{}_{}_{}_{}_{}",
+                train.code0, train.code1, train.dt0, train.time0, train.tnum0
+            ),
+        )])
+        .append_row([InlineKeyboardButton::callback(
+            lang.t(Key::DontWatchTrainButton),
+            "cancel",
+        )]);
+    Paginated::new(places, page).keyboard(reply_markup)
+}
+
+pub fn format_task(task_id: &str, task: &HashMap<String, String>, lang: Lang) -> String {
+    let unknown = "UNKNOWN".to_string();
+    let field = |name: &str| task.get(name).unwrap_or(&unknown);
+    let text = match field("type").as_str() {
+        "day" => lang.tf(
+            Key::DayTask,
+            &[
+                ("task_id", &task_id),
+                ("from", field("from_point_code")),
+                ("to", field("to_point_code")),
+                ("date", field("date")),
+            ],
+        ),
+        // Переделать под получение самого населенного пункта
+        "train" => lang.tf(
+            Key::TrainTask,
+            &[
+                ("task_id", &task_id),
+                ("from", field("from_point_code")),
+                ("to", field("to_point_code")),
+                ("date", field("date")),
+                ("time", field("time")),
+                ("tnum", field("tnum")),
+            ],
+        ),
+        _ => lang.tf(Key::UnknownTask, &[("task_id", &task_id)]),
+    };
+    if is_paused(task) {
+        format!("{text}\n{}", lang.t(Key::TaskPausedMark))
+    } else {
+        text
+    }
+}
+
+pub fn format_tasks_page(
+    tasks: &[(String, HashMap<String, String>)],
+    page: usize,
+    lang: Lang,
+) -> String {
+    Paginated::new(tasks, page)
+        .items()
+        .map(|(idx, (task_id, task))| format!("{}. {}", idx + 1, format_task(task_id, task, lang)))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+pub fn make_tasks_keyboard(
+    tasks: &[(String, HashMap<String, String>)],
+    page: usize,
+    lang: Lang,
+) -> InlineKeyboardMarkup {
+    let paginated = Paginated::new(tasks, page);
+    let mut reply_markup = InlineKeyboardMarkup::default();
+    for (idx, (task_id, _)) in paginated.items() {
+        reply_markup = reply_markup.append_row([
+            InlineKeyboardButton::callback(
+                lang.tf(Key::DeleteTaskButton, &[("n", &(idx + 1))]),
+                task_id.clone(),
+            ),
+            InlineKeyboardButton::callback(
+                lang.tf(Key::HistoryButton, &[("n", &(idx + 1))]),
+                format!("{HISTORY_PREFIX}{task_id}"),
+            ),
+            InlineKeyboardButton::callback(
+                lang.tf(Key::EditTaskButton, &[("n", &(idx + 1))]),
+                TaskAction::Menu.callback(task_id),
+            ),
+        ]);
+    }
+    paginated.keyboard(reply_markup)
+}
+
+pub fn make_import_keyboard(lang: Lang) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default().append_row([
+        InlineKeyboardButton::callback(lang.t(Key::ImportConfirmButton), "import_confirm"),
+        InlineKeyboardButton::callback(lang.t(Key::ImportCancelButton), "import_cancel"),
+    ])
+}
+
+/// The task with its filters, shown above the task menu.
+pub fn format_task_menu(task_id: &str, task: &HashMap<String, String>, lang: Lang) -> String {
+    let text = format_task(task_id, task, lang);
+    if task.get("type").map(String::as_str) != Some("day") {
+        return text;
+    }
+    let car_types = task
+        .get("car_types")
+        .map(|car_types| car_types.replace(',', ", "))
+        .unwrap_or_else(|| CUPE_TYPE.to_string());
+    let seats = match task.get("seats").map(String::as_str) {
+        Some("lower") => lang.t(Key::LowerSeats),
+        Some("upper") => lang.t(Key::UpperSeats),
+        _ => lang.t(Key::AnySeats),
+    };
+    let max_price = match task.get("max_price") {
+        Some(max_price) => max_price.clone(),
+        None => lang.t(Key::NoPriceLimit).to_string(),
+    };
+    let settings = lang.tf(
+        Key::TaskSettings,
+        &[
+            ("car_types", &car_types),
+            ("seats", &seats),
+            ("max_price", &max_price),
+        ],
+    );
+    format!("{text}\n\n{settings}")
+}
+
+/// Train tasks follow a single train, so only day tasks can change their date and filters.
+pub fn make_task_menu_keyboard(
+    task_id: &str,
+    task: &HashMap<String, String>,
+    lang: Lang,
+) -> InlineKeyboardMarkup {
+    let button = |key: Key, action: TaskAction| {
+        InlineKeyboardButton::callback(lang.t(key), action.callback(task_id))
+    };
+    let mut reply_markup = InlineKeyboardMarkup::default().append_row([if is_paused(task) {
+        button(Key::ResumeTaskButton, TaskAction::Resume)
+    } else {
+        button(Key::PauseTaskButton, TaskAction::Pause)
+    }]);
+    if task.get("type").map(String::as_str) == Some("day") {
+        reply_markup = reply_markup
+            .append_row([
+                button(Key::EditDateButton, TaskAction::Edit(TaskEdit::Date)),
+                button(
+                    Key::EditCarTypesButton,
+                    TaskAction::Edit(TaskEdit::CarTypes),
+                ),
+            ])
+            .append_row([
+                button(Key::EditSeatsButton, TaskAction::Edit(TaskEdit::Seats)),
+                button(
+                    Key::EditMaxPriceButton,
+                    TaskAction::Edit(TaskEdit::MaxPrice),
+                ),
+            ])
+            .append_row([button(
+                Key::CloneTaskButton,
+                TaskAction::Edit(TaskEdit::Clone),
+            )]);
+    }
+    reply_markup
+}
+
+/// Task id and page of a history button, the ones in the task list have no page and open a new message.
+pub fn parse_history_callback(data: &str) -> Option<(&str, Option<usize>)> {
+    let data = data.strip_prefix(HISTORY_PREFIX)?;
+    match data.split_once('_') {
+        Some((task_id, page)) => Some((task_id, Some(page.parse().ok()?))),
+        None => Some((data, None)),
+    }
+}
+
+/// Sent notifications of a task, the latest first.
+pub fn format_history_page(history: &[SentNotification], page: usize, lang: Lang) -> String {
+    let entries = Paginated::new(history, page)
+        .items()
+        .map(|(_, notification)| format_sent_notification(notification, lang))
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    format!("{}\n\n{entries}", lang.t(Key::NotificationHistory))
+}
+
+fn format_sent_notification(notification: &SentNotification, lang: Lang) -> String {
+    let sent_at = DateTime::parse_from_rfc3339(&notification.sent_at)
+        .map(|sent_at| sent_at.format("%d.%m.%Y %H:%M").to_string())
+        .unwrap_or_else(|_| notification.sent_at.clone());
+    let mut lines = vec![lang.tf(
+        Key::HistoryEntry,
+        &[
+            ("sent_at", &sent_at),
+            ("added", &notification.added),
+            ("removed", &notification.removed),
+        ],
+    )];
+    for found in notification.seats.iter() {
+        lines.push(match &found.car {
+            Some(car) => lang.tf(
+                Key::HistoryCarSeats,
+                &[
+                    ("train", &found.train),
+                    ("car", car),
+                    ("seats", &found.seats),
+                ],
+            ),
+            None => lang.tf(
+                Key::HistoryTrainSeats,
+                &[("train", &found.train), ("seats", &found.seats)],
+            ),
+        });
+    }
+    lines.join("\n")
+}
+
+pub fn make_history_keyboard(
+    task_id: &str,
+    history: &[SentNotification],
+    page: usize,
+) -> InlineKeyboardMarkup {
+    Paginated::new(history, page)
+        .with_prefix(format!("{HISTORY_PREFIX}{task_id}_"))
+        .keyboard(InlineKeyboardMarkup::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rzd::{GetRZDTrainsCarriagesCars, GetRZDTrainsCarriagesListResponse};
+
+    #[test]
+    fn skips_places_rzd_sent_malformed() {
+        let carriages = GetRZDTrainsCarriagesResponse {
+            lst: vec![GetRZDTrainsCarriagesListResponse {
+                cars: vec![GetRZDTrainsCarriagesCars {
+                    places: ["001-004", "Ж-М", "009-", "12Ж3-016", "013М-016М"]
+                        .iter()
+                        .map(|place| place.to_string())
+                        .collect(),
+                    cnumber: "05".to_string(),
+                    _type: "Купе".to_string(),
+                    seats: vec![],
+                }],
+            }],
+        };
+        assert_eq!(
+            find_free_compartments(&carriages),
+            vec![("05".to_string(), 1), ("05".to_string(), 13)]
+        );
+    }
+}